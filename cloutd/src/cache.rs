@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+use tokio::time::MissedTickBehavior;
+
+use crate::kernel::Kernel;
+
+/// How often expired entries are looked for
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where an entry came from and what it says about its protocol address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Registered by a client, expires after the holding time it asked for
+    Dynamic,
+    /// Configured, never expires
+    Static,
+    /// One of our own addresses, never expires
+    Local,
+    /// Being resolved, the NBMA address is not known yet
+    Incomplete,
+    /// Known not to resolve until it expires
+    Negative,
+}
+
+/// A binding of a protocol address, or the prefix starting at it, to an NBMA address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub kind: EntryKind,
+    /// GRE interface the NBMA address is reached through
+    pub ifindex: usize,
+    /// Length of the prefix the entry covers, the full address length for a single host
+    pub prefix_len: u8,
+    /// NBMA address packets for the protocol address are sent to. Incomplete and negative
+    /// entries have none.
+    pub nbma_addr: Option<IpAddr>,
+    /// NBMA address of the peer that registered the entry. Only it may purge or change it.
+    pub registrant: Option<IpAddr>,
+    /// The registrant asked for no other NBMA address to be registered for the address
+    pub unique: bool,
+    pub holding_time: Duration,
+    pub mtu: u16,
+    pub preference: u8,
+    pub created: Instant,
+}
+
+impl CacheEntry {
+    /// Entry that never expires, binding a single host on `ifindex` to `nbma_addr`
+    pub fn fixed(kind: EntryKind, ifindex: usize, proto_addr: IpAddr, nbma_addr: IpAddr) -> Self {
+        Self {
+            kind,
+            ifindex,
+            prefix_len: host_prefix_len(&proto_addr),
+            nbma_addr: Some(nbma_addr),
+            registrant: None,
+            unique: true,
+            holding_time: Duration::ZERO,
+            mtu: 0,
+            preference: 0,
+            created: Instant::now(),
+        }
+    }
+
+    /// Entry without an NBMA address for a single host on `ifindex`, expiring after
+    /// `holding_time`
+    pub fn unresolved(kind: EntryKind, ifindex: usize, proto_addr: IpAddr, holding_time: Duration) -> Self {
+        Self {
+            kind,
+            ifindex,
+            prefix_len: host_prefix_len(&proto_addr),
+            nbma_addr: None,
+            registrant: None,
+            unique: false,
+            holding_time,
+            mtu: 0,
+            preference: 0,
+            created: Instant::now(),
+        }
+    }
+
+    /// When the entry expires, `None` if it never does
+    pub fn expires(&self) -> Option<Instant> {
+        match self.kind {
+            EntryKind::Static | EntryKind::Local => None,
+            _ => Some(self.created + self.holding_time),
+        }
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires().is_some_and(|expires| expires <= now)
+    }
+
+    /// Seconds left until the entry expires, `None` if it never does
+    pub fn remaining(&self, now: Instant) -> Option<u16> {
+        self.expires().map(|expires| {
+            let secs = expires.saturating_duration_since(now).as_secs();
+            u16::try_from(secs).unwrap_or(u16::MAX)
+        })
+    }
+
+    /// The NBMA address the entry resolves to, if it resolves at all
+    pub fn resolves(&self) -> Option<IpAddr> {
+        match self.kind {
+            EntryKind::Dynamic | EntryKind::Static | EntryKind::Local => self.nbma_addr,
+            EntryKind::Incomplete | EntryKind::Negative => None,
+        }
+    }
+
+    /// Whether `addr` lies in the prefix of this entry for `proto_addr`
+    fn covers(&self, proto_addr: &IpAddr, addr: &IpAddr) -> bool {
+        match (proto_addr, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) =>
+                prefix_matches(&prefix.octets(), &addr.octets(), self.prefix_len),
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) =>
+                prefix_matches(&prefix.octets(), &addr.octets(), self.prefix_len),
+            _ => false,
+        }
+    }
+}
+
+/// Prefix length of a single host address of the family of `addr`
+pub fn host_prefix_len(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn prefix_matches(prefix: &[u8], addr: &[u8], len: u8) -> bool {
+    let len = len as usize;
+    let (bytes, bits) = (len / 8, len % 8);
+    if prefix[..bytes] != addr[..bytes] {
+        return false;
+    }
+    bits == 0 || {
+        let mask = !(0xffu8 >> bits);
+        prefix[bytes] & mask == addr[bytes] & mask
+    }
+}
+
+/// The NHRP cache, entries by the protocol address they bind
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: HashMap<IpAddr, CacheEntry>,
+}
+
+/// The cache shared by the handlers of all operations and the expiry task
+pub type PeerCache = Arc<RwLock<Cache>>;
+
+impl Cache {
+    /// Entry registered for exactly `proto_addr`
+    pub fn get(&self, proto_addr: &IpAddr) -> Option<&CacheEntry> {
+        self.entries.get(proto_addr)
+    }
+
+    /// Most specific unexpired entry covering `addr`, with the protocol address it is for
+    pub fn lookup(&self, addr: &IpAddr, now: Instant) -> Option<(IpAddr, &CacheEntry)> {
+        self.entries.iter()
+            .filter(|(proto_addr, entry)| !entry.is_expired(now) && entry.covers(proto_addr, addr))
+            .max_by_key(|(_, entry)| entry.prefix_len)
+            .map(|(proto_addr, entry)| (*proto_addr, entry))
+    }
+
+    pub fn insert(&mut self, proto_addr: IpAddr, entry: CacheEntry) -> Option<CacheEntry> {
+        self.entries.insert(proto_addr, entry)
+    }
+
+    /// Remove the entry for `proto_addr` on behalf of the peer at `nbma_addr`. Only the peer
+    /// that registered an entry may withdraw it, otherwise its registrant is returned as error.
+    /// Entries nobody registered are not purged at all.
+    pub fn purge(&mut self, proto_addr: &IpAddr, nbma_addr: IpAddr) -> Result<Option<CacheEntry>, IpAddr> {
+        match self.entries.get(proto_addr).and_then(|entry| entry.registrant) {
+            Some(registrant) if registrant == nbma_addr => Ok(self.entries.remove(proto_addr)),
+            Some(registrant) => Err(registrant),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove and return all entries expired at `now`
+    pub fn expire(&mut self, now: Instant) -> Vec<(IpAddr, CacheEntry)> {
+        let expired: Vec<IpAddr> = self.entries.iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(proto_addr, _)| *proto_addr)
+            .collect();
+        expired.into_iter()
+            .filter_map(|proto_addr| self.entries.remove(&proto_addr).map(|entry| (proto_addr, entry)))
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IpAddr, &CacheEntry)> {
+        self.entries.iter()
+    }
+}
+
+/// Remove expired entries from `cache` and withdraw their neighbour entries from the kernel.
+/// Runs forever.
+pub async fn expire(cache: PeerCache, kernel: Kernel) {
+    let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        // The lock is held while the kernel is updated, so a registration renewing an expired
+        // binding can't have its neighbour entry removed after it was installed again.
+        let mut cache = cache.write().await;
+        for (proto_addr, entry) in cache.expire(Instant::now()) {
+            tracing::info!(%proto_addr, kind = ?entry.kind, nbma_addr = ?entry.nbma_addr, "cache entry expired");
+            if entry.nbma_addr.is_none() {
+                continue;
+            }
+            if let Err(error) = kernel.delete_neighbour(entry.ifindex, proto_addr).await {
+                tracing::warn!(%error, %proto_addr, "removing neighbour entry failed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::config::CacheConfig;
+
+    const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    const SUBNET: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0));
+    const SPOKE_NBMA: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 5));
+    const OTHER_NBMA: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 6));
+
+    fn registered(registrant: IpAddr, holding_time: Duration) -> CacheEntry {
+        CacheEntry {
+            registrant: Some(registrant),
+            holding_time,
+            ..CacheEntry::fixed(EntryKind::Dynamic, 7, SPOKE, registrant)
+        }
+    }
+
+    #[test]
+    fn purges_for_registrant_only() {
+        let mut cache = Cache::default();
+        let entry = registered(SPOKE_NBMA, Duration::from_secs(600));
+        cache.insert(SPOKE, entry.clone());
+
+        // A third party can't withdraw the binding, whatever it claims to be
+        assert_eq!(cache.purge(&SPOKE, OTHER_NBMA), Err(SPOKE_NBMA));
+        assert_eq!(cache.get(&SPOKE), Some(&entry));
+
+        assert_eq!(cache.purge(&SPOKE, SPOKE_NBMA), Ok(Some(entry)));
+        assert_eq!(cache.get(&SPOKE), None);
+        assert_eq!(cache.purge(&SPOKE, SPOKE_NBMA), Ok(None));
+    }
+
+    #[test]
+    fn keeps_unregistered_entries_on_purge() {
+        let mut cache = Cache::default();
+        let entry = CacheEntry::fixed(EntryKind::Static, 7, SPOKE, SPOKE_NBMA);
+        cache.insert(SPOKE, entry.clone());
+        assert_eq!(cache.purge(&SPOKE, SPOKE_NBMA), Ok(None));
+        assert_eq!(cache.get(&SPOKE), Some(&entry));
+    }
+
+    #[test]
+    fn expires_after_holding_time() {
+        let now = Instant::now();
+        let mut cache = Cache::default();
+        let entry = CacheEntry { created: now, ..registered(SPOKE_NBMA, Duration::from_secs(600)) };
+        assert_eq!(entry.expires(), Some(now + Duration::from_secs(600)));
+        assert!(!entry.is_expired(now + Duration::from_secs(599)));
+        assert!(entry.is_expired(now + Duration::from_secs(600)));
+
+        let local = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        cache.insert(SPOKE, entry.clone());
+        cache.insert(local, CacheEntry::fixed(EntryKind::Local, 7, local, SPOKE_NBMA));
+        assert!(cache.expire(now + Duration::from_secs(599)).is_empty());
+        assert_eq!(cache.expire(now + Duration::from_secs(3600)), vec![(SPOKE, entry)]);
+        // Configured and local entries stay forever
+        assert!(cache.get(&local).is_some());
+        assert_eq!(cache.get(&local).unwrap().expires(), None);
+    }
+
+    #[test]
+    fn caps_remaining_holding_time() {
+        let now = Instant::now();
+        let entry = CacheEntry { created: now, ..registered(SPOKE_NBMA, Duration::from_secs(100_000)) };
+        // More than a CIE can carry
+        assert_eq!(entry.remaining(now), Some(u16::MAX));
+        assert_eq!(entry.remaining(now + Duration::from_secs(99_000)), Some(1000));
+        assert_eq!(entry.remaining(now + Duration::from_secs(200_000)), Some(0));
+
+        let entry = CacheEntry::fixed(EntryKind::Static, 7, SPOKE, SPOKE_NBMA);
+        assert_eq!(entry.remaining(now), None);
+
+        // Registrations without a holding time of their own get no more than the longest allowed
+        let limits = CacheConfig { max_holding_time: 600, default_holding_time: 7200 };
+        assert_eq!(limits.default_holding_time(), 600);
+        assert_eq!(CacheConfig::default().default_holding_time(), 7200);
+    }
+
+    #[test]
+    fn matches_prefixes() {
+        let prefix = [10, 0, 0, 0];
+        assert!(prefix_matches(&prefix, &[192, 0, 2, 1], 0));
+        assert!(prefix_matches(&prefix, &[10, 255, 0, 1], 8));
+        assert!(!prefix_matches(&prefix, &[11, 0, 0, 0], 8));
+        // Lengths within an octet only compare its leading bits
+        assert!(prefix_matches(&prefix, &[10, 15, 0, 1], 12));
+        assert!(!prefix_matches(&prefix, &[10, 16, 0, 1], 12));
+        assert!(prefix_matches(&prefix, &prefix, 32));
+        assert!(!prefix_matches(&prefix, &[10, 0, 0, 1], 32));
+
+        let prefix = Ipv6Addr::new(0xfd00, 0, 0, 1, 0, 0, 0, 0).octets();
+        assert!(prefix_matches(&prefix, &Ipv6Addr::new(0xfd00, 0, 0, 1, 0, 0, 0, 5).octets(), 64));
+        assert!(!prefix_matches(&prefix, &Ipv6Addr::new(0xfd00, 0, 0, 2, 0, 0, 0, 5).octets(), 64));
+    }
+
+    #[test]
+    fn looks_up_most_specific_entry() {
+        let now = Instant::now();
+        let mut cache = Cache::default();
+        let subnet = CacheEntry { prefix_len: 24, created: now, ..registered(OTHER_NBMA, Duration::from_secs(600)) };
+        let host = CacheEntry { created: now, ..registered(SPOKE_NBMA, Duration::from_secs(60)) };
+        cache.insert(SUBNET, subnet.clone());
+        cache.insert(SPOKE, host.clone());
+
+        assert_eq!(cache.lookup(&SPOKE, now), Some((SPOKE, &host)));
+        let neighbour = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(cache.lookup(&neighbour, now), Some((SUBNET, &subnet)));
+        assert_eq!(cache.lookup(&IpAddr::V4(Ipv4Addr::new(10, 0, 1, 2)), now), None);
+        assert_eq!(cache.lookup(&IpAddr::V6(Ipv6Addr::LOCALHOST), now), None);
+
+        // Expired entries are skipped before the expiry task gets to them
+        let later = now + Duration::from_secs(60);
+        assert_eq!(cache.lookup(&SPOKE, later), Some((SUBNET, &subnet)));
+    }
+
+    #[test]
+    fn looks_up_unresolved_entries() {
+        let now = Instant::now();
+        let mut cache = Cache::default();
+        let negative = CacheEntry {
+            created: now,
+            ..CacheEntry::unresolved(EntryKind::Negative, 7, SPOKE, Duration::from_secs(30))
+        };
+        cache.insert(SPOKE, negative.clone());
+
+        // Found, so the address is not resolved again, but it does not resolve
+        let (_, entry) = cache.lookup(&SPOKE, now).unwrap();
+        assert_eq!(entry, &negative);
+        assert_eq!(entry.resolves(), None);
+        assert_eq!(cache.lookup(&SPOKE, now + Duration::from_secs(30)), None);
+
+        let incomplete = CacheEntry {
+            nbma_addr: Some(SPOKE_NBMA),
+            ..CacheEntry::unresolved(EntryKind::Incomplete, 7, SPOKE, Duration::from_secs(30))
+        };
+        assert_eq!(incomplete.resolves(), None);
+        assert_eq!(registered(SPOKE_NBMA, Duration::from_secs(30)).resolves(), Some(SPOKE_NBMA));
+    }
+}
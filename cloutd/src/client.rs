@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nhrp::{ClientInformationEntry, NhrpMessage, RegistrationCode, RegistrationFlags, RegistrationReplyMessage, RegistrationRequestMessage};
+
+use crate::config::{InterfaceConfig, NhsConfig};
+use crate::socket::PeerAddr;
+
+/// Wait before the first retry of a registration that was refused or not answered. The wait
+/// doubles with every further retry.
+const MIN_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Longest wait between two attempts to register
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Registration of one of our protocol addresses with one NHS
+#[derive(Debug)]
+struct Registration {
+    ifindex: usize,
+    proto_addr: IpAddr,
+    nbma_addr: Option<IpAddr>,
+    nhs: NhsConfig,
+    holding_time: u16,
+    /// Request ID of the request awaiting a reply
+    pending: Option<u32>,
+    /// When the next request is sent
+    due: Instant,
+    /// Wait before retrying if the pending request fails
+    backoff: Duration,
+}
+
+impl Registration {
+    fn request(&self, request_id: u32) -> Result<NhrpMessage, nhrp::Error> {
+        // Behind NAT the NHS will see a different address than the one we claim, and tell us.
+        let src_nbma_addr = self.nbma_addr.unwrap_or(match self.nhs.nbma_address {
+            IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        });
+        let mut cie = ClientInformationEntry::builder()
+            .client_proto_addr(self.proto_addr)
+            .holding_time(self.holding_time);
+        if let Some(nbma_addr) = self.nbma_addr {
+            cie = cie.client_nbma_addr(nbma_addr);
+        }
+        let request = RegistrationRequestMessage::builder(src_nbma_addr, self.proto_addr, self.nhs.protocol_address)
+            .request_id(request_id)
+            .flags(RegistrationFlags { nat: true, ..Default::default() })
+            .cie(cie.build()?)
+            .build()?;
+        NhrpMessage::builder(request).build()
+    }
+}
+
+/// A registration request to send
+#[derive(Debug)]
+pub struct Request {
+    pub msg: NhrpMessage,
+    /// Interface the NHS is reached through
+    pub ifindex: usize,
+    pub nhs: NhsConfig,
+    /// How long to wait for the reply before the request is sent again
+    pub timeout: Duration,
+}
+
+/// Outcome of a registration, reported once its reply arrives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The NHS accepted the registration for `holding_time` seconds
+    Registered { ifindex: usize, nhs: NhsConfig, holding_time: u16 },
+    /// The NHS refused the registration, it is retried after `backoff`
+    Refused { ifindex: usize, nhs: NhsConfig, code: RegistrationCode, backoff: Duration },
+}
+
+/// Next Hop Client: keeps our protocol addresses registered with the configured NHSes.
+///
+/// Every registration is renewed after a third of its holding time. Refused and unanswered ones
+/// are retried with exponential backoff.
+#[derive(Debug)]
+pub struct NhrpClient {
+    registrations: Vec<Registration>,
+    /// Request ID of the next request sent
+    request_id: u32,
+}
+
+impl NhrpClient {
+    /// Client registering the protocol address of every interface with the NHSes configured for
+    /// it. Registrations are due immediately.
+    pub fn new(interfaces: &HashMap<usize, InterfaceConfig>) -> Self {
+        let now = Instant::now();
+        let mut registrations = Vec::new();
+        for (ifindex, iface) in interfaces.iter() {
+            let client = match iface.client {
+                Some(ref client) => client,
+                None => continue,
+            };
+            let proto_addr = match iface.protocol_address {
+                Some(addr) => addr,
+                None => {
+                    tracing::warn!(ifindex, "not registering with NHSes, interface has no protocol address");
+                    continue;
+                },
+            };
+            registrations.extend(client.nhs.iter().map(|nhs| Registration {
+                ifindex: *ifindex,
+                proto_addr,
+                nbma_addr: iface.nbma_address,
+                nhs: *nhs,
+                holding_time: client.holding_time.get(),
+                pending: None,
+                due: now,
+                backoff: MIN_BACKOFF,
+            }));
+        }
+
+        // Replies to requests sent before a restart must not be mistaken for replies to ours
+        let request_id = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |t| t.as_secs() as u32);
+        Self { registrations, request_id }
+    }
+
+    /// When the next request is due, `None` if nothing is ever registered
+    pub fn next_due(&self) -> Option<Instant> {
+        self.registrations.iter().map(|r| r.due).min()
+    }
+
+    /// Requests of all registrations due at `now`. If no reply arrives before their timeout they
+    /// are sent again.
+    pub fn due_requests(&mut self, now: Instant) -> Vec<Request> {
+        let mut requests = Vec::new();
+        for registration in self.registrations.iter_mut().filter(|r| r.due <= now) {
+            let request_id = self.request_id;
+            self.request_id = self.request_id.wrapping_add(1);
+
+            if let Some(previous) = registration.pending {
+                tracing::info!(request_id = previous, nhs = %registration.nhs.protocol_address,
+                    "registration request not answered, retrying");
+            }
+            let timeout = registration.backoff;
+            registration.due = now + timeout;
+            registration.backoff = (registration.backoff * 2).min(MAX_BACKOFF);
+            match registration.request(request_id) {
+                Ok(msg) => {
+                    registration.pending = Some(request_id);
+                    requests.push(Request { msg, ifindex: registration.ifindex, nhs: registration.nhs, timeout });
+                },
+                Err(error) => {
+                    registration.pending = None;
+                    tracing::warn!(%error, proto_addr = %registration.proto_addr,
+                        nhs = %registration.nhs.protocol_address, "building registration request failed");
+                },
+            }
+        }
+        requests
+    }
+
+    /// Match `reply`, received from `source`, to the request it answers by its request ID.
+    /// Returns `None` if we have no such request pending with the NHS the reply came from.
+    pub fn on_reply(&mut self, reply: &RegistrationReplyMessage, source: &PeerAddr, now: Instant)
+        -> Option<Outcome>
+    {
+        // Request IDs are easily guessed, only the NHS asked may answer
+        let request_id = reply.header().request_id;
+        let registration = self.registrations.iter_mut().find(|r| r.pending == Some(request_id)
+            && r.ifindex == source.ifindex && r.nhs.nbma_address == source.nbma)?;
+        registration.pending = None;
+
+        // Our request carries a single CIE, the reply answers it with one coded for it alone
+        let cie = reply.cie().iter()
+            .find(|cie| cie.client_proto_addr.is_none_or(|addr| addr == registration.proto_addr));
+        match cie.map_or(reply.code(), |cie| cie.code.into()) {
+            RegistrationCode::Success => {
+                let holding_time = match cie.map_or(0, |cie| cie.holding_time) {
+                    0 => registration.holding_time,
+                    granted => granted,
+                };
+                // Very short holding times must not have us flood the NHS with renewals
+                registration.due = now + Duration::from_secs(holding_time as u64 / 3).max(MIN_BACKOFF);
+                registration.backoff = MIN_BACKOFF;
+                Some(Outcome::Registered { ifindex: registration.ifindex, nhs: registration.nhs, holding_time })
+            },
+            code => {
+                let backoff = registration.backoff;
+                registration.due = now + backoff;
+                Some(Outcome::Refused { ifindex: registration.ifindex, nhs: registration.nhs, code, backoff })
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use nhrp::{CieCode, Operation};
+
+    use super::*;
+    use crate::config::ClientConfig;
+
+    const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    const SPOKE_NBMA: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 5));
+    const NHS: NhsConfig = NhsConfig {
+        protocol_address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        nbma_address: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 4)),
+    };
+
+    /// Where replies of the NHS come from
+    const SOURCE: PeerAddr = PeerAddr { ifindex: 7, nbma: NHS.nbma_address };
+
+    fn client(holding_time: u16) -> NhrpClient {
+        let holding_time = NonZeroU16::new(holding_time).unwrap();
+        let iface = InterfaceConfig {
+            nbma_address: Some(SPOKE_NBMA),
+            protocol_address: Some(SPOKE),
+            client: Some(ClientConfig { nhs: vec![NHS], holding_time }),
+            ..Default::default()
+        };
+        NhrpClient::new(&HashMap::from([(7, iface)]))
+    }
+
+    /// Request ID of the single request due at `now`
+    fn request_id(client: &mut NhrpClient, now: Instant) -> u32 {
+        let requests = client.due_requests(now);
+        assert_eq!(requests.len(), 1);
+        match requests[0].msg.operation {
+            Operation::RegistrationRequest(ref request) => request.header().request_id,
+            ref other => panic!("not a registration request: {:?}", other),
+        }
+    }
+
+    fn reply(request_id: u32, code: CieCode, holding_time: u16) -> RegistrationReplyMessage {
+        let cie = ClientInformationEntry::builder()
+            .code(code)
+            .client_proto_addr(SPOKE)
+            .holding_time(holding_time)
+            .build().unwrap();
+        RegistrationReplyMessage::builder(SPOKE_NBMA, SPOKE, NHS.protocol_address)
+            .request_id(request_id)
+            .cie(cie)
+            .build().unwrap()
+    }
+
+    #[test]
+    fn renews_after_a_third_of_the_holding_time() {
+        let mut client = client(600);
+        let now = Instant::now();
+        assert!(client.next_due().is_some_and(|due| due <= now));
+        let id = request_id(&mut client, now);
+        assert!(client.due_requests(now).is_empty());
+
+        // The NHS may grant less than we asked for
+        let outcome = client.on_reply(&reply(id, CieCode::Success, 300), &SOURCE, now);
+        assert_eq!(outcome, Some(Outcome::Registered { ifindex: 7, nhs: NHS, holding_time: 300 }));
+        assert_eq!(client.next_due(), Some(now + Duration::from_secs(100)));
+
+        // No holding time in the reply leaves the one we asked for
+        let later = now + Duration::from_secs(100);
+        let id = request_id(&mut client, later);
+        let outcome = client.on_reply(&reply(id, CieCode::Success, 0), &SOURCE, later);
+        assert_eq!(outcome, Some(Outcome::Registered { ifindex: 7, nhs: NHS, holding_time: 600 }));
+        assert_eq!(client.next_due(), Some(later + Duration::from_secs(200)));
+    }
+
+    #[test]
+    fn backs_off_unanswered_requests() {
+        let mut client = client(600);
+        let mut now = Instant::now();
+        for secs in [10, 20, 40, 80, 160, 320, 600, 600] {
+            let requests = client.due_requests(now);
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].timeout, Duration::from_secs(secs));
+            now += requests[0].timeout;
+            assert_eq!(client.next_due(), Some(now));
+        }
+    }
+
+    #[test]
+    fn backs_off_refused_registrations() {
+        let mut client = client(600);
+        let now = Instant::now();
+        let id = request_id(&mut client, now);
+        let outcome = client.on_reply(&reply(id, CieCode::Prohibited, 0), &SOURCE, now);
+        assert_eq!(outcome, Some(Outcome::Refused {
+            ifindex: 7,
+            nhs: NHS,
+            code: RegistrationCode::Prohibited,
+            backoff: Duration::from_secs(20),
+        }));
+        assert_eq!(client.next_due(), Some(now + Duration::from_secs(20)));
+
+        // Success resets the backoff
+        let later = now + Duration::from_secs(20);
+        let id = request_id(&mut client, later);
+        client.on_reply(&reply(id, CieCode::Success, 30), &SOURCE, later).unwrap();
+        let later = later + Duration::from_secs(10);
+        assert_eq!(client.due_requests(later)[0].timeout, MIN_BACKOFF);
+    }
+
+    #[test]
+    fn matches_replies_by_request_id() {
+        let mut client = client(600);
+        let now = Instant::now();
+        let id = request_id(&mut client, now);
+
+        assert_eq!(client.on_reply(&reply(id.wrapping_add(1), CieCode::Success, 600), &SOURCE, now), None);
+        assert!(client.on_reply(&reply(id, CieCode::Success, 600), &SOURCE, now).is_some());
+        // Answered already
+        assert_eq!(client.on_reply(&reply(id, CieCode::Success, 600), &SOURCE, now), None);
+
+        // Replies to requests that were retried since are not accepted either
+        let later = now + Duration::from_secs(200);
+        let stale = request_id(&mut client, later);
+        let retried = request_id(&mut client, later + MIN_BACKOFF);
+        assert_ne!(stale, retried);
+        assert_eq!(client.on_reply(&reply(stale, CieCode::Success, 600), &SOURCE, later), None);
+        assert!(client.on_reply(&reply(retried, CieCode::Success, 600), &SOURCE, later).is_some());
+    }
+
+    #[test]
+    fn renews_short_registrations_after_min_backoff() {
+        let mut client = client(600);
+        let now = Instant::now();
+        let id = request_id(&mut client, now);
+        let outcome = client.on_reply(&reply(id, CieCode::Success, 1), &SOURCE, now);
+        assert_eq!(outcome, Some(Outcome::Registered { ifindex: 7, nhs: NHS, holding_time: 1 }));
+        assert_eq!(client.next_due(), Some(now + MIN_BACKOFF));
+    }
+
+    #[test]
+    fn ignores_replies_from_other_peers() {
+        let mut client = client(600);
+        let now = Instant::now();
+        let id = request_id(&mut client, now);
+
+        let forger = PeerAddr { nbma: SPOKE_NBMA, ..SOURCE };
+        assert_eq!(client.on_reply(&reply(id, CieCode::Prohibited, 0), &forger, now), None);
+        let elsewhere = PeerAddr { ifindex: 8, ..SOURCE };
+        assert_eq!(client.on_reply(&reply(id, CieCode::Success, 60), &elsewhere, now), None);
+
+        // The request is still pending for the NHS itself
+        let outcome = client.on_reply(&reply(id, CieCode::Success, 600), &SOURCE, now);
+        assert_eq!(outcome, Some(Outcome::Registered { ifindex: 7, nhs: NHS, holding_time: 600 }));
+    }
+}
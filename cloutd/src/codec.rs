@@ -1,6 +1,70 @@
 use std::io;
 use bytes::{Bytes, BytesMut};
-use nhrp::{Emitable, NhrpBuffer, NhrpMessage};
+use nhrp::{Emitable, NhrpMessage};
 use thiserror::Error;
 use miette::Diagnostic;
-use crate::NhrpSocket;
\ No newline at end of file
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::PacketErr;
+use crate::socket;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum Error {
+    #[error("socket error occurred")]
+    #[diagnostic(code(nhrp::codec::socket))]
+    Socket(#[source] #[from] #[diagnostic_source] socket::Error),
+
+    #[error("I/O error occurred")]
+    #[diagnostic(code(nhrp::codec::io))]
+    Io(#[source] #[from] io::Error),
+
+    #[error("received invalid NHRP message")]
+    #[diagnostic(code(nhrp::codec::parse))]
+    Parse(#[source] #[from] #[diagnostic_source] PacketErr),
+
+    #[error("message of {0} octets does not fit into an NHRP packet")]
+    #[diagnostic(code(nhrp::codec::too_long))]
+    TooLong(usize),
+}
+
+/// A received message together with the packet it was parsed from. Authentication and Error
+/// Indications need the packet exactly as the peer sent it.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub bytes: Bytes,
+    pub message: NhrpMessage,
+}
+
+/// Decodes each datagram into a [`Packet`] and encodes [`NhrpMessage`]s for sending.
+///
+/// Checksums are not verified when decoding since the policy for that depends on the interface
+/// a packet was received on.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NhrpCodec;
+
+impl Decoder for NhrpCodec {
+    type Item = Packet;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>, Error> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let bytes = src.split().freeze();
+        let message = NhrpMessage::from_bytes(&bytes).map_err(|e| PacketErr::new(&bytes, e))?;
+        Ok(Some(Packet { bytes, message }))
+    }
+}
+
+impl Encoder<NhrpMessage> for NhrpCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: NhrpMessage, dst: &mut BytesMut) -> Result<(), Error> {
+        let len = msg.buffer_len();
+        if len > u16::MAX as usize {
+            return Err(Error::TooLong(len));
+        }
+        msg.emit_to(dst);
+        Ok(())
+    }
+}
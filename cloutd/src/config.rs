@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroU16;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+use thiserror::Error;
+use miette::Diagnostic;
+use nix::net::if_::if_nametoindex;
+use serde::Deserialize;
+
+use nhrp::{Authenticator, ChecksumPolicy, ViolationClass};
+
+pub const DEFAULT_PATH: &str = "/etc/cloutd.toml";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum Error {
+    #[error("reading config file {path:?} failed")]
+    #[diagnostic(code(cloutd::config::read))]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("config file {path:?} is invalid")]
+    #[diagnostic(code(cloutd::config::parse))]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("configured interface {0} does not exist")]
+    #[diagnostic(code(cloutd::config::interface), help("check the output of `ip link` for the names of the GRE interfaces"))]
+    UnknownInterface(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub interfaces: HashMap<String, InterfaceConfig>,
+    /// Send Traffic Indications for traffic hairpinning through this host
+    pub redirect: Option<RedirectConfig>,
+    /// What to do with messages that break the rules of RFC 2332
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    /// Limits on the bindings clients register
+    #[serde(default)]
+    pub cache: CacheConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InterfaceConfig {
+    /// Our own NBMA address on this interface, used as source of messages we originate.
+    pub nbma_address: Option<IpAddr>,
+    /// Our own protocol address on this interface, used as source of messages we originate.
+    pub protocol_address: Option<IpAddr>,
+    /// Require all messages received on this interface to be authenticated.
+    pub authentication: Option<AuthConfig>,
+    /// Tell spokes to resolve a shortcut when their traffic leaves this interface again
+    #[serde(default)]
+    pub redirect: bool,
+    /// Accept messages without a checksum, for peers that never fill it in
+    #[serde(default)]
+    pub allow_zero_checksum: bool,
+    /// Peers reachable on this interface without registering, NBMA addresses by protocol
+    /// address
+    #[serde(default)]
+    pub static_peers: HashMap<IpAddr, IpAddr>,
+    /// Register our protocol address with Next Hop Servers on this interface
+    pub client: Option<ClientConfig>,
+}
+
+impl InterfaceConfig {
+    pub fn checksum_policy(&self) -> ChecksumPolicy {
+        if self.allow_zero_checksum {
+            ChecksumPolicy::AllowZero
+        } else {
+            ChecksumPolicy::Verify
+        }
+    }
+}
+
+/// Spoke-side registration. Requires the interface to have a `protocol-address`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Next Hop Servers to register with
+    pub nhs: Vec<NhsConfig>,
+    /// Holding time in seconds to register for. Registrations are renewed after a third of it.
+    #[serde(default = "ClientConfig::default_holding_time")]
+    pub holding_time: NonZeroU16,
+}
+
+impl ClientConfig {
+    fn default_holding_time() -> NonZeroU16 {
+        NonZeroU16::new(7200).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NhsConfig {
+    pub protocol_address: IpAddr,
+    pub nbma_address: IpAddr,
+}
+
+/// Hub-side shortcut detection.
+///
+/// Packets forwarded back out the tunnel they arrived on have to be passed to cloutd by a
+/// netfilter rule such as
+/// `iptables -A FORWARD -i gre1 -o gre1 -m hashlimit --hashlimit-upto 4/minute --hashlimit-burst 1
+/// --hashlimit-mode srcip,dstip --hashlimit-name loglimit -j NFLOG --nflog-group 1`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RedirectConfig {
+    /// NFLOG group the forwarded packets are logged to
+    pub nflog_group: u16,
+    /// Minimum number of seconds between two Traffic Indications for the same source and
+    /// destination
+    #[serde(default = "RedirectConfig::default_interval")]
+    pub interval: u64,
+}
+
+impl RedirectConfig {
+    fn default_interval() -> u64 {
+        10
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Longest holding time in seconds a client may register a binding for. Registrations
+    /// asking for more are refused.
+    pub max_holding_time: u16,
+    /// Holding time in seconds of registrations that don't ask for one
+    pub default_holding_time: u16,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        // RFC 2332 recommends a holding time of 7200 seconds
+        CacheConfig {
+            max_holding_time: 7200,
+            default_holding_time: 7200,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn default_holding_time(&self) -> u16 {
+        self.default_holding_time.min(self.max_holding_time)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(flatten)]
+    pub method: AuthMethod,
+    #[serde(default)]
+    pub on_failure: AuthFailureAction,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Cleartext(String),
+    KeyedHash { spi: u16, key: String },
+}
+
+impl std::fmt::Debug for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.authenticator().fmt(f)
+    }
+}
+
+impl AuthMethod {
+    pub fn authenticator(&self) -> Authenticator {
+        match self {
+            AuthMethod::Cleartext(password) => Authenticator::Cleartext(password.as_bytes().to_vec()),
+            AuthMethod::KeyedHash { spi, key } => Authenticator::KeyedHash {
+                spi: *spi,
+                key: key.as_bytes().to_vec(),
+            },
+        }
+    }
+}
+
+/// What to do with a message that fails authentication
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthFailureAction {
+    /// Silently discard the message
+    #[default]
+    Drop,
+    /// Discard the message and tell the sender with an Authentication Failure error indication
+    ErrorIndication,
+}
+
+/// Action for each class of RFC 2332 violation in received messages, see
+/// [`nhrp::NhrpMessage::validate`]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// Violations in the fixed header, such as an unknown version
+    pub header: ViolationAction,
+    /// Violations in the mandatory part, such as a Resolution Reply without a CIE
+    pub operation: ViolationAction,
+    /// Violations in the CIEs, such as an overlong prefix length
+    pub cie: ViolationAction,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            header: ViolationAction::Drop,
+            operation: ViolationAction::ErrorIndication,
+            cie: ViolationAction::Log,
+        }
+    }
+}
+
+impl ValidationConfig {
+    pub fn action(&self, class: ViolationClass) -> ViolationAction {
+        match class {
+            ViolationClass::Header => self.header,
+            ViolationClass::Operation => self.operation,
+            ViolationClass::Cie => self.cie,
+        }
+    }
+}
+
+/// What to do with a message that breaks the rules of RFC 2332
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViolationAction {
+    /// Log the violation and handle the message anyway
+    Log,
+    /// Discard the message
+    Drop,
+    /// Discard the message and tell the sender with an Error Indication
+    ErrorIndication,
+}
+
+impl Config {
+    /// Load the config from `path`. If no path was given and the default config file does not
+    /// exist the default configuration is used.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let (path, explicit) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_PATH), false),
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if !explicit && e.kind() == io::ErrorKind::NotFound => {
+                tracing::info!(?path, "no config file found, using defaults");
+                return Ok(Self::default());
+            },
+            Err(source) => return Err(Error::Read { path: path.to_owned(), source }),
+        };
+
+        toml::from_str(&contents)
+            .map_err(|source| Error::Parse { path: path.to_owned(), source })
+    }
+
+    /// Resolve the configured interface names to the ifindex packets are received with.
+    pub fn interfaces_by_index(&self) -> Result<HashMap<usize, InterfaceConfig>, Error> {
+        self.interfaces.iter()
+            .map(|(name, config)| {
+                let index = if_nametoindex(name.as_str())
+                    .map_err(|_| Error::UnknownInterface(name.clone()))?;
+                Ok((index as usize, config.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_client_holding_time() {
+        let client: ClientConfig = toml::from_str("nhs = []").unwrap();
+        assert_eq!(client.holding_time.get(), 7200);
+        let client: ClientConfig = toml::from_str("nhs = []\nholding-time = 1").unwrap();
+        assert_eq!(client.holding_time.get(), 1);
+        assert!(toml::from_str::<ClientConfig>("nhs = []\nholding-time = 0").is_err());
+    }
+}
@@ -1,6 +1,7 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::ops::Range;
 use thiserror::Error;
-use miette::{Diagnostic, LabeledSpan, Severity, SourceCode};
+use miette::{Diagnostic, LabeledSpan, Severity, SourceCode, SourceSpan};
 use nix::errno::Errno;
 
 #[derive(Debug, Error, Diagnostic)]
@@ -156,6 +157,66 @@ impl ErrnoErr {
     }
 }
 
+/// Octets per line of a hexdump
+const HEXDUMP_WIDTH: usize = 16;
+/// Length of the offset column of a hexdump, including the separating spaces
+const HEXDUMP_PREFIX: usize = 6;
+/// Length of a full hexdump line: the offset column and two digits plus separator per octet
+const HEXDUMP_LINE: usize = HEXDUMP_PREFIX + HEXDUMP_WIDTH * 3;
+
+/// A received packet that failed to parse, rendered as a hexdump with the offending field
+/// labeled.
+#[derive(Debug, Error, Diagnostic)]
+#[error("received malformed NHRP packet")]
+#[diagnostic(code(cloutd::packet))]
+pub struct PacketErr {
+    #[source]
+    pub error: nhrp::Error,
+    #[source_code]
+    dump: String,
+    #[label("{label}")]
+    span: Option<SourceSpan>,
+    label: String,
+}
+impl PacketErr {
+    pub fn new(packet: &[u8], error: nhrp::Error) -> Self {
+        let span = error.span().and_then(|span| hexdump_span(packet, span));
+        let label = match error {
+            nhrp::Error::Malformed(ref m) =>
+                format!("{}: expected {}, found {}", m.field, m.expected, m.found),
+            ref e => e.to_string(),
+        };
+        Self { error, dump: hexdump(packet), span, label }
+    }
+}
+
+fn hexdump(packet: &[u8]) -> String {
+    let mut dump = String::with_capacity(packet.len().div_ceil(HEXDUMP_WIDTH) * HEXDUMP_LINE);
+    for (line, octets) in packet.chunks(HEXDUMP_WIDTH).enumerate() {
+        let _ = write!(dump, "{:04x}  ", line * HEXDUMP_WIDTH);
+        for (i, octet) in octets.iter().enumerate() {
+            let separator = if i + 1 == octets.len() { '\n' } else { ' ' };
+            let _ = write!(dump, "{:02x}{}", octet, separator);
+        }
+    }
+    dump
+}
+
+/// Position of the octet at `offset` in the output of [`hexdump`]
+fn hexdump_offset(offset: usize) -> usize {
+    (offset / HEXDUMP_WIDTH) * HEXDUMP_LINE + HEXDUMP_PREFIX + (offset % HEXDUMP_WIDTH) * 3
+}
+
+/// Span of `octets` in the hexdump of `packet`. Fields that lie past the end of the packet, as
+/// they do for truncated ones, are pointed at the last octet.
+fn hexdump_span(packet: &[u8], octets: Range<usize>) -> Option<SourceSpan> {
+    let last = packet.len().checked_sub(1)?;
+    let start = octets.start.min(last);
+    let end = octets.end.min(packet.len()).max(start + 1);
+    let from = hexdump_offset(start);
+    Some((from, hexdump_offset(end - 1) + 2 - from).into())
+}
+
 pub trait ErrnoAdvice {
     const EPERM: Option<&'static str> = None;
     const UNKNOWNERRNO: Option<&'static str> = None;
@@ -0,0 +1,102 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::BytesMut;
+use futures::{Sink, Stream};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::socket::{self, NhrpSocket, PeerAddr};
+
+/// Largest packet that is received in full, longer ones are truncated by the kernel
+const BUFFER_LEN: usize = 2048;
+
+/// Stream and sink of the messages on an [`NhrpSocket`], framed by a codec.
+///
+/// This is the packet socket counterpart of tokio-util's `UdpFramed`: every datagram is decoded
+/// on its own and yielded together with the GRE peer it was received from, and every message sent
+/// is encoded into a datagram to the GRE peer given with it.
+#[derive(Debug)]
+pub struct NhrpFramed<C> {
+    socket: NhrpSocket,
+    codec: C,
+    rd: BytesMut,
+    wr: BytesMut,
+    out_addr: Option<PeerAddr>,
+}
+
+impl<C> NhrpFramed<C> {
+    pub fn new(socket: NhrpSocket, codec: C) -> Self {
+        Self {
+            socket,
+            codec,
+            rd: BytesMut::new(),
+            wr: BytesMut::new(),
+            out_addr: None,
+        }
+    }
+
+}
+
+impl<C> Stream for NhrpFramed<C>
+    where C: Decoder + Unpin,
+          C::Error: From<socket::Error>,
+{
+    type Item = Result<(C::Item, PeerAddr), C::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            this.rd.clear();
+            this.rd.resize(BUFFER_LEN, 0);
+            // A packet socket has no end of stream, empty datagrams are decoded to nothing
+            let (len, source) = ready!(this.socket.poll_recv_from(cx, &mut this.rd))?;
+            this.rd.truncate(len);
+            match this.codec.decode_eof(&mut this.rd) {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok((frame, source)))),
+                Ok(None) => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+impl<I, C> Sink<(I, PeerAddr)> for NhrpFramed<C>
+    where C: Encoder<I> + Unpin,
+          C::Error: From<socket::Error> + From<io::Error>,
+{
+    type Error = C::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        // Only one datagram is buffered at a time
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, (frame, addr): (I, PeerAddr)) -> Result<(), C::Error> {
+        let this = self.get_mut();
+        this.codec.encode(frame, &mut this.wr)?;
+        this.out_addr = Some(addr);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        let this = self.get_mut();
+        let addr = match this.out_addr {
+            Some(ref addr) => addr,
+            None => return Poll::Ready(Ok(())),
+        };
+
+        let sent = ready!(this.socket.poll_send_to(cx, &this.wr, addr));
+        let len = this.wr.len();
+        this.wr.clear();
+        this.out_addr = None;
+        match sent? {
+            n if n == len => Poll::Ready(Ok(())),
+            _ => Poll::Ready(Err(io::Error::other("failed to send entire NHRP packet").into())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        self.poll_flush(cx)
+    }
+}
@@ -1,10 +1,133 @@
 use std::io;
+use std::net::IpAddr;
 use thiserror::Error;
 use miette::Diagnostic;
+use nix::errno::Errno;
+use nix::libc;
+use rtnetlink::packet::constants::{NUD_PERMANENT, NUD_REACHABLE};
+use rtnetlink::packet::neighbour::Nla;
+use rtnetlink::packet::NeighbourMessage;
+use rtnetlink::{new_connection, Handle};
+
+use crate::error::{ErrnoAdvice, ErrnoErr};
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum Error {
     #[error("Opening rt-netlink connection failed")]
     #[diagnostic(code("rtnl::conn::open"))]
-    ConnectionError(#[source] io::Error),
-}
\ No newline at end of file
+    Connection(#[source] io::Error),
+
+    #[error("{operation} neighbour {proto_addr} on interface {ifindex} failed")]
+    #[diagnostic(code("kernel::neigh"))]
+    Neighbour {
+        operation: &'static str,
+        proto_addr: IpAddr,
+        ifindex: usize,
+        #[source]
+        #[diagnostic_source]
+        errno: ErrnoErr,
+        #[help]
+        help: Option<&'static str>,
+    },
+
+    #[error("rt-netlink request failed")]
+    #[diagnostic(code("rtnl::request"))]
+    Request(#[source] rtnetlink::Error),
+}
+
+pub struct Advice;
+impl ErrnoAdvice for Advice {
+    const EPERM: Option<&'static str> = Some("Changing neighbour entries requires CAP_NET_ADMIN");
+    const ENODEV: Option<&'static str> = Some("The GRE interface does not exist (anymore)");
+    const EINVAL: Option<&'static str> = Some("The NBMA address must be of the family the GRE interface tunnels over");
+}
+
+/// State neighbour entries are installed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighbourState {
+    /// Learned from a peer, replaced when it registers or resolves again
+    Reachable,
+    /// Configured, never touched by the kernel's garbage collection
+    Permanent,
+}
+
+impl NeighbourState {
+    fn nud(self) -> u16 {
+        match self {
+            NeighbourState::Reachable => NUD_REACHABLE,
+            NeighbourState::Permanent => NUD_PERMANENT,
+        }
+    }
+}
+
+fn octets(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().to_vec(),
+        IpAddr::V6(addr) => addr.octets().to_vec(),
+    }
+}
+
+fn family(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+/// Neighbour table of the GRE interfaces, programmed through rt-netlink.
+///
+/// A neighbour entry of a GRE interface binds a protocol address to the NBMA address packets
+/// for it are tunneled to, which ipgre and ip6gre devices take as link-layer address.
+#[derive(Debug, Clone)]
+pub struct Kernel {
+    handle: Handle,
+}
+
+impl Kernel {
+    /// Open an rt-netlink connection, driven by a task on the current runtime
+    pub fn connect() -> Result<Self, Error> {
+        let (connection, handle, _) = new_connection().map_err(Error::Connection)?;
+        tokio::spawn(connection);
+        Ok(Self { handle })
+    }
+
+    /// Send packets for `proto_addr` leaving the GRE interface `ifindex` straight to the peer
+    /// with NBMA address `nbma_addr`, adding its neighbour entry or changing the existing one.
+    ///
+    /// Entries are replaced rather than only added as GRE interfaces create their own for any
+    /// address they send to before it is resolved.
+    pub async fn replace_neighbour(&self, ifindex: usize, proto_addr: IpAddr, nbma_addr: IpAddr, state: NeighbourState)
+        -> Result<(), Error>
+    {
+        self.handle.neighbours().add(ifindex as u32, proto_addr)
+            .link_local_address(&octets(nbma_addr))
+            .state(state.nud())
+            .replace()
+            .execute().await
+            .map_err(|error| neighbour_error("replacing", ifindex, proto_addr, error))
+    }
+
+    /// Remove the neighbour entry for `proto_addr` from the GRE interface `ifindex`. An entry
+    /// the kernel already dropped is not an error.
+    pub async fn delete_neighbour(&self, ifindex: usize, proto_addr: IpAddr) -> Result<(), Error> {
+        let mut message = NeighbourMessage::default();
+        message.header.family = family(proto_addr);
+        message.header.ifindex = ifindex as u32;
+        message.nlas.push(Nla::Destination(octets(proto_addr)));
+
+        match self.handle.neighbours().del(message).execute().await {
+            Err(rtnetlink::Error::NetlinkError(ref e)) if e.code == -libc::ENOENT => Ok(()),
+            result => result.map_err(|error| neighbour_error("deleting", ifindex, proto_addr, error)),
+        }
+    }
+}
+
+fn neighbour_error(operation: &'static str, ifindex: usize, proto_addr: IpAddr, error: rtnetlink::Error) -> Error {
+    match error {
+        rtnetlink::Error::NetlinkError(e) => {
+            let errno = ErrnoErr::with::<Advice>(Errno::from_i32(-e.code));
+            Error::Neighbour { operation, proto_addr, ifindex, help: errno.advice, errno }
+        },
+        error => Error::Request(error),
+    }
+}
@@ -6,17 +6,31 @@
 
 use std::io;
 use std::net::IpAddr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::process::Command;
-use rtnetlink::new_connection;
 
 mod socket;
+mod cache;
+mod client;
 mod codec;
+mod framed;
 mod kernel;
 mod error;
 mod server;
+mod config;
+mod nflog;
+mod redirect;
+#[cfg(test)]
+mod test_util;
 
 use crate::socket::NhrpSocket;
+use crate::codec::NhrpCodec;
+use crate::framed::NhrpFramed;
+use crate::server::NhrpHandler;
+use crate::kernel::Kernel;
+use crate::config::Config;
+use crate::redirect::Redirector;
 
 #[tokio::main]
 async fn main() -> Result<(), miette::Error> {
@@ -33,13 +47,31 @@ async fn main() -> Result<(), miette::Error> {
     tracing_subscriber::fmt::init();
     tracing::info!("cloutd is starting");
 
-    let (nlconn, nlhandle, answers) = new_connection()
-        .map_err(kernel::Error::ConnectionError)?;
-    tokio::spawn(nlconn);
+    let config_path = std::env::args_os().nth(1).map(PathBuf::from);
+    let config = Config::load(config_path.as_deref())?;
+    let interfaces = config.interfaces_by_index()?;
+
+    let kernel = Kernel::connect()?;
 
     let nhrp_sock = NhrpSocket::new()?;
 
     tracing::info!(?nhrp_sock, "Opened NHRP sockets");
 
+    let redirect_interfaces: HashSet<usize> = interfaces.iter()
+        .filter(|(_, iface)| iface.redirect)
+        .map(|(index, _)| *index)
+        .collect();
+    let redirector = match config.redirect {
+        Some(ref redirect) if !redirect_interfaces.is_empty() =>
+            Some(Redirector::new(redirect, redirect_interfaces).await.map_err(server::Error::from)?),
+        _ => None,
+    };
+
+    let mut framed = NhrpFramed::new(nhrp_sock, NhrpCodec);
+    let handler = NhrpHandler::new(interfaces, config.validation, config.cache, kernel.clone());
+    handler.install_static_peers().await;
+    tokio::spawn(cache::expire(handler.cache(), kernel));
+    handler.handle_messages(&mut framed, redirector).await?;
+
     Ok(())
 }
@@ -0,0 +1,267 @@
+/*
+ * Minimal nfnetlink_log client. Only binding to a group and receiving logged packets is
+ * implemented, which is all shortcut detection needs.
+ */
+
+use std::io;
+
+use thiserror::Error;
+use miette::Diagnostic;
+use netlink_sys::{protocols::NETLINK_NETFILTER, AsyncSocket, AsyncSocketExt, SocketAddr, TokioSocket};
+use nix::errno::Errno;
+
+use crate::error::{ErrnoAdvice, ErrnoErr};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum Error {
+    #[error("opening netfilter log socket failed")]
+    #[diagnostic(code(cloutd::nflog::open))]
+    Socket {
+        #[source]
+        #[diagnostic_source]
+        errno: ErrnoErr,
+        #[help]
+        help: Option<&'static str>,
+    },
+
+    #[error("binding to NFLOG group {group} failed")]
+    #[diagnostic(code(cloutd::nflog::bind))]
+    Bind {
+        group: u16,
+        #[source]
+        #[diagnostic_source]
+        errno: ErrnoErr,
+        #[help]
+        help: Option<&'static str>,
+    },
+
+    #[error("receiving logged packets failed")]
+    #[diagnostic(code(cloutd::nflog::recv))]
+    Recv(#[source] io::Error),
+}
+impl Error {
+    fn socket(err: io::Error) -> Self {
+        let errno = errno(&err);
+        Self::Socket { help: errno.advice, errno }
+    }
+
+    fn bind(group: u16, err: io::Error) -> Self {
+        let errno = errno(&err);
+        Self::Bind { group, help: errno.advice, errno }
+    }
+}
+
+fn errno(err: &io::Error) -> ErrnoErr {
+    ErrnoErr::with::<Advice>(Errno::from_i32(err.raw_os_error().unwrap_or(0)))
+}
+
+pub struct Advice;
+impl ErrnoAdvice for Advice {
+    const EPERM: Option<&'static str> = Some("Receiving logged packets requires CAP_NET_ADMIN");
+    const EBUSY: Option<&'static str> = Some("Another process is already bound to this NFLOG group");
+}
+
+const NFNL_SUBSYS_ULOG: u16 = 4;
+const NFULNL_MSG_PACKET: u16 = 0;
+const NFULNL_MSG_CONFIG: u16 = 1;
+
+const NFULA_CFG_CMD: u16 = 1;
+const NFULA_CFG_MODE: u16 = 2;
+const NFULNL_CFG_CMD_BIND: u8 = 1;
+const NFULNL_COPY_PACKET: u8 = 2;
+
+const NFULA_IFINDEX_INDEV: u16 = 4;
+const NFULA_IFINDEX_OUTDEV: u16 = 5;
+const NFULA_PAYLOAD: u16 = 9;
+
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLMSG_HDRLEN: usize = 16;
+const NFGENMSG_LEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3FFF;
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A packet logged to our NFLOG group
+#[derive(Debug, Clone)]
+pub struct LoggedPacket {
+    pub indev: Option<usize>,
+    pub outdev: Option<usize>,
+    /// The packet starting with its network layer header, truncated to the copy range
+    pub payload: Vec<u8>,
+}
+
+pub struct NflogSocket {
+    socket: TokioSocket,
+}
+
+impl NflogSocket {
+    /// Bind to NFLOG `group`, copying at most `copy_range` bytes of each packet.
+    pub async fn bind(group: u16, copy_range: u32) -> Result<Self, Error> {
+        let mut socket = TokioSocket::new(NETLINK_NETFILTER).map_err(Error::socket)?;
+        socket.socket_mut().bind_auto().map_err(Error::socket)?;
+        socket.socket_mut().connect(&SocketAddr::new(0, 0)).map_err(Error::socket)?;
+
+        let mut mode = [0u8; 6];
+        mode[0..4].copy_from_slice(&copy_range.to_be_bytes());
+        mode[4] = NFULNL_COPY_PACKET;
+
+        let mut msg = Vec::new();
+        push_header(&mut msg, NFULNL_MSG_CONFIG, NLM_F_REQUEST | NLM_F_ACK, group);
+        push_attribute(&mut msg, NFULA_CFG_CMD, &[NFULNL_CFG_CMD_BIND]);
+        push_attribute(&mut msg, NFULA_CFG_MODE, &mode);
+        let len = msg.len() as u32;
+        msg[0..4].copy_from_slice(&len.to_ne_bytes());
+
+        socket.send(&msg).await.map_err(|e| Error::bind(group, e))?;
+
+        // Wait for the kernel to acknowledge the binding
+        loop {
+            let (reply, _) = socket.recv_from_full().await.map_err(|e| Error::bind(group, e))?;
+            for (mtype, payload) in messages(&reply) {
+                if mtype == NLMSG_ERROR && payload.len() >= 4 {
+                    let code = i32::from_ne_bytes(payload[0..4].try_into().unwrap());
+                    if code == 0 {
+                        return Ok(Self { socket });
+                    }
+                    return Err(Error::bind(group, io::Error::from_raw_os_error(-code)));
+                }
+            }
+        }
+    }
+
+    /// Receive the next batch of logged packets
+    pub async fn recv(&mut self) -> Result<Vec<LoggedPacket>, Error> {
+        let (buf, _) = self.socket.recv_from_full().await.map_err(Error::Recv)?;
+
+        let packets = messages(&buf)
+            .filter(|(mtype, _)| *mtype == (NFNL_SUBSYS_ULOG << 8 | NFULNL_MSG_PACKET))
+            .filter(|(_, payload)| payload.len() >= NFGENMSG_LEN)
+            .map(|(_, payload)| parse_packet(&payload[NFGENMSG_LEN..]))
+            .collect();
+        Ok(packets)
+    }
+}
+
+fn push_header(buf: &mut Vec<u8>, mtype: u16, flags: u16, group: u16) {
+    // nlmsghdr, length is filled in once the message is complete
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(&(NFNL_SUBSYS_ULOG << 8 | mtype).to_ne_bytes());
+    buf.extend_from_slice(&flags.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    // nfgenmsg: AF_UNSPEC, NFNETLINK_V0, resource id in network byte order
+    buf.extend_from_slice(&[0, 0]);
+    buf.extend_from_slice(&group.to_be_bytes());
+}
+
+fn push_attribute(buf: &mut Vec<u8>, atype: u16, data: &[u8]) {
+    let len = NLA_HDRLEN + data.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&atype.to_ne_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len() + align(len) - len, 0);
+}
+
+/// Iterate over the netlink messages in `buf`, yielding their type and payload
+fn messages(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < NLMSG_HDRLEN {
+            return None;
+        }
+        let len = u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return None;
+        }
+        let mtype = u16::from_ne_bytes(buf[4..6].try_into().unwrap());
+        let payload = &buf[NLMSG_HDRLEN..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((mtype, payload))
+    })
+}
+
+fn parse_packet(mut attrs: &[u8]) -> LoggedPacket {
+    let mut packet = LoggedPacket { indev: None, outdev: None, payload: Vec::new() };
+
+    while attrs.len() >= NLA_HDRLEN {
+        let len = u16::from_ne_bytes(attrs[0..2].try_into().unwrap()) as usize;
+        if len < NLA_HDRLEN || len > attrs.len() {
+            break;
+        }
+        let atype = u16::from_ne_bytes(attrs[2..4].try_into().unwrap()) & NLA_TYPE_MASK;
+        let data = &attrs[NLA_HDRLEN..len];
+        match atype {
+            NFULA_IFINDEX_INDEV if data.len() == 4 =>
+                packet.indev = Some(u32::from_be_bytes(data.try_into().unwrap()) as usize),
+            NFULA_IFINDEX_OUTDEV if data.len() == 4 =>
+                packet.outdev = Some(u32::from_be_bytes(data.try_into().unwrap()) as usize),
+            NFULA_PAYLOAD => packet.payload = data.to_vec(),
+            _ => {},
+        }
+        attrs = &attrs[align(len).min(attrs.len())..];
+    }
+
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::hex;
+
+    /// Packet message as logged on a little-endian host for group 5: the outer IPv4 header and
+    /// GRE header of the first packet in `NHRP.pcapng`, forwarded from interface 7 back out it.
+    const LOGGED: &str = concat!(
+        "58000000", "0004", "0000", "00000000", "00000000", // nlmsghdr
+        "02000005",                                         // nfgenmsg
+        "08000100", "08000200",                             // NFULA_PACKET_HDR
+        "09000a00", "6e68727000000000",                     // NFULA_PREFIX "nhrp", padded
+        "08000400", "00000007",                             // NFULA_IFINDEX_INDEV
+        "08000500", "00000007",                             // NFULA_IFINDEX_OUTDEV
+        "20000900", "450000742b360000402ffab4c6336405c6336404", "0000200100010800", // NFULA_PAYLOAD
+    );
+    const PAYLOAD: &str = "450000742b360000402ffab4c6336405c63364040000200100010800";
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn parses_logged_packet() {
+        let buf = hex(LOGGED);
+        let mut msgs = messages(&buf);
+        let (mtype, payload) = msgs.next().unwrap();
+        assert!(msgs.next().is_none());
+        assert_eq!(mtype, NFNL_SUBSYS_ULOG << 8 | NFULNL_MSG_PACKET);
+
+        let packet = parse_packet(&payload[NFGENMSG_LEN..]);
+        assert_eq!((packet.indev, packet.outdev), (Some(7), Some(7)));
+        assert_eq!(packet.payload, hex(PAYLOAD));
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn stops_at_truncated_attribute() {
+        let buf = hex(LOGGED);
+        let attrs = &buf[NLMSG_HDRLEN + NFGENMSG_LEN..];
+        // Cut into the payload attribute: the interfaces before it are still read
+        let packet = parse_packet(&attrs[..attrs.len() - 4]);
+        assert_eq!((packet.indev, packet.outdev), (Some(7), Some(7)));
+        assert!(packet.payload.is_empty());
+
+        // A message longer than the buffer is not yielded at all
+        assert!(messages(&buf[..buf.len() - 1]).next().is_none());
+    }
+
+    #[test]
+    fn builds_bind_request() {
+        let mut msg = Vec::new();
+        push_header(&mut msg, NFULNL_MSG_CONFIG, NLM_F_REQUEST | NLM_F_ACK, 5);
+        push_attribute(&mut msg, NFULA_CFG_CMD, &[NFULNL_CFG_CMD_BIND]);
+        assert_eq!(msg.len(), NLMSG_HDRLEN + NFGENMSG_LEN + 8);
+        // Resource ID is the group in network byte order, the command is padded to 4 octets
+        assert_eq!(&msg[NLMSG_HDRLEN..NLMSG_HDRLEN + NFGENMSG_LEN], &[0, 0, 0, 5]);
+        assert_eq!(&msg[NLMSG_HDRLEN + NFGENMSG_LEN + NLA_HDRLEN..], &[NFULNL_CFG_CMD_BIND, 0, 0, 0]);
+    }
+}
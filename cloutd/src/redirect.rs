@@ -0,0 +1,231 @@
+/*
+ * Shortcut detection for hubs: a packet that is forwarded out the same tunnel interface it came
+ * in on travelled spoke -> hub -> spoke, and the originating spoke should be told to resolve a
+ * direct path to the destination instead.
+ */
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use crate::config::RedirectConfig;
+use crate::nflog::{self, LoggedPacket, NflogSocket};
+
+/// How much of an offending packet is copied into a Traffic Indication: the longest network
+/// layer header we parse plus the first 8 octets of the transport header, like ICMP does.
+const COPY_RANGE: u32 = 60 + 8;
+const IPV6_HEADER_LEN: usize = 40;
+const TRANSPORT_HEADER_LEN: usize = 8;
+
+/// Number of rate limit entries at which expired ones are cleaned up
+const RATE_LIMIT_PRUNE: usize = 1024;
+
+/// A packet that should be answered with a Traffic Indication
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// The tunnel interface the packet hairpinned through
+    pub ifindex: usize,
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    /// The network layer header and the start of the transport header of the packet
+    pub packet: Vec<u8>,
+}
+
+pub struct Redirector {
+    socket: NflogSocket,
+    detector: Detector,
+    pending: VecDeque<Redirect>,
+}
+
+impl Redirector {
+    /// Start watching for hairpinned traffic on `interfaces`
+    pub async fn new(config: &RedirectConfig, interfaces: HashSet<usize>) -> Result<Self, nflog::Error> {
+        let socket = NflogSocket::bind(config.nflog_group, COPY_RANGE).await?;
+        tracing::info!(group = config.nflog_group, ?interfaces, "watching for traffic to redirect");
+
+        Ok(Self {
+            socket,
+            detector: Detector::new(interfaces, config.interval()),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next packet the originating spoke should be sent a Traffic Indication for
+    pub async fn next(&mut self) -> Result<Redirect, nflog::Error> {
+        loop {
+            if let Some(redirect) = self.pending.pop_front() {
+                return Ok(redirect);
+            }
+
+            for packet in self.socket.recv().await? {
+                if let Some(redirect) = self.detector.check(packet, Instant::now()) {
+                    self.pending.push_back(redirect);
+                }
+            }
+        }
+    }
+
+}
+
+/// Picks the logged packets that hairpinned through one of our tunnel interfaces, at most one
+/// per source and destination every interval
+struct Detector {
+    interfaces: HashSet<usize>,
+    interval: Duration,
+    last_sent: HashMap<(IpAddr, IpAddr), Instant>,
+}
+
+impl Detector {
+    fn new(interfaces: HashSet<usize>, interval: Duration) -> Self {
+        Self { interfaces, interval, last_sent: HashMap::new() }
+    }
+
+    fn check(&mut self, packet: LoggedPacket, now: Instant) -> Option<Redirect> {
+        let ifindex = match (packet.indev, packet.outdev) {
+            (Some(indev), Some(outdev)) if indev == outdev => indev,
+            _ => return None,
+        };
+        if !self.interfaces.contains(&ifindex) {
+            return None;
+        }
+        let (src_addr, dst_addr, header_len) = match parse_ip_header(&packet.payload) {
+            Some(parsed) => parsed,
+            None => {
+                tracing::debug!(ifindex, "ignoring logged packet without a valid IP header");
+                return None;
+            },
+        };
+
+        if let Some(last) = self.last_sent.get(&(src_addr, dst_addr)) {
+            if now.duration_since(*last) < self.interval {
+                return None;
+            }
+        }
+        if self.last_sent.len() >= RATE_LIMIT_PRUNE {
+            let interval = self.interval;
+            self.last_sent.retain(|_, last| now.duration_since(*last) < interval);
+        }
+        self.last_sent.insert((src_addr, dst_addr), now);
+
+        let len = (header_len + TRANSPORT_HEADER_LEN).min(packet.payload.len());
+        let mut payload = packet.payload;
+        payload.truncate(len);
+
+        Some(Redirect { ifindex, src_addr, dst_addr, packet: payload })
+    }
+}
+
+/// Source address, destination address and header length of an IP packet
+fn parse_ip_header(packet: &[u8]) -> Option<(IpAddr, IpAddr, usize)> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = ((packet[0] & 0x0F) as usize) * 4;
+            if header_len < 20 || packet.len() < header_len {
+                return None;
+            }
+            let src: [u8; 4] = packet[12..16].try_into().unwrap();
+            let dst: [u8; 4] = packet[16..20].try_into().unwrap();
+            Some((Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into(), header_len))
+        },
+        6 => {
+            if packet.len() < IPV6_HEADER_LEN {
+                return None;
+            }
+            let src: [u8; 16] = packet[8..24].try_into().unwrap();
+            let dst: [u8; 16] = packet[24..40].try_into().unwrap();
+            Some((Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into(), IPV6_HEADER_LEN))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::hex;
+
+    /// Outer IPv4 header and GRE header of the first packet in `NHRP.pcapng`
+    const IPV4: &str = "450000742b360000402ffab4c6336405c63364040000200100010800";
+    /// ICMPv6 echo request from fd00::2 to fd00::3
+    const IPV6: &str = "6000000000083a3ffd000000000000000000000000000002fd0000000000000000000000000000038000000000000000";
+
+    const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 5));
+    const HUB: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 4));
+
+    fn logged(indev: usize, outdev: usize, payload: &str) -> LoggedPacket {
+        LoggedPacket { indev: Some(indev), outdev: Some(outdev), payload: hex(payload) }
+    }
+
+    #[test]
+    fn parses_ipv4_header() {
+        assert_eq!(parse_ip_header(&hex(IPV4)), Some((SPOKE, HUB, 20)));
+        // Header length of 24 with only 20 octets present
+        let mut packet = hex(IPV4);
+        packet[0] = 0x46;
+        assert_eq!(parse_ip_header(&packet[..20]), None);
+        packet[0] = 0x44;
+        assert_eq!(parse_ip_header(&packet), None);
+    }
+
+    #[test]
+    fn parses_ipv6_header() {
+        let src = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+        let dst = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 3));
+        let packet = hex(IPV6);
+        assert_eq!(parse_ip_header(&packet), Some((src, dst, IPV6_HEADER_LEN)));
+        assert_eq!(parse_ip_header(&packet[..IPV6_HEADER_LEN - 1]), None);
+        assert_eq!(parse_ip_header(&[]), None);
+        assert_eq!(parse_ip_header(&[0x50; 40]), None);
+    }
+
+    #[test]
+    fn redirects_hairpinned_packets_only() {
+        let now = Instant::now();
+        let mut detector = Detector::new(HashSet::from([7]), Duration::from_secs(10));
+        assert!(detector.check(logged(7, 8, IPV4), now).is_none());
+        assert!(detector.check(logged(8, 8, IPV4), now).is_none());
+
+        let redirect = detector.check(logged(7, 7, IPV4), now).unwrap();
+        assert_eq!((redirect.ifindex, redirect.src_addr, redirect.dst_addr), (7, SPOKE, HUB));
+        assert_eq!(redirect.packet, hex(IPV4));
+    }
+
+    #[test]
+    fn truncates_to_transport_header() {
+        let mut packet = logged(7, 7, IPV4);
+        packet.payload.extend_from_slice(&[0; 32]);
+        let mut detector = Detector::new(HashSet::from([7]), Duration::from_secs(10));
+        let redirect = detector.check(packet, Instant::now()).unwrap();
+        assert_eq!(redirect.packet.len(), 20 + TRANSPORT_HEADER_LEN);
+    }
+
+    #[test]
+    fn rate_limits_per_source_and_destination() {
+        let interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut detector = Detector::new(HashSet::from([7]), interval);
+        assert!(detector.check(logged(7, 7, IPV4), start).is_some());
+        assert!(detector.check(logged(7, 7, IPV4), start + interval / 2).is_none());
+
+        // The reverse direction is another pair
+        let mut reverse = hex(IPV4);
+        reverse[12..20].copy_from_slice(&hex("c6336404c6336405"));
+        let packet = LoggedPacket { indev: Some(7), outdev: Some(7), payload: reverse };
+        assert!(detector.check(packet, start + interval / 2).is_some());
+
+        assert!(detector.check(logged(7, 7, IPV4), start + interval).is_some());
+    }
+
+    #[test]
+    fn prunes_expired_rate_limits() {
+        let interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut detector = Detector::new(HashSet::from([7]), interval);
+        for i in 0..RATE_LIMIT_PRUNE {
+            let dst = Ipv4Addr::from(0x0a00_0000 + i as u32).into();
+            detector.last_sent.insert((SPOKE, dst), start);
+        }
+        detector.check(logged(7, 7, IPV4), start + interval).unwrap();
+        assert_eq!(detector.last_sent.len(), 1);
+    }
+}
@@ -8,59 +8,694 @@
  *    handle Error = void $ liftIO $ print error
  */
 
-use std::io;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use miette::Diagnostic;
-use nhrp::{NhrpBuffer, NhrpOp, OperationBuffer};
-use crate::{NhrpSocket, socket};
+use futures::{SinkExt, StreamExt};
+use nhrp::{ClientInformationEntry, ErrorCode, ErrorIndicationMessage, Extension, NbmaAddress, NhrpBuffer, NhrpMessage, NhrpMessageView, Operation, RegistrationCode, RegistrationFlags, RegistrationReplyMessage, ResolutionCode, ResolutionFlags, ResolutionReplyMessage, TrafficCode, TrafficIndicationMessage};
+use crate::{codec, nflog};
+use crate::kernel::{Kernel, NeighbourState};
+use crate::cache::{host_prefix_len, CacheEntry, EntryKind, PeerCache};
+use crate::client::{NhrpClient, Outcome};
+use crate::codec::{NhrpCodec, Packet};
+use crate::framed::NhrpFramed;
+use crate::socket::PeerAddr;
+use crate::config::{AuthFailureAction, CacheConfig, InterfaceConfig, ValidationConfig, ViolationAction};
+use crate::redirect::{Redirect, Redirector};
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum Error {
-    #[error("socket error occurred")]
-    Socket(#[source] #[from] #[diagnostic_source] socket::Error),
-    #[error("received invalid NHRP message")]
-    Parse(#[source] #[from] nhrp::Error),
-    #[error("received msg with unknown operation type {0}")]
-    UnknownOpType(u8),
+    #[error("NHRP socket failed")]
+    Socket(#[source] #[from] #[diagnostic_source] codec::Error),
+    #[error("failed to build NHRP message")]
+    Build(#[source] #[from] nhrp::Error),
+    #[error("watching for traffic to redirect failed")]
+    Redirect(#[source] #[from] #[diagnostic_source] nflog::Error),
 }
 
-const BUFFER_LEN: usize = 2048;
+/// Holding time handed out in resolution replies for entries that never expire
+const RESOLUTION_HOLDING_TIME: u16 = 60;
 
-pub struct NhrpHandler {
+/// Hop count of Traffic Indications, which are only ever sent to directly attached spokes
+const TRAFFIC_INDICATION_HOPCOUNT: u8 = 1;
+
+pub type Framed = NhrpFramed<NhrpCodec>;
+
+fn unspecified(like: &IpAddr) -> IpAddr {
+    match like {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    }
+}
+
+/// Resolves once `redirector` yields a packet to redirect, never if there is none
+async fn next_redirect(redirector: &mut Option<Redirector>) -> Result<Redirect, nflog::Error> {
+    match redirector {
+        Some(redirector) => redirector.next().await,
+        None => std::future::pending().await,
+    }
+}
 
+/// Cisco NAT extension telling a client which NBMA address its request came from
+fn nat_extension(observed: IpAddr, proto_addr: IpAddr) -> Result<Extension, nhrp::Error> {
+    let cie = ClientInformationEntry::builder()
+        .client_nbma_addr(observed)
+        .client_proto_addr(proto_addr)
+        .build()?;
+    Ok(Extension::NatAddress { compulsory: false, cies: vec![cie] })
+}
+
+/// Extensions of a request that are copied into the reply. Authentication is recalculated when
+/// sending and the NAT extension is answered separately.
+fn reply_extensions(extensions: Vec<Extension>) -> Vec<Extension> {
+    extensions.into_iter()
+        .filter(|e| !matches!(e,
+            Extension::Authentication { .. } | Extension::NatAddress { .. } | Extension::EndOfExtensions))
+        .collect()
+}
+
+/// Prefix length a CIE registers, a single host if it leaves it open
+fn registered_prefix_len(cie_prefix_len: u8, proto_addr: &IpAddr) -> u8 {
+    let host = host_prefix_len(proto_addr);
+    match cie_prefix_len {
+        0 => host,
+        len => len.min(host),
+    }
+}
+
+pub struct NhrpHandler {
+    interfaces: HashMap<usize, InterfaceConfig>,
+    validation: ValidationConfig,
+    limits: CacheConfig,
+    peers: PeerCache,
+    kernel: Kernel,
+    client: Mutex<NhrpClient>,
 }
 impl NhrpHandler {
-    pub fn new() -> Self {
-        Self { }
+    /// Handler answering from a cache holding our own addresses and the static peers of
+    /// `interfaces`, and registering with the NHSes configured for them
+    pub fn new(interfaces: HashMap<usize, InterfaceConfig>, validation: ValidationConfig, limits: CacheConfig,
+               kernel: Kernel) -> Self
+    {
+        let mut cache = crate::cache::Cache::default();
+        for (ifindex, iface) in interfaces.iter() {
+            if let (Some(proto_addr), Some(nbma_addr)) = (iface.protocol_address, iface.nbma_address) {
+                cache.insert(proto_addr, CacheEntry::fixed(EntryKind::Local, *ifindex, proto_addr, nbma_addr));
+            }
+            for (proto_addr, nbma_addr) in iface.static_peers.iter() {
+                cache.insert(*proto_addr, CacheEntry::fixed(EntryKind::Static, *ifindex, *proto_addr, *nbma_addr));
+            }
+        }
+        let client = Mutex::new(NhrpClient::new(&interfaces));
+        Self { interfaces, validation, limits, peers: PeerCache::new(cache.into()), kernel, client }
     }
 
-    pub async fn on_resolution_request(&self, msg: NhrpBuffer<&'_ [u8]>) -> Result<(), Error> {
-        Ok(())
+    /// The cache the handler answers from
+    pub fn cache(&self) -> PeerCache {
+        self.peers.clone()
     }
 
-    pub async fn handle_messages(&mut self, socket: &NhrpSocket) -> Result<(), Error> {
-        loop {
-            let mut buf = [0u8; BUFFER_LEN];
-            let (len, source) = socket.recv(&mut buf).await?;
-            if len == 0 {
+    /// Install neighbour entries for the static peers
+    pub async fn install_static_peers(&self) {
+        let cache = self.peers.read().await;
+        let static_peers = cache.iter().filter(|(_, entry)| entry.kind == EntryKind::Static);
+        for (proto_addr, entry) in static_peers {
+            let nbma_addr = match entry.nbma_addr {
+                Some(nbma_addr) => nbma_addr,
+                None => continue,
+            };
+            if let Err(error) = self.kernel.replace_neighbour(entry.ifindex, *proto_addr, nbma_addr, NeighbourState::Permanent).await {
+                tracing::warn!(%error, %proto_addr, %nbma_addr, "installing neighbour entry failed");
+            }
+        }
+    }
+
+    /// Check the authentication of a received message against the policy of the interface it
+    /// was received on. Returns `false` if the message must be discarded.
+    async fn check_authentication(&self, framed: &mut Framed, packet: &[u8], source: &PeerAddr)
+        -> Result<bool, Error>
+    {
+        let iface = match self.interfaces.get(&source.ifindex) {
+            Some(iface) => iface,
+            None => return Ok(true),
+        };
+        let auth = match iface.authentication {
+            Some(ref auth) => auth,
+            None => return Ok(true),
+        };
+
+        match auth.method.authenticator().verify(packet) {
+            Ok(()) => Ok(true),
+            Err(error) => {
+                tracing::warn!(%error, ifindex = source.ifindex,
+                    "discarding message that failed authentication");
+                if auth.on_failure == AuthFailureAction::ErrorIndication {
+                    self.send_error_indication(framed, packet, ErrorCode::AuthenticationFailure, 0, source)
+                        .await?;
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Check a received message against the rules of RFC 2332 and act on the violations as
+    /// configured for their class. The strictest action wins. Returns `false` if the message
+    /// must be discarded.
+    async fn check_validity(&self, framed: &mut Framed, packet: &[u8], msg: &NhrpMessage, source: &PeerAddr)
+        -> Result<bool, Error>
+    {
+        let mut violations = NhrpMessageView::new(packet).map(|view| view.validate()).unwrap_or_default();
+        violations.extend(msg.validate());
+        for violation in violations.iter() {
+            tracing::warn!(%violation, section = violation.section(), ifindex = source.ifindex,
+                "message violates RFC 2332");
+        }
+
+        let worst = match violations.iter().max_by_key(|v| self.validation.action(v.class())) {
+            Some(violation) => violation,
+            None => return Ok(true),
+        };
+        match self.validation.action(worst.class()) {
+            ViolationAction::Log => Ok(true),
+            ViolationAction::Drop => Ok(false),
+            ViolationAction::ErrorIndication => {
+                let offset = u16::try_from(worst.offset()).unwrap_or(0);
+                self.send_error_indication(framed, packet, worst.error_code(), offset, source).await?;
+                Ok(false)
+            },
+        }
+    }
+
+    /// Answer the message in `packet` with an Error Indication.
+    ///
+    /// Errors are never answered with errors, nor are messages we can't make sense of at all.
+    async fn send_error_indication(&self,
+                                   framed: &mut Framed,
+                                   packet: &[u8],
+                                   code: ErrorCode,
+                                   offset: u16,
+                                   dest: &PeerAddr
+    ) -> Result<(), Error> {
+        let offending: NhrpMessage = match NhrpMessage::from_bytes(packet) {
+            Ok(msg) => msg,
+            Err(error) => {
+                tracing::debug!(%error, "not answering unparseable message with an error indication");
                 return Ok(());
+            },
+        };
+        let offending_header = match offending.operation.common_header() {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+
+        let iface = self.interfaces.get(&dest.ifindex).cloned().unwrap_or_default();
+        let src_nbma_addr = match (iface.nbma_address, offending_header.src_nbma_addr.ip()) {
+            (Some(addr), _) => addr.into(),
+            (None, Some(addr)) => unspecified(&addr).into(),
+            (None, None) => NbmaAddress::new(offending_header.src_nbma_addr.addr_type(), Vec::new())?,
+        };
+        let src_proto_addr = iface.protocol_address.unwrap_or(offending_header.dst_proto_addr);
+
+        let len = NhrpBuffer::new(packet).length() as usize;
+        let error = ErrorIndicationMessage::builder(code, src_nbma_addr, src_proto_addr,
+                offending_header.src_proto_addr)
+            .offset(offset)
+            .packet(packet[..len].to_vec())
+            .build()?;
+        // The source NBMA address may be empty, so take the address family from the offending
+        // packet instead of deriving it.
+        let msg = NhrpMessage::builder(error)
+            .afn(offending.header.afn())
+            .protocol_type(offending.header.protocol_type())
+            .hopcount(offending.header.hopcount())
+            .build()?;
+
+        self.send(framed, msg, dest).await
+    }
+
+    /// Tell the spoke a hairpinned packet came from to resolve a shortcut to its destination.
+    async fn send_traffic_indication(&self, framed: &mut Framed, redirect: Redirect) -> Result<(), Error> {
+        let registered = self.peers.read().await.get(&redirect.src_addr)
+            .filter(|entry| entry.kind == EntryKind::Dynamic)
+            .and_then(CacheEntry::resolves);
+        let nbma_addr = match registered {
+            Some(addr) => addr,
+            None => {
+                tracing::debug!(src_addr = %redirect.src_addr, dst_addr = %redirect.dst_addr,
+                    "not redirecting traffic from a source that is not a registered spoke");
+                return Ok(());
+            },
+        };
+        let dest = PeerAddr::new(redirect.ifindex, nbma_addr);
+        tracing::debug!(src_addr = %redirect.src_addr, dst_addr = %redirect.dst_addr, %nbma_addr,
+            "sending traffic indication");
+
+        let iface = self.interfaces.get(&redirect.ifindex).cloned().unwrap_or_default();
+        let src_nbma_addr = iface.nbma_address.unwrap_or_else(|| unspecified(&nbma_addr));
+        let src_proto_addr = iface.protocol_address.unwrap_or_else(|| unspecified(&redirect.src_addr));
+
+        let indication = TrafficIndicationMessage::builder(TrafficCode::Redirect, src_nbma_addr,
+                src_proto_addr, redirect.src_addr)
+            .packet(redirect.packet)
+            .build()?;
+        let msg = NhrpMessage::builder(indication)
+            .hopcount(TRAFFIC_INDICATION_HOPCOUNT)
+            .build()?;
+
+        self.send(framed, msg, &dest).await
+    }
+
+    /// Resolves once a registration with an NHS is due, never if there are none
+    async fn registration_due(&self) {
+        let due = self.client.lock().unwrap().next_due();
+        match due {
+            Some(due) => tokio::time::sleep_until(due.into()).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Send the registration requests that are due. An NHS we never registered with has an
+    /// incomplete cache entry until it answers.
+    async fn send_registrations(&self, framed: &mut Framed) {
+        let requests = self.client.lock().unwrap().due_requests(Instant::now());
+        for request in requests {
+            let nhs = request.nhs.protocol_address;
+            {
+                let mut peers = self.peers.write().await;
+                if peers.get(&nhs).is_none_or(|entry| entry.resolves().is_none()) {
+                    peers.insert(nhs, CacheEntry::unresolved(EntryKind::Incomplete, request.ifindex, nhs, request.timeout));
+                }
+            }
+            tracing::debug!(%nhs, nbma_addr = %request.nhs.nbma_address, "sending registration request");
+            let dest = PeerAddr::new(request.ifindex, request.nhs.nbma_address);
+            if let Err(error) = self.send(framed, request.msg, &dest).await {
+                tracing::warn!(?error, %nhs, "sending registration request failed");
+            }
+        }
+    }
+
+    /// Send a message to `dest`, authenticating it if the outgoing interface requires it.
+    async fn send(&self, framed: &mut Framed, mut msg: NhrpMessage, dest: &PeerAddr) -> Result<(), Error> {
+        if let Some(auth) = self.interfaces.get(&dest.ifindex).and_then(|i| i.authentication.as_ref()) {
+            msg.authenticate(&auth.method.authenticator());
+        }
+
+        framed.send((msg, *dest)).await?;
+        Ok(())
+    }
+
+    /// Register the bindings of a Registration Request and answer it with a Registration Reply.
+    /// Without CIEs the request registers its source.
+    pub async fn on_registration_request(&self, msg: NhrpMessage, source: &PeerAddr)
+        -> Result<Option<NhrpMessage>, Error>
+    {
+        let (header, operation, extensions) = msg.into_parts();
+        let (hdr, flags, cies) = match operation {
+            Operation::RegistrationRequest(msg) => msg.into_parts(),
+            _ => return Ok(None),
+        };
+
+        // A client behind NAT only knows its private address and claims that one. Peers have to
+        // be sent to the address its requests actually arrive from instead.
+        let claimed = hdr.src_nbma_addr.ip();
+        let observed = source.nbma;
+        let behind_nat = claimed != Some(observed);
+        if behind_nat {
+            tracing::info!(claimed = %hdr.src_nbma_addr, %observed, src_proto_addr = %hdr.src_proto_addr,
+                "registering client is behind NAT");
+        }
+
+        // Whatever a CIE claims, its binding is to the address the request arrived from: trusting
+        // the claim would let any client redirect traffic for its addresses elsewhere.
+        let mut bindings: Vec<(IpAddr, Option<&ClientInformationEntry>)> = cies.iter()
+            .map(|cie| {
+                let proto_addr = cie.client_proto_addr.unwrap_or(hdr.src_proto_addr);
+                if let Some(cie_claimed) = cie.client_nbma_addr.as_ref().and_then(NbmaAddress::ip) {
+                    if cie_claimed != observed {
+                        tracing::info!(%proto_addr, claimed = %cie_claimed, %observed,
+                            "CIE claims another NBMA address than the request came from, binding the observed one");
+                    }
+                }
+                (proto_addr, Some(cie))
+            })
+            .collect();
+        if bindings.is_empty() {
+            bindings.push((hdr.src_proto_addr, None));
+        }
+
+        // Every CIE is echoed with the code of its own registration (RFC 2332, 5.2.4)
+        let mut code = RegistrationCode::Success;
+        let mut reply_cies = Vec::new();
+        let mut registered = Vec::new();
+        {
+            let mut peers = self.peers.write().await;
+            let now = Instant::now();
+            for (proto_addr, cie) in bindings {
+                let requested = cie.map_or(0, |cie| cie.holding_time);
+                let granted = match requested {
+                    0 => self.limits.default_holding_time(),
+                    requested => requested,
+                };
+                let refusal = if granted > self.limits.max_holding_time {
+                    tracing::info!(%proto_addr, holding_time = granted, max = self.limits.max_holding_time,
+                        "refusing registration with overlong holding time");
+                    Some(RegistrationCode::Prohibited)
+                } else {
+                    match peers.get(&proto_addr) {
+                        Some(entry) if matches!(entry.kind, EntryKind::Static | EntryKind::Local) => {
+                            tracing::info!(%proto_addr, kind = ?entry.kind, "refusing registration of configured address");
+                            Some(RegistrationCode::Prohibited)
+                        },
+                        Some(entry) if !entry.is_expired(now) && entry.kind == EntryKind::Dynamic
+                            && entry.nbma_addr != Some(observed) && (entry.unique || flags.unique) =>
+                        {
+                            tracing::info!(%proto_addr, registered = ?entry.nbma_addr, nbma_addr = %observed,
+                                "refusing registration of address registered uniquely");
+                            Some(RegistrationCode::AlreadyRegistered)
+                        },
+                        _ => None,
+                    }
+                };
+
+                let outcome = refusal.unwrap_or(RegistrationCode::Success);
+                if code == RegistrationCode::Success {
+                    code = outcome;
+                }
+                if let Some(cie) = cie {
+                    reply_cies.push(ClientInformationEntry {
+                        code: outcome.into(),
+                        holding_time: if refusal.is_none() { granted } else { cie.holding_time },
+                        ..cie.clone()
+                    });
+                }
+                if refusal.is_some() {
+                    continue;
+                }
+
+                peers.insert(proto_addr, CacheEntry {
+                    kind: EntryKind::Dynamic,
+                    ifindex: source.ifindex,
+                    prefix_len: registered_prefix_len(cie.map_or(0, |cie| cie.prefix_len), &proto_addr),
+                    nbma_addr: Some(observed),
+                    registrant: Some(observed),
+                    unique: flags.unique,
+                    holding_time: Duration::from_secs(granted.into()),
+                    mtu: cie.map_or(0, |cie| cie.mtu),
+                    preference: cie.map_or(0, |cie| cie.preference),
+                    created: now,
+                });
+                registered.push(proto_addr);
+            }
+            tracing::debug!(peers = ?*peers, "NBMA associations updated");
+        }
+        for proto_addr in registered {
+            if let Err(error) = self.kernel.replace_neighbour(source.ifindex, proto_addr, observed, NeighbourState::Reachable).await {
+                tracing::warn!(%error, %proto_addr, nbma_addr = %observed, "installing neighbour entry failed");
             }
-            let msgbuf = &buf[0..len];
-            let msg = NhrpBuffer::new_checked(msgbuf)?;
-            match msg.optype() {
-                NhrpOp::ResolutionRequest => {
-                    self.on_resolution_request(msg).await?;
+        }
+
+        let nat_requested = extensions.iter().any(|e| matches!(e, Extension::NatAddress { .. }));
+        let mut extensions = reply_extensions(extensions);
+        if nat_requested || behind_nat {
+            extensions.push(nat_extension(observed, hdr.src_proto_addr)?);
+        }
+
+        let mut reply = RegistrationReplyMessage::builder(hdr.src_nbma_addr, hdr.src_proto_addr, hdr.dst_proto_addr)
+            .request_id(hdr.request_id)
+            .flags(RegistrationFlags { unique: flags.unique, ..Default::default() });
+        if reply_cies.is_empty() {
+            reply = reply.code(code);
+        }
+        for cie in reply_cies {
+            reply = reply.cie(cie);
+        }
+        let msg = NhrpMessage::builder(reply.build()?)
+            .hopcount(header.hopcount())
+            .extensions(extensions)
+            .build()?;
+        Ok(Some(msg))
+    }
+
+    /// Answer a Resolution Request with the binding registered for its destination, if any
+    pub async fn on_resolution_request(&self, msg: NhrpMessage) -> Result<Option<NhrpMessage>, Error> {
+        let (header, operation, extensions) = msg.into_parts();
+        let (hdr, flags, _) = match operation {
+            Operation::ResolutionRequest(msg) => msg.into_parts(),
+            _ => return Ok(None),
+        };
+
+        let now = Instant::now();
+        let resolved = self.peers.read().await.lookup(&hdr.dst_proto_addr, now)
+            .map(|(proto_addr, entry)| (proto_addr, entry.clone()));
+        let code = match resolved {
+            Some((_, ref entry)) if entry.resolves().is_some() => {
+                tracing::debug!(nbma_addr = ?entry.nbma_addr, dst_proto_addr = %hdr.dst_proto_addr, "resolved NBMA address");
+                ResolutionCode::Success
+            },
+            _ => {
+                tracing::debug!(dst_proto_addr = %hdr.dst_proto_addr, "no NBMA address registered");
+                ResolutionCode::NoBindingExists
+            },
+        };
+
+        let mut reply = ResolutionReplyMessage::builder(hdr.src_nbma_addr, hdr.src_proto_addr, hdr.dst_proto_addr)
+            .request_id(hdr.request_id)
+            .flags(ResolutionFlags {
+                requester_router: flags.requester_router,
+                authoritative: true,
+                unique: true,
+                source_stable: flags.source_stable,
+                ..Default::default()
+            })
+            .code(code);
+        // Negative entries are answered with how long the address is known not to resolve
+        let answer = resolved.filter(|(_, entry)| entry.kind != EntryKind::Incomplete);
+        if let Some((proto_addr, entry)) = answer {
+            let mut cie = ClientInformationEntry::builder()
+                .client_proto_addr(proto_addr)
+                .prefix_len(entry.prefix_len)
+                .mtu(entry.mtu)
+                .preference(entry.preference)
+                .holding_time(entry.remaining(now).unwrap_or(RESOLUTION_HOLDING_TIME));
+            if let Some(nbma_addr) = entry.resolves() {
+                cie = cie.client_nbma_addr(nbma_addr);
+            }
+            reply = reply.cie(cie.build()?);
+        }
+        let msg = NhrpMessage::builder(reply.build()?)
+            .hopcount(header.hopcount())
+            .extensions(reply_extensions(extensions))
+            .build()?;
+        Ok(Some(msg))
+    }
+
+    /// Withdraw the bindings named by a Purge Request and answer it with a Purge Reply, unless
+    /// the sender asked for none. Without CIEs the request purges the binding of its source.
+    pub async fn on_purge_request(&self, msg: NhrpMessage, source: &PeerAddr)
+        -> Result<Option<NhrpMessage>, Error>
+    {
+        let (header, operation, extensions) = msg.into_parts();
+        let purge = match operation {
+            Operation::PurgeRequest(msg) => msg,
+            _ => return Ok(None),
+        };
+        let hdr = purge.header();
+
+        let mut proto_addrs: Vec<IpAddr> = purge.cie().iter()
+            .map(|cie| cie.client_proto_addr.unwrap_or(hdr.src_proto_addr))
+            .collect();
+        if proto_addrs.is_empty() {
+            proto_addrs.push(hdr.src_proto_addr);
+        }
+
+        // Only the peer a binding belongs to may withdraw it. The source NBMA address in the
+        // message is whatever the sender chose to put there, so only the observed one counts.
+        let observed = source.nbma;
+        let mut purged = Vec::new();
+        {
+            let mut peers = self.peers.write().await;
+            for proto_addr in proto_addrs {
+                match peers.purge(&proto_addr, observed) {
+                    Ok(Some(entry)) => purged.push((proto_addr, entry.ifindex)),
+                    Ok(None) => tracing::debug!(%proto_addr, "no binding to purge"),
+                    Err(registrant) => tracing::info!(%proto_addr, %registrant, %observed,
+                        "not purging binding registered by another peer"),
                 }
-                NhrpOp::ResolutionReply => {}
-                NhrpOp::RegistrationRequest => {}
-                NhrpOp::RegistrationReply => {}
-                NhrpOp::PurgeRequest => {}
-                NhrpOp::PurgeReply => {}
-                NhrpOp::ErrorIndication => {}
-                NhrpOp::Other(val) => {
-                    return Err(Error::UnknownOpType(val));
+            }
+            tracing::debug!(peers = ?*peers, "NBMA associations updated");
+        }
+        for (proto_addr, ifindex) in purged {
+            if let Err(error) = self.kernel.delete_neighbour(ifindex, proto_addr).await {
+                tracing::warn!(%error, %proto_addr, "removing neighbour entry failed");
+            }
+        }
+
+        if purge.flags().no_reply {
+            return Ok(None);
+        }
+        // The reply echoes the request
+        let msg = NhrpMessage::builder(Operation::PurgeReply(purge))
+            .hopcount(header.hopcount())
+            .extensions(reply_extensions(extensions))
+            .build()?;
+        Ok(Some(msg))
+    }
+
+    /// Act on the reply to one of our registrations: install the mapping of an NHS that
+    /// accepted it, remember for the backoff that one refused it.
+    pub async fn on_registration_reply(&self, msg: NhrpMessage, source: &PeerAddr) -> Result<(), Error> {
+        let reply = match msg.operation {
+            Operation::RegistrationReply(ref reply) => reply,
+            _ => return Ok(()),
+        };
+        let outcome = self.client.lock().unwrap().on_reply(reply, source, Instant::now());
+        match outcome {
+            None => tracing::debug!(request_id = reply.header().request_id, %source,
+                "ignoring registration reply to no request pending with its sender"),
+            Some(Outcome::Registered { ifindex, nhs, holding_time }) => {
+                tracing::info!(nhs = %nhs.protocol_address, holding_time, "registered with NHS");
+                // The binding lasts as long as the NHS granted, renewals refresh it. An NHS that is
+                // configured as static peer as well keeps its entry that never expires.
+                {
+                    let mut peers = self.peers.write().await;
+                    let configured = peers.get(&nhs.protocol_address)
+                        .is_some_and(|entry| matches!(entry.kind, EntryKind::Static | EntryKind::Local));
+                    if configured {
+                        return Ok(());
+                    }
+                    peers.insert(nhs.protocol_address, CacheEntry {
+                        holding_time: Duration::from_secs(holding_time.into()),
+                        ..CacheEntry::fixed(EntryKind::Dynamic, ifindex, nhs.protocol_address, nhs.nbma_address)
+                    });
+                }
+                let installed = self.kernel.replace_neighbour(ifindex, nhs.protocol_address, nhs.nbma_address,
+                    NeighbourState::Reachable).await;
+                if let Err(error) = installed {
+                    tracing::warn!(%error, nhs = %nhs.protocol_address, "installing neighbour entry failed");
                 }
+            },
+            Some(Outcome::Refused { ifindex, nhs, code, backoff }) => {
+                tracing::warn!(nhs = %nhs.protocol_address, ?code, retry_in = ?backoff, "NHS refused registration");
+                // An NHS we were registered with stays reachable, only our binding is gone
+                let mut peers = self.peers.write().await;
+                if peers.get(&nhs.protocol_address).is_none_or(|entry| entry.resolves().is_none()) {
+                    peers.insert(nhs.protocol_address,
+                        CacheEntry::unresolved(EntryKind::Negative, ifindex, nhs.protocol_address, backoff));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub async fn on_error_indication(&self, msg: NhrpMessage) -> Result<(), Error> {
+        if let Operation::ErrorIndication(ref err) = msg.operation {
+            let header = err.header();
+            tracing::warn!(
+                code = ?header.code,
+                offset = header.offset,
+                src_nbma_addr = %header.src_nbma_addr,
+                src_proto_addr = %header.src_proto_addr,
+                "peer reported an error for one of our messages"
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn on_traffic_indication(&self, msg: NhrpMessage) -> Result<(), Error> {
+        if let Operation::TrafficIndication(ref indication) = msg.operation {
+            let header = indication.header();
+            tracing::debug!(
+                code = ?header.code,
+                src_nbma_addr = %header.src_nbma_addr,
+                src_proto_addr = %header.src_proto_addr,
+                "ignoring traffic indication, shortcuts are only resolved by clients"
+            );
+        }
+        Ok(())
+    }
+
+    /// Handle NHRP messages received on `framed` and register with the configured NHSes. If a
+    /// `redirector` is given, spokes whose traffic it reports are sent Traffic Indications.
+    ///
+    /// Only failures of the socket or the redirector end the loop. A message that can't be handled
+    /// is logged and dropped.
+    pub async fn handle_messages(&self, framed: &mut Framed, mut redirector: Option<Redirector>)
+        -> Result<(), Error>
+    {
+        loop {
+            let (packet, source) = tokio::select! {
+                received = framed.next() => match received {
+                    Some(Ok(received)) => received,
+                    Some(Err(codec::Error::Parse(error))) => {
+                        tracing::warn!(error = ?miette::Report::new(error), "discarding invalid message");
+                        continue;
+                    },
+                    Some(Err(error)) => return Err(error.into()),
+                    None => return Ok(()),
+                },
+                _ = self.registration_due() => {
+                    self.send_registrations(framed).await;
+                    continue;
+                },
+                redirect = next_redirect(&mut redirector) => {
+                    if let Err(error) = self.send_traffic_indication(framed, redirect?).await {
+                        tracing::warn!(?error, "sending traffic indication failed");
+                    }
+                    continue;
+                },
+            };
+            if let Err(error) = self.handle_packet(framed, packet, &source).await {
+                tracing::warn!(?error, ifindex = source.ifindex, "handling message failed");
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Check a received packet and dispatch it to the handler for its operation. Replies are sent
+    /// back to the link-layer address the packet came from.
+    async fn handle_packet(&self, framed: &mut Framed, packet: Packet, source: &PeerAddr) -> Result<(), Error> {
+        let Packet { bytes, message: msg } = packet;
+        let policy = self.interfaces.get(&source.ifindex)
+            .map(InterfaceConfig::checksum_policy)
+            .unwrap_or_default();
+        if let Err(error) = NhrpMessageView::new(&bytes).and_then(|view| view.verify_checksum(policy)) {
+            tracing::warn!(%error, ifindex = source.ifindex, "discarding message with bad checksum");
+            return Ok(());
+        }
+        if !self.check_authentication(framed, &bytes, source).await? {
+            return Ok(());
+        }
+        if !self.check_validity(framed, &bytes, &msg, source).await? {
+            return Ok(());
+        }
+        tracing::trace!("received {:#}", msg);
+
+        let reply = match msg.operation {
+            Operation::ResolutionRequest(_) => self.on_resolution_request(msg).await?,
+            Operation::RegistrationRequest(_) => self.on_registration_request(msg, source).await?,
+            Operation::PurgeRequest(_) => self.on_purge_request(msg, source).await?,
+            Operation::RegistrationReply(_) => {
+                self.on_registration_reply(msg, source).await?;
+                None
+            },
+            Operation::ResolutionReply(_) | Operation::PurgeReply(_) => {
+                tracing::debug!(optype = ?msg.header.optype(), "ignoring reply, no such requests are sent");
+                None
+            },
+            Operation::ErrorIndication(_) => {
+                self.on_error_indication(msg).await?;
+                None
+            },
+            Operation::TrafficIndication(_) => {
+                self.on_traffic_indication(msg).await?;
+                None
+            },
+        };
+        match reply {
+            Some(reply) => self.send(framed, reply, source).await,
+            None => Ok(()),
+        }
+    }
+}
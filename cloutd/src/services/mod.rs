@@ -1,7 +0,0 @@
-
-
-pub mod registration;
-pub use self::registration::*;
-
-pub mod purge;
-pub use self::purge::*;
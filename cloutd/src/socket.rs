@@ -1,17 +1,14 @@
-use nix::sys::socket::{recvmsg, sendmsg, socket, AddressFamily, MsgFlags, RecvMsg, SockFlag, SockProtocol, SockType, SockaddrLike, recvfrom};
-use std::io::{IoSlice, IoSliceMut};
 use std::net::IpAddr;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::{io, mem};
-use std::fmt::Debug;
-use std::marker::PhantomData;
-use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::{fmt, future, io, mem};
+use std::task::{ready, Context, Poll};
 
 use thiserror::Error;
 use miette::Diagnostic;
 use nix::errno::Errno;
-use tokio::io::{AsyncRead, AsyncWrite};
+use nix::libc;
+use bytes::BytesMut;
+use nhrp::{Emitable, NhrpMessage};
 
 use tokio::io::unix::AsyncFd;
 use crate::error::{ErrnoAdvice, ErrnoErr};
@@ -53,11 +50,101 @@ impl Error {
     }
 }
 
+/// Protocol type GRE uses for NHRP
+const NHRP_PROTOCOL: u16 = 0x2001;
+
 pub struct Advice;
 impl ErrnoAdvice for Advice {
     const EPERM: Option<&'static str> = Some("Opening raw packet sockets requires root privileges");
 }
 
+/// Largest link-layer address the kernel reports for a packet, `MAX_ADDR_LEN`
+const MAX_ADDR_LEN: usize = 32;
+
+/// Offset of `sll_addr` in `struct sockaddr_ll`
+const SLL_ADDR: usize = 12;
+
+/// A peer reached through a GRE interface.
+///
+/// The NBMA address of the peer is the outer address of the GRE packets exchanged with it, which
+/// ipgre and ip6gre devices use as link-layer address of their neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerAddr {
+    /// Index of the GRE interface
+    pub ifindex: usize,
+    /// NBMA address of the peer
+    pub nbma: IpAddr,
+}
+
+impl PeerAddr {
+    pub fn new(ifindex: usize, nbma: IpAddr) -> Self {
+        Self { ifindex, nbma }
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on interface {}", self.nbma, self.ifindex)
+    }
+}
+
+/// A `struct sockaddr_ll` with room for link-layer addresses longer than the 8 octets it
+/// declares. The kernel reads and writes as much of the address as `sll_halen` says, and
+/// ip6gre devices use 16 octet IPv6 addresses.
+#[derive(Debug)]
+#[repr(C, align(4))]
+struct SockaddrLl([u8; SLL_ADDR + MAX_ADDR_LEN]);
+
+impl SockaddrLl {
+    fn empty() -> Self {
+        Self([0; SLL_ADDR + MAX_ADDR_LEN])
+    }
+
+    /// Address of `peer` and the length to pass to the kernel for it
+    fn new(peer: &PeerAddr) -> (Self, libc::socklen_t) {
+        let mut addr = Self::empty();
+        let b = &mut addr.0;
+        b[0..2].copy_from_slice(&(libc::AF_PACKET as u16).to_ne_bytes());
+        b[2..4].copy_from_slice(&NHRP_PROTOCOL.to_be_bytes());
+        b[4..8].copy_from_slice(&(peer.ifindex as i32).to_ne_bytes());
+        let halen = match peer.nbma {
+            IpAddr::V4(nbma) => {
+                b[SLL_ADDR..SLL_ADDR + 4].copy_from_slice(&nbma.octets());
+                4
+            }
+            IpAddr::V6(nbma) => {
+                b[SLL_ADDR..SLL_ADDR + 16].copy_from_slice(&nbma.octets());
+                16
+            }
+        };
+        b[11] = halen as u8;
+        // The kernel rejects anything shorter than a plain sockaddr_ll
+        let len = (SLL_ADDR + halen).max(mem::size_of::<libc::sockaddr_ll>());
+        (addr, len as libc::socklen_t)
+    }
+
+    /// The peer this address was filled in for, if it is one of a GRE interface
+    fn peer(&self, len: libc::socklen_t) -> Option<PeerAddr> {
+        let b = &self.0;
+        let len = len as usize;
+        if len < SLL_ADDR || u16::from_ne_bytes([b[0], b[1]]) != libc::AF_PACKET as u16 {
+            return None;
+        }
+        let ifindex = i32::from_ne_bytes([b[4], b[5], b[6], b[7]]) as usize;
+        let halen = b[11] as usize;
+        if len < SLL_ADDR + halen {
+            return None;
+        }
+        let addr = &b[SLL_ADDR..SLL_ADDR + halen];
+        let nbma = match halen {
+            4 => IpAddr::V4(<[u8; 4]>::try_from(addr).unwrap().into()),
+            16 => IpAddr::V6(<[u8; 16]>::try_from(addr).unwrap().into()),
+            _ => return None,
+        };
+        Some(PeerAddr { ifindex, nbma })
+    }
+}
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct NhrpSocket {
@@ -66,55 +153,69 @@ pub struct NhrpSocket {
 
 impl NhrpSocket {
     pub fn new() -> Result<Self, Error> {
-        let protocol: SockProtocol = unsafe { mem::transmute(0x2001i32.to_be()) };
-        let socket = socket(
-            AddressFamily::Packet,
-            SockType::Datagram,
-            SockFlag::SOCK_NONBLOCK,
-            protocol,
-        ).map_err(|errno| Error::socket(ErrnoErr::with::<Advice>(errno).into()))?;
+        // The protocol is an ethertype, which packet sockets take in network byte order
+        let socket = unsafe {
+            libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                NHRP_PROTOCOL.to_be() as libc::c_int)
+        };
+        if socket < 0 {
+            return Err(Error::socket(ErrnoErr::with::<Advice>(Errno::last())));
+        }
 
         Ok(Self {
             io: AsyncFd::new(RawNhrpSocket { socket }).map_err(Error::AsyncFd)?,
         })
     }
 
-    pub async fn send_vectored(
-        &self,
-        bufs: &[IoSlice<'_>],
-        addr: &impl SockaddrLike,
-    ) -> Result<usize, Error> {
-        loop {
-            let mut guard = self.io.writable().await.map_err(Error::Readiness)?;
+    /// Send `msg` to the peer with NBMA address `nbma` through the GRE interface `ifindex`
+    #[allow(dead_code)] // The daemon sends through NhrpFramed
+    pub async fn send_to(&self, msg: &NhrpMessage, ifindex: usize, nbma: IpAddr) -> Result<usize, Error> {
+        let mut buf = BytesMut::with_capacity(msg.buffer_len());
+        msg.emit_to(&mut buf);
+        let peer = PeerAddr::new(ifindex, nbma);
+        future::poll_fn(|cx| self.poll_send_to(cx, &buf, &peer)).await
+    }
 
-            match guard.try_io(|asyncfd| asyncfd.get_ref().send_vectored(bufs, addr)) {
-                Err(_would_block) => continue,
-                Ok(result) => return result.map_err(Error::Send),
-            }
-        }
+    /// Receive a packet into `buf`, returning its length and the peer it was received from.
+    ///
+    /// Packets that did not arrive on an ipgre or ip6gre interface are skipped.
+    #[allow(dead_code)] // The daemon receives through NhrpFramed
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, PeerAddr), Error> {
+        future::poll_fn(|cx| self.poll_recv_from(cx, buf)).await
     }
 
-    pub async fn recv_vectored<'a, S: SockaddrLike + 'a>(
-        &self,
-        bufs: &mut [IoSliceMut<'_>],
-    ) -> Result<RecvMsg<'a, S>, Error> {
+    /// Poll to send `buf` to `peer`, for use in manually implemented sinks such as
+    /// [`NhrpFramed`](crate::framed::NhrpFramed)
+    pub fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], peer: &PeerAddr)
+        -> Poll<Result<usize, Error>>
+    {
+        let addr = SockaddrLl::new(peer);
         loop {
-            let mut guard = self.io.readable().await.map_err(Error::Readiness)?;
+            let mut guard = ready!(self.io.poll_write_ready(cx)).map_err(Error::Readiness)?;
 
-            match guard.try_io(|asyncfd| asyncfd.get_ref().recv_vectored(bufs)) {
+            match guard.try_io(|asyncfd| asyncfd.get_ref().send_to(buf, &addr)) {
                 Err(_would_block) => continue,
-                Ok(result) => return result.map_err(Error::Recv),
+                Ok(result) => return Poll::Ready(result.map_err(Error::Send)),
             }
         }
     }
 
-    pub async fn recv<S: SockaddrLike>(&self, buf: &mut [u8]) -> Result<(usize, Option<S>), Error> {
+    /// Poll to receive a packet into `buf`, for use in manually implemented streams.
+    ///
+    /// Packets that did not arrive on an ipgre or ip6gre interface are skipped.
+    pub fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<Result<(usize, PeerAddr), Error>>
+    {
         loop {
-            let mut guard = self.io.readable().await.map_err(Error::Readiness)?;
+            let mut guard = ready!(self.io.poll_read_ready(cx)).map_err(Error::Readiness)?;
 
-            match guard.try_io(|asyncfd| asyncfd.get_ref().recv(buf)) {
+            match guard.try_io(|asyncfd| asyncfd.get_ref().recv_from(buf)) {
                 Err(_would_block) => continue,
-                Ok(result) => return result.map_err(Error::Recv),
+                Ok(Err(error)) => return Poll::Ready(Err(Error::Recv(error))),
+                Ok(Ok((len, addr, addr_len))) => match addr.peer(addr_len) {
+                    Some(peer) => return Poll::Ready(Ok((len, peer))),
+                    None => tracing::debug!(len, "skipping packet not received from a GRE peer"),
+                },
             }
         }
     }
@@ -128,26 +229,34 @@ struct RawNhrpSocket {
 }
 
 impl RawNhrpSocket {
-    pub fn send_vectored(
-        &self,
-        bufs: &[IoSlice<'_>],
-        addr: &impl SockaddrLike,
-    ) -> io::Result<usize> {
-        sendmsg(self.as_raw_fd(), bufs, &[], MsgFlags::empty(), Some(addr))
-            .map_err(|errno| io::Error::from_raw_os_error(errno as i32))
+    fn send_to(&self, buf: &[u8], (addr, len): &(SockaddrLl, libc::socklen_t)) -> io::Result<usize> {
+        let sent = unsafe {
+            libc::sendto(self.socket, buf.as_ptr().cast(), buf.len(), 0,
+                addr.0.as_ptr().cast(), *len)
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sent as usize)
     }
 
-    pub fn recv_vectored<'a, 'outer, 'inner, S: SockaddrLike + 'a>(
-        &self,
-        bufs: &'outer mut [IoSliceMut<'inner>],
-    ) -> io::Result<RecvMsg<'a, S>> {
-        recvmsg(self.as_raw_fd(), bufs, None, MsgFlags::empty())
-            .map_err(|errno| io::Error::from_raw_os_error(errno as i32))
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SockaddrLl, libc::socklen_t)> {
+        let mut addr = SockaddrLl::empty();
+        let mut len = addr.0.len() as libc::socklen_t;
+        let received = unsafe {
+            libc::recvfrom(self.socket, buf.as_mut_ptr().cast(), buf.len(), 0,
+                addr.0.as_mut_ptr().cast(), &mut len)
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((received as usize, addr, len))
     }
+}
 
-    pub fn recv<S: SockaddrLike>(&self, buf: &mut [u8]) -> io::Result<(usize, Option<S>)> {
-        recvfrom(self.as_raw_fd(), buf)
-            .map_err(|errno| io::Error::from_raw_os_error(errno as i32))
+impl Drop for RawNhrpSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.socket) };
     }
 }
 
@@ -156,3 +265,132 @@ impl AsRawFd for RawNhrpSocket {
         self.socket
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
+
+    use nhrp::PurgeMessage;
+
+    use super::*;
+
+    /// A TUN device, removed again once dropped.
+    ///
+    /// TUN devices have no link-layer addresses: what is sent to one can be read back from it,
+    /// but what is written to it does not come from a GRE peer.
+    struct Tun {
+        fd: RawFd,
+        ifindex: usize,
+    }
+
+    impl Tun {
+        fn open() -> io::Result<Self> {
+            let fd = unsafe { libc::open(c"/dev/net/tun".as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut tun = Tun { fd, ifindex: 0 };
+
+            // struct ifreq: the name, then the flags
+            let mut ifr = [0u8; 40];
+            ifr[..10].copy_from_slice(b"nhrptest%d");
+            ifr[16..18].copy_from_slice(&(libc::IFF_TUN as u16).to_ne_bytes());
+            if unsafe { libc::ioctl(fd, libc::TUNSETIFF, ifr.as_mut_ptr()) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+            if sock < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            ifr[16..18].copy_from_slice(&(libc::IFF_UP as u16).to_ne_bytes());
+            let up = unsafe { libc::ioctl(sock, libc::SIOCSIFFLAGS, ifr.as_mut_ptr()) };
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(sock) };
+            if up < 0 {
+                return Err(error);
+            }
+            tun.ifindex = unsafe { libc::if_nametoindex(ifr.as_ptr().cast()) } as usize;
+            Ok(tun)
+        }
+    }
+
+    impl Drop for Tun {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_to_peers_and_skips_other_sources() {
+        // Packet sockets and TUN devices need CAP_NET_ADMIN and CAP_NET_RAW
+        let (socket, tun) = match (NhrpSocket::new(), Tun::open()) {
+            (Ok(socket), Ok(tun)) => (socket, tun),
+            (socket, tun) => {
+                eprintln!("skipping, no packet socket or TUN device: {:?}, {:?}", socket.err(), tun.err());
+                return;
+            },
+        };
+
+        let hub = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let spoke = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let purge = PurgeMessage::builder(Ipv4Addr::new(198, 51, 100, 5), spoke, hub).build().unwrap();
+        let msg = NhrpMessage::builder(nhrp::Operation::PurgeRequest(purge)).build().unwrap();
+        let mut packet = vec![0; msg.buffer_len()];
+        msg.emit(&mut packet);
+
+        let nbma = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 4));
+        assert_eq!(socket.send_to(&msg, tun.ifindex, nbma).await.unwrap(), packet.len());
+        // Read back behind the packet information TUN devices prefix: flags and protocol
+        let mut buf = [0; 2048];
+        let len = unsafe { libc::read(tun.fd, buf.as_mut_ptr().cast(), buf.len()) };
+        assert_eq!(len as usize, 4 + packet.len());
+        assert_eq!(&buf[2..4], &NHRP_PROTOCOL.to_be_bytes());
+        assert_eq!(&buf[4..len as usize], &packet[..]);
+
+        // The same packet coming in on the TUN device has no GRE peer as source
+        let written = unsafe { libc::write(tun.fd, buf.as_ptr().cast(), len as usize) };
+        assert_eq!(written, len);
+        let received = tokio::time::timeout(Duration::from_millis(200), socket.recv_from(&mut buf)).await;
+        assert!(received.is_err(), "packet from no GRE peer was received: {:?}", received);
+    }
+
+    #[test]
+    fn fills_in_ipgre_peer() {
+        let peer = PeerAddr::new(7, IpAddr::V4(Ipv4Addr::new(198, 51, 100, 5)));
+        let (addr, len) = SockaddrLl::new(&peer);
+        // Padded to a plain sockaddr_ll, which has room for the 4 octet address
+        assert_eq!(len as usize, mem::size_of::<libc::sockaddr_ll>());
+        assert_eq!(&addr.0[2..4], &[0x20, 0x01]);
+        assert_eq!(addr.0[11], 4);
+        assert_eq!(&addr.0[SLL_ADDR..SLL_ADDR + 4], &[198, 51, 100, 5]);
+        assert_eq!(addr.peer(len), Some(peer));
+    }
+
+    #[test]
+    fn fills_in_ip6gre_peer() {
+        let peer = PeerAddr::new(8, IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 5)));
+        let (addr, len) = SockaddrLl::new(&peer);
+        // The 16 octet address overruns the 8 octets sockaddr_ll declares for it
+        assert_eq!(len as usize, SLL_ADDR + 16);
+        assert!(len as usize > mem::size_of::<libc::sockaddr_ll>());
+        assert_eq!(addr.0[11], 16);
+        assert_eq!(addr.peer(len), Some(peer));
+    }
+
+    #[test]
+    fn skips_addresses_of_other_devices() {
+        let peer = PeerAddr::new(8, IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 5)));
+        let (mut addr, len) = SockaddrLl::new(&peer);
+        // Truncated by the kernel
+        assert_eq!(addr.peer(len - 1), None);
+        assert_eq!(addr.peer(SLL_ADDR as libc::socklen_t - 1), None);
+
+        // A 6 octet Ethernet address
+        addr.0[11] = 6;
+        assert_eq!(addr.peer(len), None);
+        addr.0[11] = 16;
+        addr.0[0..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+        assert_eq!(addr.peer(len), None);
+    }
+}
@@ -0,0 +1,9 @@
+//! Helpers shared by the unit tests
+
+/// Octets of a string of hex digits
+pub fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,222 @@
+//! Reading packets from pcap and pcapng files
+//!
+//! Only what is needed to get at the packets is decoded: link types, timestamps and the packet
+//! data. Everything else, e.g. comments or name resolution blocks, is skipped.
+
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("not a pcap or pcapng file")]
+    UnknownFormat,
+    #[error("capture is truncated at offset {0}")]
+    Truncated(usize),
+    #[error("pcapng block at offset {0} is not part of a section")]
+    NoSection(usize),
+    #[error("packet at offset {offset} refers to undeclared interface {interface}")]
+    UnknownInterface { offset: usize, interface: u32 },
+}
+
+/// A packet as it was captured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Link-layer header type of the interface the packet was captured on
+    pub linktype: u16,
+    /// Time of capture since the Unix epoch, if the capture records one
+    pub timestamp: Option<Duration>,
+    pub data: &'a [u8],
+}
+
+const PCAP_MAGIC_USEC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NSEC: u32 = 0xa1b23c4d;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_PACKET_HEADER_LEN: usize = 20;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+/// Read all packets of a pcap or pcapng file
+pub fn read(data: &[u8]) -> Result<Vec<Record<'_>>, Error> {
+    let magic: [u8; 4] = data.get(0..4).ok_or(Error::UnknownFormat)?.try_into().unwrap();
+    if u32::from_be_bytes(magic) == PCAPNG_SECTION_HEADER {
+        return read_pcapng(data);
+    }
+    let endian = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (PCAP_MAGIC_USEC | PCAP_MAGIC_NSEC, _) => Endian::Little,
+        (_, PCAP_MAGIC_USEC | PCAP_MAGIC_NSEC) => Endian::Big,
+        _ => return Err(Error::UnknownFormat),
+    };
+    read_pcap(data, endian)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], offset: usize) -> Result<u16, Error> {
+        let bytes = data.get(offset..offset + 2).ok_or(Error::Truncated(offset))?;
+        let bytes = bytes.try_into().unwrap();
+        Ok(match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Result<u32, Error> {
+        let bytes = data.get(offset..offset + 4).ok_or(Error::Truncated(offset))?;
+        let bytes = bytes.try_into().unwrap();
+        Ok(match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset.checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::Truncated(offset))
+}
+
+fn read_pcap(data: &[u8], endian: Endian) -> Result<Vec<Record<'_>>, Error> {
+    let nanos = endian.u32(data, 0)? == PCAP_MAGIC_NSEC;
+    // The upper bits carry FCS information we have no use for
+    let linktype = endian.u32(data, 20)? as u16;
+
+    let mut records = Vec::new();
+    let mut offset = PCAP_HEADER_LEN;
+    while offset < data.len() {
+        let seconds = endian.u32(data, offset)?;
+        let fraction = endian.u32(data, offset + 4)?;
+        let caplen = endian.u32(data, offset + 8)? as usize;
+        let subsec = if nanos { fraction } else { fraction.saturating_mul(1000) };
+
+        offset += PCAP_RECORD_HEADER_LEN;
+        records.push(Record {
+            linktype,
+            timestamp: Some(Duration::new(seconds as u64, 0) + Duration::from_nanos(subsec as u64)),
+            data: slice(data, offset, caplen)?,
+        });
+        offset += caplen;
+    }
+    Ok(records)
+}
+
+/// An interface of the current pcapng section
+struct Interface {
+    linktype: u16,
+    /// Timestamp units per second
+    resolution: u64,
+}
+
+impl Interface {
+    fn timestamp(&self, high: u32, low: u32) -> Duration {
+        let ticks = ((high as u64) << 32) | low as u64;
+        let seconds = ticks / self.resolution;
+        let nanos = (ticks % self.resolution) as u128 * 1_000_000_000 / self.resolution as u128;
+        Duration::new(seconds, nanos as u32)
+    }
+}
+
+/// Decode the `if_tsresol` option: powers of ten, or of two if the top bit is set
+fn resolution(value: u8) -> u64 {
+    let exponent = (value & 0x7f) as u32;
+    let resolution = if value & 0x80 == 0 {
+        10u64.checked_pow(exponent)
+    } else {
+        2u64.checked_pow(exponent)
+    };
+    resolution.unwrap_or(u64::MAX)
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Record<'_>>, Error> {
+    let mut records = Vec::new();
+    let mut endian = None;
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        if Endian::Big.u32(data, offset)? == PCAPNG_SECTION_HEADER {
+            // The byte order of a section is only known after reading its header
+            endian = match Endian::Big.u32(data, offset + 8)? {
+                PCAPNG_BYTE_ORDER_MAGIC => Some(Endian::Big),
+                _ if Endian::Little.u32(data, offset + 8)? == PCAPNG_BYTE_ORDER_MAGIC => Some(Endian::Little),
+                _ => return Err(Error::UnknownFormat),
+            };
+            interfaces.clear();
+        }
+        let endian = endian.ok_or(Error::NoSection(offset))?;
+
+        let btype = endian.u32(data, offset)?;
+        let blen = endian.u32(data, offset + 4)? as usize;
+        if blen < 12 || !blen.is_multiple_of(4) {
+            return Err(Error::Truncated(offset));
+        }
+        let body = slice(data, offset + 8, blen - 12)?;
+
+        match btype {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let linktype = endian.u16(body, 0)?;
+                let mut resolution_value = 6;
+                let mut option = 8;
+                while option + 4 <= body.len() {
+                    let code = endian.u16(body, option)?;
+                    let len = endian.u16(body, option + 2)? as usize;
+                    if code == PCAPNG_OPT_END {
+                        break;
+                    }
+                    if code == PCAPNG_OPT_IF_TSRESOL && len == 1 {
+                        resolution_value = slice(body, option + 4, 1)?[0];
+                    }
+                    option += 4 + len.div_ceil(4) * 4;
+                }
+                interfaces.push(Interface { linktype, resolution: resolution(resolution_value) });
+            },
+            PCAPNG_ENHANCED_PACKET | PCAPNG_PACKET => {
+                // The obsolete Packet Block has a 16 bit interface ID followed by a drop count
+                let interface = if btype == PCAPNG_ENHANCED_PACKET {
+                    endian.u32(body, 0)?
+                } else {
+                    endian.u16(body, 0)? as u32
+                };
+                let iface = interfaces.get(interface as usize)
+                    .ok_or(Error::UnknownInterface { offset, interface })?;
+                let high = endian.u32(body, 4)?;
+                let low = endian.u32(body, 8)?;
+                let caplen = endian.u32(body, 12)? as usize;
+                records.push(Record {
+                    linktype: iface.linktype,
+                    timestamp: Some(iface.timestamp(high, low)),
+                    data: slice(body, PCAPNG_PACKET_HEADER_LEN, caplen)?,
+                });
+            },
+            PCAPNG_SIMPLE_PACKET => {
+                let iface = interfaces.first()
+                    .ok_or(Error::UnknownInterface { offset, interface: 0 })?;
+                let len = endian.u32(body, 0)? as usize;
+                // Without a captured length the packet is whatever fits into the block
+                let caplen = len.min(body.len() - 4);
+                records.push(Record {
+                    linktype: iface.linktype,
+                    timestamp: None,
+                    data: slice(body, 4, caplen)?,
+                });
+            },
+            _ => {},
+        }
+
+        offset += blen;
+    }
+    Ok(records)
+}
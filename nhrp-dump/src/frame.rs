@@ -0,0 +1,154 @@
+//! Finding NHRP packets in captured frames
+//!
+//! NHRP is carried in GRE with protocol type 0x2001. Captures taken on the underlying network
+//! show it inside IPv4 or IPv6, captures taken on a GRE interface show it with only a Linux
+//! cooked header in front.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const LINKTYPE_ETHERNET: u16 = 1;
+pub const LINKTYPE_RAW: u16 = 101;
+pub const LINKTYPE_LINUX_SLL: u16 = 113;
+pub const LINKTYPE_IPV4: u16 = 228;
+pub const LINKTYPE_IPV6: u16 = 229;
+pub const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const ETHERTYPE_NHRP: u16 = 0x2001;
+
+const IPPROTO_GRE: u8 = 47;
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+const GRE_CHECKSUM: u16 = 0x8000;
+const GRE_ROUTING: u16 = 0x4000;
+const GRE_KEY: u16 = 0x2000;
+const GRE_SEQUENCE: u16 = 0x1000;
+const GRE_VERSION: u16 = 0x0007;
+
+const SLL_HEADER_LEN: usize = 16;
+const SLL2_HEADER_LEN: usize = 20;
+const SLL_ADDR_MAX: usize = 8;
+
+/// An NHRP packet and the NBMA addresses it was exchanged between
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// Sender of the packet, if the encapsulation shows it
+    pub nbma_src: Option<IpAddr>,
+    /// Receiver of the packet, if the encapsulation shows it
+    pub nbma_dst: Option<IpAddr>,
+    pub nhrp: &'a [u8],
+}
+
+/// Extract the NHRP packet from a frame with the given link-layer header type. Returns `None`
+/// for frames that don't carry NHRP or are encapsulated in a way we don't understand.
+pub fn decapsulate(linktype: u16, data: &[u8]) -> Option<Frame<'_>> {
+    match linktype {
+        LINKTYPE_ETHERNET => ethernet(data),
+        LINKTYPE_RAW => match data.first()? >> 4 {
+            4 => ipv4(data),
+            6 => ipv6(data),
+            _ => None,
+        },
+        LINKTYPE_IPV4 => ipv4(data),
+        LINKTYPE_IPV6 => ipv6(data),
+        LINKTYPE_LINUX_SLL => {
+            let addr_len = u16::from_be_bytes(data.get(4..6)?.try_into().unwrap()) as usize;
+            let addr = data.get(6..(6 + addr_len.min(SLL_ADDR_MAX)))?;
+            let protocol = u16::from_be_bytes(data.get(14..16)?.try_into().unwrap());
+            cooked(protocol, addr, data.get(SLL_HEADER_LEN..)?)
+        },
+        LINKTYPE_LINUX_SLL2 => {
+            let protocol = u16::from_be_bytes(data.get(0..2)?.try_into().unwrap());
+            let addr_len = *data.get(11)? as usize;
+            let addr = data.get(12..(12 + addr_len.min(SLL_ADDR_MAX)))?;
+            cooked(protocol, addr, data.get(SLL2_HEADER_LEN..)?)
+        },
+        _ => None,
+    }
+}
+
+/// Payload of a Linux cooked capture. On GRE interfaces the link-layer address is the IP
+/// address of the peer the packet was received from or sent to.
+fn cooked<'a>(protocol: u16, addr: &[u8], payload: &'a [u8]) -> Option<Frame<'a>> {
+    match protocol {
+        ETHERTYPE_NHRP => {
+            let peer = match addr.len() {
+                4 => Some(IpAddr::from(<[u8; 4]>::try_from(addr).unwrap())),
+                _ => None,
+            };
+            Some(Frame { nbma_src: peer, nbma_dst: None, nhrp: payload })
+        },
+        ETHERTYPE_IPV4 => ipv4(payload),
+        ETHERTYPE_IPV6 => ipv6(payload),
+        _ => None,
+    }
+}
+
+fn ethernet(data: &[u8]) -> Option<Frame<'_>> {
+    let mut offset = 12;
+    let mut ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().unwrap());
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        offset += 4;
+        ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().unwrap());
+    }
+    let payload = data.get(offset + 2..)?;
+    match ethertype {
+        ETHERTYPE_IPV4 => ipv4(payload),
+        ETHERTYPE_IPV6 => ipv6(payload),
+        _ => None,
+    }
+}
+
+fn ipv4(data: &[u8]) -> Option<Frame<'_>> {
+    let header_len = (*data.first()? & 0x0f) as usize * 4;
+    let total_len = u16::from_be_bytes(data.get(2..4)?.try_into().unwrap()) as usize;
+    let fragment = u16::from_be_bytes(data.get(6..8)?.try_into().unwrap());
+    // Fragments can't be decoded on their own
+    if fragment & 0x3fff != 0 || *data.get(9)? != IPPROTO_GRE {
+        return None;
+    }
+    let src: [u8; 4] = data.get(12..16)?.try_into().unwrap();
+    let dst: [u8; 4] = data.get(16..20)?.try_into().unwrap();
+    // Ethernet pads short frames, only the IP length tells where the packet ends
+    let payload = data.get(header_len..total_len.min(data.len()))?;
+    gre(payload, Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into())
+}
+
+fn ipv6(data: &[u8]) -> Option<Frame<'_>> {
+    let payload_len = u16::from_be_bytes(data.get(4..6)?.try_into().unwrap()) as usize;
+    let src: [u8; 16] = data.get(8..24)?.try_into().unwrap();
+    let dst: [u8; 16] = data.get(24..40)?.try_into().unwrap();
+    let end = (40 + payload_len).min(data.len());
+
+    let mut next = *data.get(6)?;
+    let mut offset = 40;
+    while matches!(next, IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS) {
+        next = *data.get(offset)?;
+        offset += (*data.get(offset + 1)? as usize + 1) * 8;
+    }
+    if next != IPPROTO_GRE {
+        return None;
+    }
+    gre(data.get(offset..end)?, Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into())
+}
+
+fn gre(data: &[u8], src: IpAddr, dst: IpAddr) -> Option<Frame<'_>> {
+    let flags = u16::from_be_bytes(data.get(0..2)?.try_into().unwrap());
+    let protocol = u16::from_be_bytes(data.get(2..4)?.try_into().unwrap());
+    if flags & (GRE_ROUTING | GRE_VERSION) != 0 || protocol != ETHERTYPE_NHRP {
+        return None;
+    }
+    let optional = [GRE_CHECKSUM, GRE_KEY, GRE_SEQUENCE].iter()
+        .filter(|&&flag| flags & flag != 0)
+        .count();
+    Some(Frame {
+        nbma_src: Some(src),
+        nbma_dst: Some(dst),
+        nhrp: data.get(4 + optional * 4..)?,
+    })
+}
@@ -0,0 +1,25 @@
+//! Machine readable output, one JSON object per packet
+//!
+//! Messages use the serde representation of the `nhrp` crate, so the output can be read back
+//! into an `NhrpMessage` and emitted again.
+
+use serde_json::{json, Value};
+
+use crate::{timestamp, Packet};
+
+/// Render a packet as a JSON object
+pub fn render(packet: &Packet<'_>) -> Value {
+    let mut value = json!({
+        "frame": packet.number,
+        "timestamp": packet.timestamp.map(timestamp),
+        "nbma_src": packet.frame.nbma_src.map(|a| a.to_string()),
+        "nbma_dst": packet.frame.nbma_dst.map(|a| a.to_string()),
+        "length": packet.bytes().len(),
+        "checksum_valid": packet.checksum.is_ok(),
+    });
+    match packet.message {
+        Ok(ref msg) => value["message"] = serde_json::to_value(msg).expect("messages serialize to JSON"),
+        Err(ref e) => value["error"] = e.to_string().into(),
+    }
+    value
+}
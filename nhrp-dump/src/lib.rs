@@ -0,0 +1,69 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Decoding the NHRP messages contained in packet captures
+
+use std::time::Duration;
+
+use nhrp::{ChecksumPolicy, NhrpBuffer, NhrpMessage};
+
+pub mod capture;
+pub mod frame;
+pub mod json;
+pub mod tree;
+
+pub use self::capture::Record;
+pub use self::frame::Frame;
+
+/// An NHRP packet found in a capture
+#[derive(Debug)]
+pub struct Packet<'a> {
+    /// Number of the frame in the capture, counting from one like other tools do
+    pub number: usize,
+    pub timestamp: Option<Duration>,
+    pub frame: Frame<'a>,
+    /// Whether the checksum of the packet is correct. Parsing doesn't check it so that packets
+    /// with a wrong checksum can be looked at all the same.
+    pub checksum: nhrp::Result<()>,
+    pub message: nhrp::Result<NhrpMessage>,
+}
+
+impl<'a> Packet<'a> {
+    pub fn decode(number: usize, record: &Record<'a>) -> Option<Packet<'a>> {
+        let frame = frame::decapsulate(record.linktype, record.data)?;
+        let checksum = NhrpBuffer::new_checked(frame.nhrp)
+            .and_then(|buffer| buffer.verify_checksum(ChecksumPolicy::Verify));
+        let message = NhrpMessage::from_bytes(frame.nhrp);
+        Some(Packet {
+            number,
+            timestamp: record.timestamp,
+            frame,
+            checksum,
+            message,
+        })
+    }
+
+    /// The octets of the NHRP packet, without any trailing padding of the frame
+    pub fn bytes(&self) -> &'a [u8] {
+        match NhrpBuffer::new_checked(self.frame.nhrp) {
+            Ok(buffer) => &self.frame.nhrp[..buffer.length() as usize],
+            Err(_) => self.frame.nhrp,
+        }
+    }
+}
+
+/// Render a capture timestamp as seconds since the Unix epoch with microsecond precision
+pub fn timestamp(ts: Duration) -> String {
+    format!("{}.{:06}", ts.as_secs(), ts.subsec_micros())
+}
+
+/// All NHRP packets in `records`. Frames that don't carry NHRP are skipped but still counted.
+pub fn packets<'a>(records: &[Record<'a>]) -> Vec<Packet<'a>> {
+    records.iter()
+        .enumerate()
+        .filter_map(|(index, record)| Packet::decode(index + 1, record))
+        .collect()
+}
@@ -0,0 +1,72 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use nhrp_dump::{capture, json, packets, tree};
+
+const USAGE: &str = "usage: nhrp-dump [--json] <capture>...
+
+Print the NHRP messages in pcap or pcapng captures. Frames may be Ethernet, raw IP or Linux
+cooked captures, carrying NHRP in GRE or directly on a GRE interface.
+
+    --json    print one JSON object per message instead of a tree";
+
+fn dump(path: &PathBuf, as_json: bool, out: &mut impl Write) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let records = capture::read(&data).map_err(|e| e.to_string())?;
+
+    for packet in packets(&records) {
+        let written = if as_json {
+            let mut value = json::render(&packet);
+            value["file"] = path.display().to_string().into();
+            writeln!(out, "{}", value)
+        } else {
+            write!(out, "{}", tree::render(&packet))
+        };
+        written.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut as_json = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("--json") => as_json = true,
+            Some("-h" | "--help") => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            },
+            Some(flag) if flag.starts_with('-') => {
+                eprintln!("unknown option {}\n\n{}", flag, USAGE);
+                return ExitCode::from(2);
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut status = ExitCode::SUCCESS;
+    for path in paths.iter() {
+        if !as_json && paths.len() > 1 {
+            let _ = writeln!(out, "{}:", path.display());
+        }
+        if let Err(e) = dump(path, as_json, &mut out) {
+            eprintln!("nhrp-dump: {}: {}", path.display(), e);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
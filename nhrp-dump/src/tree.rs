@@ -0,0 +1,36 @@
+//! Human readable output, one indented tree per packet
+
+use std::fmt::Write;
+
+use crate::{timestamp, Packet};
+
+const INDENT: &str = "    ";
+
+/// Render a packet as a tree
+pub fn render(packet: &Packet<'_>) -> String {
+    let mut summary = format!("#{}", packet.number);
+    if let Some(ts) = packet.timestamp {
+        write!(summary, " {}", timestamp(ts)).unwrap();
+    }
+    match (packet.frame.nbma_src, packet.frame.nbma_dst) {
+        (Some(src), Some(dst)) => write!(summary, " {} > {}", src, dst).unwrap(),
+        (Some(src), None) => write!(summary, " {}", src).unwrap(),
+        _ => {},
+    }
+    write!(summary, ", {} octets", packet.bytes().len()).unwrap();
+    match packet.checksum {
+        Ok(()) => summary.push_str(", checksum correct"),
+        Err(ref e) => write!(summary, ", {}", e).unwrap(),
+    }
+    let body = match packet.message {
+        Ok(ref msg) => msg.to_string(),
+        Err(ref e) => format!("error: {}", e),
+    };
+
+    let mut out = summary;
+    for line in body.lines() {
+        write!(out, "\n{}{}", INDENT, line).unwrap();
+    }
+    out.push('\n');
+    out
+}
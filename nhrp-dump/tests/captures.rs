@@ -0,0 +1,155 @@
+//! The captures shipped with the repository as regression fixtures for the parser
+
+use std::net::IpAddr;
+
+use nhrp::{Emitable, NhrpMessage, NhrpOp};
+use nhrp_dump::{capture, frame, json, packets, tree, Record};
+
+fn read(name: &str) -> Vec<u8> {
+    let path = format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e))
+}
+
+fn ip(s: &str) -> Option<IpAddr> {
+    Some(s.parse().unwrap())
+}
+
+#[test]
+fn parses_captured_messages() {
+    let data = read("NHRP.pcapng");
+    let records = capture::read(&data).unwrap();
+    let packets = packets(&records);
+
+    let ops: Vec<NhrpOp> = packets.iter()
+        .map(|p| p.message.as_ref().unwrap().header.optype())
+        .collect();
+    assert_eq!(ops, [NhrpOp::RegistrationRequest, NhrpOp::RegistrationReply,
+        NhrpOp::PurgeRequest, NhrpOp::PurgeReply]);
+
+    for packet in packets.iter() {
+        assert!(packet.checksum.is_ok(), "frame {}: {:?}", packet.number, packet.checksum);
+        let msg = packet.message.as_ref().unwrap();
+        let mut emitted = vec![0; msg.buffer_len()];
+        msg.emit(&mut emitted);
+        assert_eq!(emitted, packet.bytes(), "frame {} does not round trip", packet.number);
+    }
+
+    // Requests go from the spoke to the hub, replies back
+    assert_eq!(packets[0].frame.nbma_src, ip("198.51.100.5"));
+    assert_eq!(packets[0].frame.nbma_dst, ip("198.51.100.4"));
+    assert_eq!(packets[1].frame.nbma_src, ip("198.51.100.4"));
+}
+
+#[test]
+fn renders_tree() {
+    let data = read("NHRP.pcapng");
+    let records = capture::read(&data).unwrap();
+    let rendered: String = packets(&records).iter().map(tree::render).collect();
+    assert_eq!(rendered, include_str!("NHRP.txt"));
+}
+
+#[test]
+fn renders_json() {
+    let data = read("NHRP.pcapng");
+    let records = capture::read(&data).unwrap();
+    let packets = packets(&records);
+
+    let request = json::render(&packets[0]);
+    let registration = &request["message"]["operation"]["registration_request"];
+    assert_eq!(registration["flags"]["unique"], true);
+    assert_eq!(registration["header"]["src_nbma_addr"], "198.51.100.5");
+    let nat = &request["message"]["extensions"][3]["nat_address"];
+    assert_eq!(nat["cies"][0]["client_proto_addr"], "10.0.0.1");
+
+    // The output can be turned back into the packet
+    let msg: NhrpMessage = serde_json::from_value(request["message"].clone()).unwrap();
+    let mut emitted = vec![0; msg.buffer_len()];
+    msg.emit(&mut emitted);
+    assert_eq!(emitted, packets[0].bytes());
+
+    let reply = json::render(&packets[1]);
+    assert_eq!(reply["message"]["operation"]["registration_reply"]["cie"][0]["code"], "success");
+    assert_eq!(reply["checksum_valid"], true);
+}
+
+#[test]
+fn skips_captures_without_nhrp() {
+    // Both hold rtnetlink messages the daemon exchanged with the kernel
+    for name in ["Probes.pcapng", "arpcache.pcapng"] {
+        let data = read(name);
+        let records = capture::read(&data).unwrap();
+        assert!(!records.is_empty(), "{} has no packets", name);
+        assert!(packets(&records).is_empty(), "{} has NHRP packets", name);
+    }
+}
+
+/// Write `records` as a classic pcap file, all with the same link type
+fn pcap(records: &[Record<'_>], linktype: u16, big_endian: bool) -> Vec<u8> {
+    let u16b = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+    let u32b = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&u32b(0xa1b2c3d4));
+    out.extend_from_slice(&u16b(2));
+    out.extend_from_slice(&u16b(4));
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&u32b(65535));
+    out.extend_from_slice(&u32b(linktype as u32));
+    for record in records {
+        let ts = record.timestamp.unwrap();
+        out.extend_from_slice(&u32b(ts.as_secs() as u32));
+        out.extend_from_slice(&u32b(ts.subsec_micros()));
+        out.extend_from_slice(&u32b(record.data.len() as u32));
+        out.extend_from_slice(&u32b(record.data.len() as u32));
+        out.extend_from_slice(record.data);
+    }
+    out
+}
+
+#[test]
+fn reads_classic_pcap() {
+    let data = read("NHRP.pcapng");
+    let records = capture::read(&data).unwrap();
+
+    for big_endian in [false, true] {
+        let converted = pcap(&records, frame::LINKTYPE_ETHERNET, big_endian);
+        let reread = capture::read(&converted).unwrap();
+        assert_eq!(reread.len(), records.len());
+        for (reread, record) in reread.iter().zip(records.iter()) {
+            assert_eq!(reread.data, record.data);
+            // The capture has nanosecond timestamps, classic pcap only microseconds
+            let (a, b) = (reread.timestamp.unwrap(), record.timestamp.unwrap());
+            assert_eq!((a.as_secs(), a.subsec_micros()), (b.as_secs(), b.subsec_micros()));
+        }
+    }
+}
+
+#[test]
+fn decapsulates_cooked_captures() {
+    let data = read("NHRP.pcapng");
+    let records = capture::read(&data).unwrap();
+    let packets = packets(&records);
+    let nhrp = packets[0].bytes();
+
+    // Captured on the GRE interface, the link-layer address is the NBMA address of the peer
+    let mut sll = vec![0, 0, 0x03, 0x0a, 0, 4, 198, 51, 100, 5, 0, 0, 0, 0, 0x20, 0x01];
+    sll.extend_from_slice(nhrp);
+    let mut sll2 = vec![0x20, 0x01, 0, 0, 0, 0, 0, 5, 0x03, 0x0a, 0, 4, 198, 51, 100, 5, 0, 0, 0, 0];
+    sll2.extend_from_slice(nhrp);
+
+    for (linktype, frame) in [(frame::LINKTYPE_LINUX_SLL, &sll), (frame::LINKTYPE_LINUX_SLL2, &sll2)] {
+        let decoded = frame::decapsulate(linktype, frame).unwrap();
+        assert_eq!(decoded.nhrp, nhrp);
+        assert_eq!(decoded.nbma_src, ip("198.51.100.5"));
+    }
+
+    // GRE with a key, in raw IP
+    let ethernet = records[0].data;
+    let mut ipv4 = ethernet[14..34].to_vec();
+    ipv4[2..4].copy_from_slice(&((20 + 8 + nhrp.len()) as u16).to_be_bytes());
+    ipv4.extend_from_slice(&[0x20, 0x00, 0x20, 0x01, 0, 0, 0, 42]);
+    ipv4.extend_from_slice(nhrp);
+    let decoded = frame::decapsulate(frame::LINKTYPE_RAW, &ipv4).unwrap();
+    assert_eq!(decoded.nhrp, nhrp);
+    assert_eq!(decoded.nbma_dst, ip("198.51.100.4"));
+}
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{CieBuffer, ClientInformationEntry, Parseable};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buffer) = CieBuffer::new_checked(data) {
+        let _ = buffer.code();
+        let _ = buffer.prefix_len();
+        let _ = buffer.mtu();
+        let _ = buffer.holding_time();
+        let _ = buffer.preference();
+        let _ = buffer.cli_nbma_addr();
+        let _ = buffer.cli_nbma_saddr();
+        let _ = buffer.cli_proto_addr();
+        let _: Result<ClientInformationEntry, _> = buffer.parse();
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{ErrorIndicationBuffer, ErrorIndicationMessage, Parseable, TrafficIndicationMessage};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buffer) = ErrorIndicationBuffer::new_checked(data) {
+        let _ = buffer.error_code();
+        let _ = buffer.error_offset();
+        let _ = buffer.src_nbma_addr();
+        let _ = buffer.src_nbma_saddr();
+        let _ = buffer.src_proto_addr();
+        let _ = buffer.dst_proto_addr();
+        let _ = buffer.payload();
+        let _: Result<ErrorIndicationMessage, _> = buffer.parse();
+        let _: Result<TrafficIndicationMessage, _> = buffer.parse();
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{Extension, ExtensionBuffer, ExtensionIterator, Parseable};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buffer) = ExtensionBuffer::new_checked(data) {
+        let _ = buffer.extensiontype();
+        let _ = buffer.compulsory();
+        let _ = buffer.payload();
+        let _: Result<Extension, _> = buffer.parse();
+    }
+    for ext in ExtensionIterator::new(data).flatten() {
+        let _: Result<Extension, _> = ext.parse();
+    }
+});
@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{ChecksumPolicy, Emitable, NhrpMessage, NhrpMessageView, Parseable};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = NhrpMessage::from_bytes_checked(data, ChecksumPolicy::Verify);
+
+    if let Ok(view) = NhrpMessageView::new(data) {
+        let _ = view.header();
+        let _ = view.request_id();
+        let _ = view.flags();
+        let _ = view.error_header();
+        view.cies().for_each(drop);
+        view.extensions().for_each(drop);
+        let _ = view.verify_checksum(ChecksumPolicy::AllowZero);
+        let _: Result<NhrpMessage, _> = view.parse();
+    }
+
+    // Whatever we accept we must be able to send back out and read again
+    if let Ok(msg) = NhrpMessage::from_bytes(data) {
+        let mut buffer = vec![0; msg.buffer_len()];
+        msg.emit(&mut buffer);
+        NhrpMessage::from_bytes_checked(&buffer, ChecksumPolicy::Verify)
+            .expect("emitted message does not parse");
+    }
+});
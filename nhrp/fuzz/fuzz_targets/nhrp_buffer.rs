@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{ChecksumPolicy, FixedHeader, NhrpBuffer, Parseable};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buffer) = NhrpBuffer::new_checked(data) {
+        let _ = buffer.afn();
+        let _ = buffer.protocol_type();
+        let _ = buffer.hopcount();
+        let _ = buffer.length();
+        let _ = buffer.extoffset();
+        let _ = buffer.version();
+        let _ = buffer.optype();
+        let _ = buffer.payload_length();
+        let _ = buffer.payload();
+        let _ = buffer.extensions();
+        let _ = buffer.verify_checksum(ChecksumPolicy::Verify);
+        let _: Result<FixedHeader, _> = buffer.parse();
+    }
+});
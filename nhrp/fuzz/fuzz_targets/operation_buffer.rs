@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use nhrp::{CieIterator, CommonHeader, OperationBuffer, Parseable};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buffer) = OperationBuffer::new_checked(data) {
+        let _ = buffer.flags();
+        let _ = buffer.request_id();
+        let _ = buffer.src_nbma_addr();
+        let _ = buffer.src_nbma_saddr();
+        let _ = buffer.src_proto_addr();
+        let _ = buffer.dst_proto_addr();
+        let _: Result<CommonHeader, _> = buffer.parse();
+        for cie in CieIterator::new(buffer.payload()).flatten() {
+            let _ = cie.parse();
+        }
+    }
+});
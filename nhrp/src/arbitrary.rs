@@ -0,0 +1,381 @@
+//! `Arbitrary` impls generating messages that are valid by construction, i.e. that parse back
+//! into the same value after being emitted.
+//!
+//! All addresses of a message have to agree with the address family and protocol type in its
+//! fixed header, so they are generated for an [`Addressing`] picked once per message. The
+//! impls for the parts of a message pick one of their own.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+use crate::extensions::*;
+use crate::header::{AddressFamily, FixedHeader, NhrpOp, ProtocolClass, ProtocolType};
+use crate::message::NhrpMessage;
+use crate::operation::*;
+
+/// Most CIEs in an operation or extension, and most extensions in a message
+const MAX_ENTRIES: usize = 4;
+/// Longest opaque data, i.e. packets in error and extension payloads
+const MAX_DATA_LEN: usize = 64;
+/// Longest subaddress, and NBMA address of families without a fixed address length
+const MAX_NBMA_LEN: usize = 20;
+/// Longest E.164 number (ITU-T E.164, 6)
+const MAX_E164_DIGITS: usize = 15;
+
+/// Address family and protocol type of a message
+#[derive(Debug, Clone, Copy)]
+struct Addressing {
+    afn: AddressFamily,
+    protocol_type: ProtocolType,
+}
+
+impl<'a> Arbitrary<'a> for Addressing {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let afn = *u.choose(&[AddressFamily::IPv4, AddressFamily::IPv6, AddressFamily::NSAP,
+            AddressFamily::IEEE802, AddressFamily::E164, AddressFamily::Unknown(0xffff)])?;
+        let protype = *u.choose(&[ProtocolClass::Ethertype(0x0800), ProtocolClass::NLPID(0xcc),
+            ProtocolClass::Ethertype(0x86dd), ProtocolClass::NLPID(0x8e)])?;
+        let protocol_type = ProtocolType { protype, prosnap: u.arbitrary()? };
+        Ok(Addressing { afn, protocol_type })
+    }
+}
+
+impl Addressing {
+    /// A protocol address of the protocol type
+    fn proto_addr(&self, u: &mut Unstructured<'_>) -> Result<IpAddr> {
+        Ok(match self.protocol_type.address_len() {
+            Some(4) => Ipv4Addr::from(u.arbitrary::<[u8; 4]>()?).into(),
+            _ => Ipv6Addr::from(u.arbitrary::<[u8; 16]>()?).into(),
+        })
+    }
+
+    /// An NBMA address of the address family. The address or the subaddress may be empty.
+    fn nbma_addr(&self, u: &mut Unstructured<'_>) -> Result<NbmaAddress> {
+        let address = if u.arbitrary()? {
+            Vec::new()
+        } else if self.afn == AddressFamily::E164 {
+            let len = u.int_in_range(1..=MAX_E164_DIGITS)?;
+            (0..len).map(|_| Ok(b'0' + u.int_in_range(0..=9)?)).collect::<Result<_>>()?
+        } else {
+            let len = match self.afn.address_len() {
+                Some(len) => len,
+                None => u.int_in_range(1..=MAX_NBMA_LEN)?,
+            };
+            u.bytes(len)?.to_vec()
+        };
+        let addr_type = match self.afn {
+            AddressFamily::E164 => NbmaAddrType::E164,
+            _ => NbmaAddrType::NSAP,
+        };
+        let mut nbma = NbmaAddress::new(addr_type, address).map_err(|_| Error::IncorrectFormat)?;
+
+        // An empty subaddress is the same as none on the wire
+        if u.arbitrary()? {
+            let len = u.int_in_range(1..=MAX_NBMA_LEN)?;
+            let addr_type = *u.choose(&[NbmaAddrType::NSAP, NbmaAddrType::E164])?;
+            nbma = nbma.with_subaddress(addr_type, u.bytes(len)?.to_vec()).map_err(|_| Error::IncorrectFormat)?;
+        }
+        Ok(nbma)
+    }
+
+    /// A non-empty NBMA address, or none at all since an empty one is the same on the wire
+    fn client_nbma_addr(&self, u: &mut Unstructured<'_>) -> Result<Option<NbmaAddress>> {
+        Ok(Some(self.nbma_addr(u)?).filter(|addr| !addr.is_empty()))
+    }
+}
+
+/// Up to `max` octets of opaque data
+fn octets(u: &mut Unstructured<'_>, max: usize) -> Result<Vec<u8>> {
+    let len = u.int_in_range(0..=max)?;
+    Ok(u.bytes(len)?.to_vec())
+}
+
+/// A part of a message, generated for the addressing of the message it is in
+trait ArbitraryFor<'a>: Sized {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self>;
+}
+
+/// Up to [`MAX_ENTRIES`] parts for the same message
+fn entries<'a, T: ArbitraryFor<'a>>(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Vec<T>> {
+    let len = u.int_in_range(0..=MAX_ENTRIES)?;
+    (0..len).map(|_| T::arbitrary_for(u, addressing)).collect()
+}
+
+/// Implement `Arbitrary` for parts of a message with an addressing of their own
+macro_rules! arbitrary_for_any_addressing {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl<'a> Arbitrary<'a> for $ty {
+                fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                    let addressing = Addressing::arbitrary(u)?;
+                    <$ty>::arbitrary_for(u, addressing)
+                }
+            }
+        )*
+    };
+}
+
+arbitrary_for_any_addressing!(NbmaAddress, CommonHeader, ErrorHeader, TrafficHeader,
+    ClientInformationEntry, ResolutionRequestMessage, ResolutionReplyMessage,
+    RegistrationRequestMessage, RegistrationReplyMessage, PurgeMessage, ErrorIndicationMessage,
+    TrafficIndicationMessage, Operation, Extension);
+
+/*
+ * Values without addresses. Their conversions from the wire format are total, so anything
+ * converted from it is valid.
+ */
+
+impl<'a> Arbitrary<'a> for AddressFamily {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for ProtocolType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(ProtocolType {
+            protype: u16::arbitrary(u)?.into(),
+            prosnap: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for NhrpOp {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u8::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for FixedHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(FixedHeader::new(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ResolutionFlags {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for RegistrationFlags {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for PurgeFlags {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for CieCode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u8::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for ErrorCode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for TrafficCode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(u16::arbitrary(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for ExtensionType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(ExtensionType::from_bits(u.arbitrary()?))
+    }
+}
+
+/*
+ * Parts of a message
+ */
+
+impl<'a> ArbitraryFor<'a> for NbmaAddress {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        addressing.nbma_addr(u)
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for CommonHeader {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        Ok(CommonHeader {
+            request_id: u.arbitrary()?,
+            src_nbma_addr: addressing.nbma_addr(u)?,
+            src_proto_addr: addressing.proto_addr(u)?,
+            dst_proto_addr: addressing.proto_addr(u)?,
+        })
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for ErrorHeader {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        Ok(ErrorHeader {
+            code: u.arbitrary()?,
+            offset: u.arbitrary()?,
+            src_nbma_addr: addressing.nbma_addr(u)?,
+            src_proto_addr: addressing.proto_addr(u)?,
+            dst_proto_addr: addressing.proto_addr(u)?,
+        })
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for TrafficHeader {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        Ok(TrafficHeader {
+            code: u.arbitrary()?,
+            src_nbma_addr: addressing.nbma_addr(u)?,
+            src_proto_addr: addressing.proto_addr(u)?,
+            dst_proto_addr: addressing.proto_addr(u)?,
+        })
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for ClientInformationEntry {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let client_proto_addr = if u.arbitrary()? {
+            Some(addressing.proto_addr(u)?)
+        } else {
+            None
+        };
+        Ok(ClientInformationEntry {
+            code: u.arbitrary()?,
+            prefix_len: u.arbitrary()?,
+            mtu: u.arbitrary()?,
+            holding_time: u.arbitrary()?,
+            preference: u.arbitrary()?,
+            client_nbma_addr: addressing.client_nbma_addr(u)?,
+            client_proto_addr,
+        })
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for ResolutionRequestMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = CommonHeader::arbitrary_for(u, addressing)?;
+        let cie = if u.arbitrary()? {
+            Some(ClientInformationEntry::arbitrary_for(u, addressing)?)
+        } else {
+            None
+        };
+        Ok(ResolutionRequestMessage::new(header, u.arbitrary()?, cie))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for ResolutionReplyMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = CommonHeader::arbitrary_for(u, addressing)?;
+        Ok(ResolutionReplyMessage::from_parts(header, u.arbitrary()?, entries(u, addressing)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for RegistrationRequestMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = CommonHeader::arbitrary_for(u, addressing)?;
+        Ok(RegistrationRequestMessage::new(header, u.arbitrary()?, entries(u, addressing)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for RegistrationReplyMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = CommonHeader::arbitrary_for(u, addressing)?;
+        Ok(RegistrationReplyMessage::new(header, u.arbitrary()?, entries(u, addressing)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for PurgeMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = CommonHeader::arbitrary_for(u, addressing)?;
+        Ok(PurgeMessage::new(header, u.arbitrary()?, entries(u, addressing)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for ErrorIndicationMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = ErrorHeader::arbitrary_for(u, addressing)?;
+        Ok(ErrorIndicationMessage::new(header.code, header.offset, header.src_nbma_addr,
+            header.src_proto_addr, header.dst_proto_addr, octets(u, MAX_DATA_LEN)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for TrafficIndicationMessage {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        let header = TrafficHeader::arbitrary_for(u, addressing)?;
+        Ok(TrafficIndicationMessage::new(header.code, header.src_nbma_addr, header.src_proto_addr,
+            header.dst_proto_addr, octets(u, MAX_DATA_LEN)?))
+    }
+}
+
+impl<'a> ArbitraryFor<'a> for Operation {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        use Operation::*;
+        Ok(match u.int_in_range(0..=7)? {
+            0 => ResolutionRequest(ArbitraryFor::arbitrary_for(u, addressing)?),
+            1 => ResolutionReply(ArbitraryFor::arbitrary_for(u, addressing)?),
+            2 => RegistrationRequest(ArbitraryFor::arbitrary_for(u, addressing)?),
+            3 => RegistrationReply(ArbitraryFor::arbitrary_for(u, addressing)?),
+            4 => PurgeRequest(ArbitraryFor::arbitrary_for(u, addressing)?),
+            5 => PurgeReply(ArbitraryFor::arbitrary_for(u, addressing)?),
+            6 => ErrorIndication(ArbitraryFor::arbitrary_for(u, addressing)?),
+            _ => TrafficIndication(ArbitraryFor::arbitrary_for(u, addressing)?),
+        })
+    }
+}
+
+/// Any extension but End of Extensions, which may only terminate the extensions of a message
+impl<'a> ArbitraryFor<'a> for Extension {
+    fn arbitrary_for(u: &mut Unstructured<'a>, addressing: Addressing) -> Result<Self> {
+        use Extension::*;
+        let compulsory = u.arbitrary()?;
+        Ok(match u.int_in_range(0..=6)? {
+            0 => {
+                let cie = if u.arbitrary()? {
+                    Some(ClientInformationEntry::arbitrary_for(u, addressing)?)
+                } else {
+                    None
+                };
+                ResponderAddress { compulsory, cie }
+            },
+            1 => ForwardTransitNhsRecord { compulsory, cies: entries(u, addressing)? },
+            2 => ReverseTransitNhsRecord { compulsory, cies: entries(u, addressing)? },
+            3 => Authentication { compulsory, spi: u.arbitrary()?, data: octets(u, MAX_DATA_LEN)? },
+            4 => VendorPrivate {
+                compulsory,
+                vendor_id: u.arbitrary()?,
+                data: octets(u, MAX_DATA_LEN)?,
+            },
+            5 => NatAddress { compulsory, cies: entries(u, addressing)? },
+            _ => Other {
+                // Types above the ones this crate knows, which all parse into other variants
+                etype: ExtensionType::from_bits(u.int_in_range(u16::from(NAT_ADDRESS) + 1..=0x3fff)?),
+                compulsory,
+                data: octets(u, MAX_DATA_LEN)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for NhrpMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let addressing = Addressing::arbitrary(u)?;
+        let operation = Operation::arbitrary_for(u, addressing)?;
+        let header = FixedHeader::new(addressing.afn, addressing.protocol_type, u.arbitrary()?,
+            operation.optype());
+
+        // Parsing always yields the End of Extensions, even if the sender left it out
+        let mut extensions: Vec<Extension> = entries(u, addressing)?;
+        if !extensions.is_empty() {
+            extensions.push(Extension::EndOfExtensions);
+        }
+        Ok(NhrpMessage::new(header, operation, extensions))
+    }
+}
@@ -5,16 +5,27 @@ use super::{Field, Index, Rest, Result, Error};
 const AFN: Field = 0..2;
 const PROTYPE: Field = 2..4;
 const SNAP: Field = 4..9;
-const HOPCOUNT: Index = 9;
+pub(crate) const HOPCOUNT: Index = 9;
 const PKTSIZE: Field = 10..12;
-const CHECKSUM: Field = 12..14;
+pub(crate) const CHECKSUM: Field = 12..14;
 const EXTOFFSET: Field = 14..16;
-const VERSION: Index = 16;
-const OPTYPE: Index = 17;
+pub(crate) const VERSION: Index = 16;
+pub(crate) const OPTYPE: Index = 17;
 const PAYLOAD: Rest = 18..;
 
 pub const FIXED_HEADER_LEN: usize = PAYLOAD.start;
 
+/// How strictly to check the checksum of received packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChecksumPolicy {
+    /// Reject every packet whose checksum doesn't match its contents
+    #[default]
+    Verify,
+    /// Also accept packets with a checksum of zero. Some implementations never fill in the
+    /// checksum at all.
+    AllowZero,
+}
+
 pub struct NhrpBuffer<T> {
     buffer: T
 }
@@ -32,17 +43,27 @@ impl<T: AsRef<[u8]>> NhrpBuffer<T> {
 
     fn check_buffer_length(&self) -> Result<()> {
         let len = self.buffer.as_ref().len();
-        if len < PKTSIZE.end || len < self.length() as usize {
-            Err(Error::Truncated)
-        } else {
-            Ok(())
+        if len < FIXED_HEADER_LEN {
+            return Err(Error::malformed(0, len, "fixed_header",
+                format_args!("{} octets", FIXED_HEADER_LEN), format_args!("{} octets", len)));
+        }
+        let length = self.length() as usize;
+        if length < FIXED_HEADER_LEN || length > len {
+            return Err(Error::malformed(PKTSIZE.start, PKTSIZE.len(), "length",
+                format_args!("{} to {}", FIXED_HEADER_LEN, len), length));
+        }
+        match self.extoffset() as usize {
+            0 => Ok(()),
+            offset if (FIXED_HEADER_LEN..=length).contains(&offset) => Ok(()),
+            offset => Err(Error::malformed(EXTOFFSET.start, EXTOFFSET.len(), "extoffset",
+                format_args!("0 or {} to {}", FIXED_HEADER_LEN, length), offset)),
         }
     }
 
     pub fn payload_length(&self) -> usize {
         let total_length = self.length() as usize;
         let payload_offset = PAYLOAD.start;
-        total_length - payload_offset
+        total_length.saturating_sub(payload_offset)
     }
 
     pub fn into_inner(self) -> T {
@@ -69,7 +90,7 @@ impl<T: AsRef<[u8]>> NhrpBuffer<T> {
     pub fn protocol_type(&self) -> ProtocolType {
         let protype = self.protype().into();
         ProtocolType {
-            protype: protype,
+            protype,
             prosnap: self.prosnap(),
         }
     }
@@ -104,55 +125,82 @@ impl<T: AsRef<[u8]>> NhrpBuffer<T> {
         data[OPTYPE].into()
     }
 
+    /// RFC 1071 checksum over the packet as given by its length field.
+    ///
+    /// The checksum field itself is treated as zero, so the result can be compared against
+    /// [`NhrpBuffer::checksum`] directly. An odd trailing octet is padded with a zero octet,
+    /// making it the high-order byte of the last word.
     pub fn calculate_checksum(&self) -> u16 {
-        let len = self.length() as usize;
-        let mut uints = vec![0; len/2].into_boxed_slice();
-        for i in 0..(len/2) {
-            let start = 2 * i;
-            let end = start+2;
-            let array = self.buffer.as_ref()[start..end].try_into().unwrap();
-            uints[i] = u16::from_be_bytes(array);
+        let data = self.buffer.as_ref();
+        let len = (self.length() as usize).min(data.len());
+
+        let mut sum = data[..len].chunks(2)
+            .enumerate()
+            .filter(|(i, _)| *i != CHECKSUM.start / 2)
+            .map(|(_, word)| match *word {
+                [high, low] => u16::from_be_bytes([high, low]) as u32,
+                [high] => u16::from_be_bytes([high, 0]) as u32,
+                _ => 0,
+            })
+            .sum::<u32>();
+
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
         }
-        let mut checksum = 0u32;
 
-        checksum = uints.iter().fold(checksum, |a,x| a + *x as u32);
+        !sum as u16
+    }
 
-        if len & 1 == 1 {
-            checksum += self.buffer.as_ref()[len-1] as u32
+    /// Check the checksum of the packet against `policy`.
+    pub fn verify_checksum(&self, policy: ChecksumPolicy) -> Result<()> {
+        let found = self.checksum();
+        if found == 0 && policy == ChecksumPolicy::AllowZero {
+            return Ok(());
         }
 
-        // This one goes wrong somehow
-        while checksum & 0xffff0000 != 0 {
-            checksum = (checksum & 0xffff) + (checksum >> 16);
+        let expected = self.calculate_checksum();
+        if found == expected {
+            Ok(())
+        } else {
+            Err(Error::Checksum { expected, found })
         }
+    }
+}
 
-        !checksum as u16
+impl<T: AsRef<[u8]>> NhrpBuffer<T> {
+    /// End of the operation payload. An extension offset of zero means that the packet
+    /// carries no extensions at all, in which case the payload spans the rest of the packet.
+    fn payload_end(&self) -> usize {
+        match self.extoffset() {
+            0 => self.length() as usize,
+            offset => offset as usize,
+        }
     }
 }
 
 impl<'a, T: AsRef<[u8]> + ?Sized> NhrpBuffer<&'a T> {
     pub fn payload(&self) -> &'a [u8] {
-        let range = PAYLOAD.start..self.extoffset() as usize;
+        let range = PAYLOAD.start..self.payload_end();
         let data = self.buffer.as_ref();
         &data[range]
     }
 
     pub fn extensions(&self) -> &'a [u8] {
-        let range = (self.extoffset() as usize)..;
+        let range = self.payload_end()..(self.length() as usize);
         let data = self.buffer.as_ref();
         &data[range]
     }
 }
 
-impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> NhrpBuffer<&'a mut T> {
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> NhrpBuffer<&mut T> {
     pub fn payload_mut(&mut self) -> &mut [u8] {
-        let range = PAYLOAD.start..self.extoffset() as usize;
+        let range = PAYLOAD.start..self.payload_end();
         let data = self.buffer.as_mut();
         &mut data[range]
     }
 
     pub fn extensions_mut(&mut self) -> &mut [u8] {
-        let range = (self.extoffset() as usize)..;
+        let range = self.payload_end()..(self.length() as usize);
         let data = self.buffer.as_mut();
         &mut data[range]
     }
@@ -175,7 +223,7 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> NhrpBuffer<T> {
     }
 
     pub fn set_protocol_type(&mut self, value: ProtocolType) {
-        self.set_protype(value.protype.into());
+        self.set_protype(value.protype.value());
         self.set_prosnap(value.prosnap);
     }
 
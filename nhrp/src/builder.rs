@@ -0,0 +1,688 @@
+//! Builders for messages that are to be sent.
+//!
+//! Operation builders take the addresses every message needs up front and everything else as
+//! optional setters. `build()` checks that the parts fit together and returns the finished
+//! message; [`NhrpMessage::builder`] then derives the fixed header from the operation.
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+use core::net::IpAddr;
+
+use thiserror::Error;
+
+use crate::{Emitable, Result};
+use crate::header::{AddressFamily, FixedHeader, NhrpOp, ProtocolType};
+use crate::message::NhrpMessage;
+use crate::extensions::Extension;
+use crate::operation::*;
+
+/// Hop count of messages unless set explicitly, the same Cisco routers use
+pub const DEFAULT_HOPCOUNT: u8 = 16;
+
+/// Prefix length covering the whole protocol address whatever its length
+pub(crate) const PREFIX_LEN_FULL: u8 = 0xFF;
+
+/// A combination of values a builder refused to put into a message
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuildError {
+    #[error("{0} is required")]
+    MissingField(&'static str),
+    #[error("{field} is {len} octets long, at most 63 fit into a message")]
+    AddressTooLong { field: String, len: usize },
+    #[error("{field} is not of the same address family as src_proto_addr")]
+    AddressFamilyMismatch { field: String },
+    #[error("prefix length {prefix_len} is longer than the {width} bits of the client protocol address")]
+    PrefixTooLong { prefix_len: u8, width: u8 },
+    #[error("{field} has code {code}, but requests must carry code 0")]
+    RequestCode { field: String, code: u8 },
+    #[error("error offset {offset} is outside of the {len} octet offending packet")]
+    OffsetOutOfRange { offset: u16, len: usize },
+    #[error("address family cannot be derived from source NBMA address {0}, set it explicitly")]
+    UnknownAddressFamily(NbmaAddress),
+    #[error("source NBMA address {nbma_addr} does not belong to address family {afn}")]
+    AddressFamilyConflict { afn: AddressFamily, nbma_addr: NbmaAddress },
+    #[error("protocol type {0:#06x} does not match the protocol addresses")]
+    ProtocolTypeConflict(u16),
+    #[error("hop count must not be zero")]
+    ZeroHopCount,
+    #[error("End of Extensions must be the last extension")]
+    MisplacedEndOfExtensions,
+    #[error("message is {0} octets long, at most 65535 fit into the packet size field")]
+    TooLong(usize),
+}
+
+impl From<BuildError> for crate::Error {
+    fn from(value: BuildError) -> crate::Error {
+        crate::Error::Build(value)
+    }
+}
+
+fn width(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn same_family(a: &IpAddr, b: &IpAddr) -> bool {
+    a.is_ipv4() == b.is_ipv4()
+}
+
+fn check_addresses(src_proto_addr: &IpAddr, dst_proto_addr: &IpAddr)
+    -> Result<()>
+{
+    if !same_family(src_proto_addr, dst_proto_addr) {
+        return Err(BuildError::AddressFamilyMismatch { field: "dst_proto_addr".to_string() }.into());
+    }
+    Ok(())
+}
+
+/// Check the CIEs of an operation against its source protocol address. CIEs of requests, and
+/// of purges which are echoed by their replies, must not carry a code.
+fn check_cies(cies: &[ClientInformationEntry], src_proto_addr: &IpAddr, request: bool) -> Result<()> {
+    for (index, cie) in cies.iter().enumerate() {
+        if let Some(ref addr) = cie.client_proto_addr {
+            if !same_family(addr, src_proto_addr) {
+                return Err(BuildError::AddressFamilyMismatch {
+                    field: format!("CIE[{}].client_proto_addr", index),
+                }.into());
+            }
+        }
+        if request && cie.code != CieCode::Success {
+            return Err(BuildError::RequestCode {
+                field: format!("CIE[{}]", index),
+                code: cie.code.into(),
+            }.into());
+        }
+    }
+    Ok(())
+}
+
+impl ClientInformationEntry {
+    pub fn builder() -> CieBuilder {
+        CieBuilder::default()
+    }
+}
+
+/// Builder for a [`ClientInformationEntry`]
+///
+/// The prefix length defaults to the full width of the client protocol address, or to zero if
+/// there is none.
+#[derive(Debug, Clone, Default)]
+pub struct CieBuilder {
+    code: Option<CieCode>,
+    prefix_len: Option<u8>,
+    mtu: u16,
+    holding_time: u16,
+    preference: u8,
+    client_nbma_addr: Option<NbmaAddress>,
+    client_proto_addr: Option<IpAddr>,
+}
+
+impl CieBuilder {
+    pub fn code(mut self, code: impl Into<CieCode>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Length of the prefix the entry applies to in bits. `0xFF` stands for the whole address.
+    pub fn prefix_len(mut self, prefix_len: u8) -> Self {
+        self.prefix_len = Some(prefix_len);
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u16) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// How long the binding may be cached, in seconds
+    pub fn holding_time(mut self, holding_time: u16) -> Self {
+        self.holding_time = holding_time;
+        self
+    }
+
+    pub fn preference(mut self, preference: u8) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    pub fn client_nbma_addr(mut self, addr: impl Into<NbmaAddress>) -> Self {
+        self.client_nbma_addr = Some(addr.into());
+        self
+    }
+
+    pub fn client_proto_addr(mut self, addr: IpAddr) -> Self {
+        self.client_proto_addr = Some(addr);
+        self
+    }
+
+    pub fn build(self) -> Result<ClientInformationEntry> {
+        let max = self.client_proto_addr.as_ref().map_or(0, width);
+        let prefix_len = self.prefix_len.unwrap_or(max);
+        if prefix_len > max && prefix_len != PREFIX_LEN_FULL {
+            return Err(BuildError::PrefixTooLong { prefix_len, width: max }.into());
+        }
+
+        Ok(ClientInformationEntry {
+            code: self.code.unwrap_or(CieCode::Success),
+            prefix_len,
+            mtu: self.mtu,
+            holding_time: self.holding_time,
+            preference: self.preference,
+            // An empty address is sent the same as none at all and parses back as `None`
+            client_nbma_addr: self.client_nbma_addr.filter(|a| !a.is_empty()),
+            client_proto_addr: self.client_proto_addr,
+        })
+    }
+}
+
+impl ResolutionRequestMessage {
+    pub fn builder(src_nbma_addr: impl Into<NbmaAddress>, src_proto_addr: IpAddr, dst_proto_addr: IpAddr)
+        -> ResolutionRequestBuilder
+    {
+        ResolutionRequestBuilder {
+            header: CommonHeader {
+                request_id: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            flags: ResolutionFlags::default(),
+            cie: None,
+        }
+    }
+}
+
+/// Builder for a [`ResolutionRequestMessage`]
+#[derive(Debug, Clone)]
+pub struct ResolutionRequestBuilder {
+    header: CommonHeader,
+    flags: ResolutionFlags,
+    cie: Option<ClientInformationEntry>,
+}
+
+impl ResolutionRequestBuilder {
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: ResolutionFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// The single CIE a request may carry, e.g. to ask for a particular MTU or holding time
+    pub fn cie(mut self, cie: ClientInformationEntry) -> Self {
+        self.cie = Some(cie);
+        self
+    }
+
+    pub fn build(self) -> Result<ResolutionRequestMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        check_cies(self.cie.as_slice(), &h.src_proto_addr, true)?;
+        Ok(ResolutionRequestMessage::new(self.header, self.flags, self.cie))
+    }
+}
+
+impl ResolutionReplyMessage {
+    /// The addresses and request ID are those of the request being answered.
+    pub fn builder(src_nbma_addr: impl Into<NbmaAddress>, src_proto_addr: IpAddr, dst_proto_addr: IpAddr)
+        -> ResolutionReplyBuilder
+    {
+        ResolutionReplyBuilder {
+            header: CommonHeader {
+                request_id: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            flags: ResolutionFlags::default(),
+            code: ResolutionCode::Success,
+            cies: Vec::new(),
+        }
+    }
+}
+
+/// Builder for a [`ResolutionReplyMessage`]
+///
+/// Successful replies need at least one CIE with the resolved binding. Replies with any other
+/// code get an empty CIE carrying it if none are given.
+#[derive(Debug, Clone)]
+pub struct ResolutionReplyBuilder {
+    header: CommonHeader,
+    flags: ResolutionFlags,
+    code: ResolutionCode,
+    cies: Vec<ClientInformationEntry>,
+}
+
+impl ResolutionReplyBuilder {
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: ResolutionFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Outcome of the resolution, which replaces the codes of all CIEs
+    pub fn code(mut self, code: ResolutionCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn cie(mut self, cie: ClientInformationEntry) -> Self {
+        self.cies.push(cie);
+        self
+    }
+
+    pub fn build(mut self) -> Result<ResolutionReplyMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        check_cies(&self.cies, &h.src_proto_addr, false)?;
+        if self.cies.is_empty() {
+            if self.code == ResolutionCode::Success {
+                return Err(BuildError::MissingField("cie").into());
+            }
+            self.cies.push(ClientInformationEntry::builder().build()?);
+        }
+        for cie in self.cies.iter_mut() {
+            cie.code = self.code.into();
+        }
+        Ok(ResolutionReplyMessage::from_parts(self.header, self.flags, self.cies))
+    }
+}
+
+impl RegistrationRequestMessage {
+    pub fn builder(src_nbma_addr: impl Into<NbmaAddress>, src_proto_addr: IpAddr, dst_proto_addr: IpAddr)
+        -> RegistrationRequestBuilder
+    {
+        RegistrationRequestBuilder {
+            header: CommonHeader {
+                request_id: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            flags: RegistrationFlags::default(),
+            cies: Vec::new(),
+        }
+    }
+}
+
+/// Builder for a [`RegistrationRequestMessage`]
+///
+/// Without CIEs the request registers the source addresses of the message.
+#[derive(Debug, Clone)]
+pub struct RegistrationRequestBuilder {
+    header: CommonHeader,
+    flags: RegistrationFlags,
+    cies: Vec<ClientInformationEntry>,
+}
+
+impl RegistrationRequestBuilder {
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: RegistrationFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn cie(mut self, cie: ClientInformationEntry) -> Self {
+        self.cies.push(cie);
+        self
+    }
+
+    pub fn build(self) -> Result<RegistrationRequestMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        check_cies(&self.cies, &h.src_proto_addr, true)?;
+        Ok(RegistrationRequestMessage::new(self.header, self.flags, self.cies))
+    }
+}
+
+impl RegistrationReplyMessage {
+    /// The addresses and request ID are those of the request being answered.
+    pub fn builder(src_nbma_addr: impl Into<NbmaAddress>, src_proto_addr: IpAddr, dst_proto_addr: IpAddr)
+        -> RegistrationReplyBuilder
+    {
+        RegistrationReplyBuilder {
+            header: CommonHeader {
+                request_id: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            flags: RegistrationFlags::default(),
+            code: None,
+            cies: Vec::new(),
+        }
+    }
+}
+
+/// Builder for a [`RegistrationReplyMessage`]
+///
+/// The reply carries the CIEs of the request it answers, each with the code of its own
+/// registration, or an empty one if there were none.
+#[derive(Debug, Clone)]
+pub struct RegistrationReplyBuilder {
+    header: CommonHeader,
+    flags: RegistrationFlags,
+    code: Option<RegistrationCode>,
+    cies: Vec<ClientInformationEntry>,
+}
+
+impl RegistrationReplyBuilder {
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: RegistrationFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Outcome of the whole registration, which replaces the codes of all CIEs. Without it every
+    /// CIE keeps its own.
+    pub fn code(mut self, code: RegistrationCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn cie(mut self, cie: ClientInformationEntry) -> Self {
+        self.cies.push(cie);
+        self
+    }
+
+    pub fn build(mut self) -> Result<RegistrationReplyMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        check_cies(&self.cies, &h.src_proto_addr, false)?;
+        if self.cies.is_empty() {
+            self.cies.push(ClientInformationEntry::builder().build()?);
+        }
+        if let Some(code) = self.code {
+            for cie in self.cies.iter_mut() {
+                cie.code = code.into();
+            }
+        }
+        Ok(RegistrationReplyMessage::new(self.header, self.flags, self.cies))
+    }
+}
+
+impl PurgeMessage {
+    /// Builder for either a Purge Request or a Purge Reply, which share their layout. Replies
+    /// echo the request they answer.
+    pub fn builder(src_nbma_addr: impl Into<NbmaAddress>, src_proto_addr: IpAddr, dst_proto_addr: IpAddr)
+        -> PurgeBuilder
+    {
+        PurgeBuilder {
+            header: CommonHeader {
+                request_id: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            flags: PurgeFlags::default(),
+            cies: Vec::new(),
+        }
+    }
+}
+
+/// Builder for a [`PurgeMessage`]
+#[derive(Debug, Clone)]
+pub struct PurgeBuilder {
+    header: CommonHeader,
+    flags: PurgeFlags,
+    cies: Vec<ClientInformationEntry>,
+}
+
+impl PurgeBuilder {
+    pub fn request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: PurgeFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// A binding to purge
+    pub fn cie(mut self, cie: ClientInformationEntry) -> Self {
+        self.cies.push(cie);
+        self
+    }
+
+    pub fn build(self) -> Result<PurgeMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        check_cies(&self.cies, &h.src_proto_addr, true)?;
+        Ok(PurgeMessage::new(self.header, self.flags, self.cies))
+    }
+}
+
+impl ErrorIndicationMessage {
+    /// `dst_proto_addr` is the source protocol address of the packet in error.
+    pub fn builder(code: ErrorCode,
+                   src_nbma_addr: impl Into<NbmaAddress>,
+                   src_proto_addr: IpAddr,
+                   dst_proto_addr: IpAddr,
+    ) -> ErrorIndicationBuilder {
+        ErrorIndicationBuilder {
+            header: ErrorHeader {
+                code,
+                offset: 0,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            packet: Vec::new(),
+        }
+    }
+}
+
+/// Builder for an [`ErrorIndicationMessage`]
+#[derive(Debug, Clone)]
+pub struct ErrorIndicationBuilder {
+    header: ErrorHeader,
+    packet: Vec<u8>,
+}
+
+impl ErrorIndicationBuilder {
+    /// Offset of the error in the packet in error, counted from the start of its fixed header
+    pub fn offset(mut self, offset: u16) -> Self {
+        self.header.offset = offset;
+        self
+    }
+
+    /// The NHRP packet in error
+    pub fn packet(mut self, packet: Vec<u8>) -> Self {
+        self.packet = packet;
+        self
+    }
+
+    pub fn build(self) -> Result<ErrorIndicationMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        if h.offset != 0 && h.offset as usize >= self.packet.len() {
+            return Err(BuildError::OffsetOutOfRange { offset: h.offset, len: self.packet.len() }.into());
+        }
+        let h = self.header;
+        Ok(ErrorIndicationMessage::new(h.code, h.offset, h.src_nbma_addr, h.src_proto_addr,
+            h.dst_proto_addr, self.packet))
+    }
+}
+
+impl TrafficIndicationMessage {
+    /// `dst_proto_addr` is the source address of the packet that triggered the indication.
+    pub fn builder(code: TrafficCode,
+                   src_nbma_addr: impl Into<NbmaAddress>,
+                   src_proto_addr: IpAddr,
+                   dst_proto_addr: IpAddr,
+    ) -> TrafficIndicationBuilder {
+        TrafficIndicationBuilder {
+            header: TrafficHeader {
+                code,
+                src_nbma_addr: src_nbma_addr.into(),
+                src_proto_addr,
+                dst_proto_addr,
+            },
+            packet: Vec::new(),
+        }
+    }
+}
+
+/// Builder for a [`TrafficIndicationMessage`]
+#[derive(Debug, Clone)]
+pub struct TrafficIndicationBuilder {
+    header: TrafficHeader,
+    packet: Vec<u8>,
+}
+
+impl TrafficIndicationBuilder {
+    /// The leading bytes of the data packet that triggered the indication
+    pub fn packet(mut self, packet: Vec<u8>) -> Self {
+        self.packet = packet;
+        self
+    }
+
+    pub fn build(self) -> Result<TrafficIndicationMessage> {
+        let h = &self.header;
+        check_addresses(&h.src_proto_addr, &h.dst_proto_addr)?;
+        let h = self.header;
+        Ok(TrafficIndicationMessage::new(h.code, h.src_nbma_addr, h.src_proto_addr,
+            h.dst_proto_addr, self.packet))
+    }
+}
+
+impl NhrpMessage {
+    /// Start a message carrying `operation`, which is usually built with one of the operation
+    /// builders. Purges need to be wrapped into the right [`Operation`] first.
+    pub fn builder(operation: impl Into<Operation>) -> NhrpMessageBuilder {
+        NhrpMessageBuilder {
+            operation: operation.into(),
+            afn: None,
+            protocol_type: None,
+            hopcount: DEFAULT_HOPCOUNT,
+            extensions: Vec::new(),
+        }
+    }
+}
+
+/// Builder for an [`NhrpMessage`]
+///
+/// The address family is derived from the source NBMA address and the protocol type from the
+/// protocol addresses of the operation. Either can be set explicitly, e.g. for an Error
+/// Indication that has no NBMA address to derive it from.
+#[derive(Debug, Clone)]
+pub struct NhrpMessageBuilder {
+    operation: Operation,
+    afn: Option<AddressFamily>,
+    protocol_type: Option<ProtocolType>,
+    hopcount: u8,
+    extensions: Vec<Extension>,
+}
+
+impl NhrpMessageBuilder {
+    pub fn afn(mut self, afn: impl Into<AddressFamily>) -> Self {
+        self.afn = Some(afn.into());
+        self
+    }
+
+    pub fn protocol_type(mut self, protocol_type: ProtocolType) -> Self {
+        self.protocol_type = Some(protocol_type);
+        self
+    }
+
+    pub fn hopcount(mut self, hopcount: u8) -> Self {
+        self.hopcount = hopcount;
+        self
+    }
+
+    pub fn extension(mut self, extension: Extension) -> Self {
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn extensions(mut self, extensions: impl IntoIterator<Item = Extension>) -> Self {
+        self.extensions.extend(extensions);
+        self
+    }
+
+    pub fn build(self) -> Result<NhrpMessage> {
+        let (src_nbma_addr, src_proto_addr, dst_proto_addr) = match self.operation {
+            Operation::ErrorIndication(ref msg) => {
+                let h = msg.header();
+                (&h.src_nbma_addr, h.src_proto_addr, h.dst_proto_addr)
+            },
+            Operation::TrafficIndication(ref msg) => {
+                let h = msg.header();
+                (&h.src_nbma_addr, h.src_proto_addr, h.dst_proto_addr)
+            },
+            ref op => {
+                // Every other operation has a common header
+                let h = op.common_header().unwrap();
+                (&h.src_nbma_addr, h.src_proto_addr, h.dst_proto_addr)
+            },
+        };
+        // Operations put together with their `new` constructors have not been checked yet
+        check_addresses(&src_proto_addr, &dst_proto_addr)?;
+        check_cies(self.operation.cies(), &src_proto_addr, false)?;
+
+        let afn = match self.afn {
+            Some(afn) => afn,
+            None => derive_afn(src_nbma_addr)
+                .ok_or_else(|| BuildError::UnknownAddressFamily(src_nbma_addr.clone()))?,
+        };
+        let conflict = afn.address_len()
+            .is_some_and(|len| !src_nbma_addr.is_empty() && src_nbma_addr.address().len() != len);
+        if conflict {
+            return Err(BuildError::AddressFamilyConflict { afn, nbma_addr: src_nbma_addr.clone() }.into());
+        }
+
+        let protocol_type = match self.protocol_type {
+            Some(protocol_type) => {
+                let protype = u16::try_from(protocol_type.protype)?;
+                let derived = ProtocolType::from_ip(&src_proto_addr);
+                if protocol_type.address_len().is_some_and(|len| Some(len) != derived.address_len()) {
+                    return Err(BuildError::ProtocolTypeConflict(protype).into());
+                }
+                protocol_type
+            },
+            None => ProtocolType::from_ip(&src_proto_addr),
+        };
+
+        if self.hopcount == 0 {
+            return Err(BuildError::ZeroHopCount.into());
+        }
+        let eoe = self.extensions.iter().position(|e| *e == Extension::EndOfExtensions);
+        if eoe.is_some_and(|i| i + 1 != self.extensions.len()) {
+            return Err(BuildError::MisplacedEndOfExtensions.into());
+        }
+
+        let optype: NhrpOp = self.operation.optype();
+        let header = FixedHeader::new(afn, protocol_type, self.hopcount, optype);
+        let msg = NhrpMessage::new(header, self.operation, self.extensions);
+        if msg.buffer_len() > u16::MAX as usize {
+            return Err(BuildError::TooLong(msg.buffer_len()).into());
+        }
+        Ok(msg)
+    }
+}
+
+fn derive_afn(addr: &NbmaAddress) -> Option<AddressFamily> {
+    match (addr.addr_type(), addr.ip()) {
+        (_, Some(ref ip)) => Some(AddressFamily::from_ip(ip)),
+        (_, None) if addr.address().is_empty() => None,
+        (NbmaAddrType::NSAP, None) => Some(AddressFamily::NSAP),
+        (NbmaAddrType::E164, None) => Some(AddressFamily::E164),
+    }
+}
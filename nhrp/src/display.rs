@@ -0,0 +1,479 @@
+//! Human readable formatting of messages, laid out the way Wireshark shows them.
+//!
+//! `Display` prints an indented tree with one named field per line, the alternate form `{:#}` a
+//! one-line summary that fits into log messages.
+
+use core::fmt::{self, Write};
+use core::net::IpAddr;
+
+use crate::builder::PREFIX_LEN_FULL;
+use crate::extensions::*;
+use crate::header::{AddressFamily, NhrpOp, ProtocolType};
+use crate::message::NhrpMessage;
+use crate::operation::*;
+
+const INDENT: &str = "    ";
+
+/// Writer indenting every line after the first by one level
+struct Indented<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+}
+
+impl<'a, 'b> Indented<'a, 'b> {
+    fn new(f: &'a mut fmt::Formatter<'b>) -> Self {
+        Indented { f }
+    }
+}
+
+impl Write for Indented<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (index, line) in s.split('\n').enumerate() {
+            if index > 0 {
+                self.f.write_char('\n')?;
+                self.f.write_str(INDENT)?;
+            }
+            self.f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// A registered value that is shown by name followed by its number, e.g. `Success (0)`
+trait Code: fmt::Display + Copy {
+    fn number(self) -> Option<u16>;
+}
+
+/// Shows a [`Code`] with its number. Unknown codes show it as part of their name already.
+struct Coded<T>(T);
+
+impl<T: Code> fmt::Display for Coded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.number() {
+            Some(number) => write!(f, "{} ({})", self.0, number),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl Code for AddressFamily {
+    fn number(self) -> Option<u16> {
+        match self {
+            AddressFamily::Unknown(_) => None,
+            known => Some(known.into()),
+        }
+    }
+}
+
+impl Code for NhrpOp {
+    fn number(self) -> Option<u16> {
+        match self {
+            NhrpOp::Other(_) => None,
+            known => Some(u8::from(known).into()),
+        }
+    }
+}
+
+impl fmt::Display for NhrpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use NhrpOp::*;
+        match *self {
+            ResolutionRequest => f.write_str("Resolution Request"),
+            ResolutionReply => f.write_str("Resolution Reply"),
+            RegistrationRequest => f.write_str("Registration Request"),
+            RegistrationReply => f.write_str("Registration Reply"),
+            PurgeRequest => f.write_str("Purge Request"),
+            PurgeReply => f.write_str("Purge Reply"),
+            ErrorIndication => f.write_str("Error Indication"),
+            TrafficIndication => f.write_str("Traffic Indication"),
+            Other(op) => write!(f, "Unknown Operation ({})", op),
+        }
+    }
+}
+
+impl Code for CieCode {
+    fn number(self) -> Option<u16> {
+        match self {
+            CieCode::Unknown(_) => None,
+            known => Some(u8::from(known).into()),
+        }
+    }
+}
+
+impl fmt::Display for CieCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CieCode::*;
+        match *self {
+            Success => f.write_str("Success"),
+            Prohibited => f.write_str("Administratively Prohibited"),
+            InsufficientResources => f.write_str("Insufficient Resources"),
+            NoBindingExists => f.write_str("No Binding Exists"),
+            BindingNotUnique => f.write_str("Binding Not Unique"),
+            AlreadyRegistered => f.write_str("Already Registered"),
+            Unknown(code) => write!(f, "Unknown ({})", code),
+        }
+    }
+}
+
+impl Code for ErrorCode {
+    fn number(self) -> Option<u16> {
+        match self {
+            ErrorCode::Unknown(_) => None,
+            known => Some(known.into()),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorCode::*;
+        match *self {
+            UnrecognizedExtension => f.write_str("Unrecognized Extension"),
+            LoopDetected => f.write_str("NHRP Loop Detected"),
+            ProtocolAddressUnreachable => f.write_str("Protocol Address Unreachable"),
+            ProtocolError => f.write_str("Protocol Error"),
+            SduSizeExceeded => f.write_str("NHRP SDU Size Exceeded"),
+            InvalidExtension => f.write_str("Invalid Extension"),
+            InvalidResolutionReply => f.write_str("Invalid NHRP Resolution Reply Received"),
+            AuthenticationFailure => f.write_str("Authentication Failure"),
+            HopCountExceeded => f.write_str("Hop Count Exceeded"),
+            Unknown(code) => write!(f, "Unknown ({})", code),
+        }
+    }
+}
+
+impl Code for TrafficCode {
+    fn number(self) -> Option<u16> {
+        match self {
+            TrafficCode::Unknown(_) => None,
+            known => Some(known.into()),
+        }
+    }
+}
+
+impl fmt::Display for TrafficCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TrafficCode::Redirect => f.write_str("Redirect"),
+            TrafficCode::Unknown(code) => write!(f, "Unknown ({})", code),
+        }
+    }
+}
+
+impl fmt::Display for ExtensionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            END_OF_EXTENSIONS => f.write_str("End of Extensions"),
+            RESPONDER_ADDRESS => f.write_str("Responder Address"),
+            FORWARD_TRANSIT_NHS_RECORD => f.write_str("Forward Transit NHS Record"),
+            REVERSE_TRANSIT_NHS_RECORD => f.write_str("Reverse Transit NHS Record"),
+            AUTHENTICATION => f.write_str("Authentication"),
+            VENDOR_PRIVATE => f.write_str("Vendor-Private"),
+            NAT_ADDRESS => f.write_str("NAT Address"),
+            other => write!(f, "Unknown ({:#06x})", u16::from(other)),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.protype.value();
+        match self.address_len() {
+            Some(4) => write!(f, "IPv4 ({:#06x})", value)?,
+            Some(16) => write!(f, "IPv6 ({:#06x})", value)?,
+            _ => write!(f, "{:#06x}", value)?,
+        }
+        if self.prosnap != [0; 5] {
+            f.write_str(", SNAP ")?;
+            for (index, octet) in self.prosnap.iter().enumerate() {
+                if index > 0 {
+                    f.write_char(':')?;
+                }
+                write!(f, "{:02x}", octet)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flags as their value followed by each bit with a meaning, e.g. `0x8000 (N=1)`
+fn write_flags(f: &mut fmt::Formatter<'_>, value: u16, bits: &[(&str, bool)]) -> fmt::Result {
+    write!(f, "{:#06x} (", value)?;
+    for (index, (name, set)) in bits.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}={}", name, *set as u8)?;
+    }
+    f.write_char(')')
+}
+
+impl fmt::Display for ResolutionFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flags(f, (*self).into(), &[
+            ("Q", self.requester_router),
+            ("A", self.authoritative),
+            ("D", self.destination_stable),
+            ("U", self.unique),
+            ("S", self.source_stable),
+            ("NAT", self.nat),
+        ])
+    }
+}
+
+impl fmt::Display for RegistrationFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flags(f, (*self).into(), &[("U", self.unique), ("NAT", self.nat)])
+    }
+}
+
+impl fmt::Display for PurgeFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flags(f, (*self).into(), &[("N", self.no_reply)])
+    }
+}
+
+/// An NBMA address that may be left empty
+struct Nbma<'a>(Option<&'a NbmaAddress>);
+
+impl fmt::Display for Nbma<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(addr) if !addr.is_empty() => write!(f, "{}", addr),
+            _ => f.write_str("none"),
+        }
+    }
+}
+
+fn write_hex(f: &mut impl Write, data: &[u8]) -> fmt::Result {
+    for octet in data {
+        write!(f, "{:02x}", octet)?;
+    }
+    Ok(())
+}
+
+/// The addresses shared by all headers in one line: source, its NBMA address and destination
+fn write_route(f: &mut fmt::Formatter<'_>, src_nbma_addr: &NbmaAddress, src_proto_addr: &IpAddr,
+               dst_proto_addr: &IpAddr) -> fmt::Result
+{
+    write!(f, "{} ({}) > {}", src_proto_addr, Nbma(Some(src_nbma_addr)), dst_proto_addr)
+}
+
+/// The addresses shared by all headers, one per line
+fn write_addresses(w: &mut impl Write, src_nbma_addr: &NbmaAddress, src_proto_addr: &IpAddr,
+                   dst_proto_addr: &IpAddr) -> fmt::Result
+{
+    write!(w, "\nSource NBMA Address: {}", Nbma(Some(src_nbma_addr)))?;
+    write!(w, "\nSource Protocol Address: {}", src_proto_addr)?;
+    write!(w, "\nDestination Protocol Address: {}", dst_proto_addr)
+}
+
+/// CIEs in one line, separated by semicolons since their summaries contain commas
+fn write_cies(f: &mut fmt::Formatter<'_>, cies: &[ClientInformationEntry]) -> fmt::Result {
+    f.write_str("[")?;
+    for (index, cie) in cies.iter().enumerate() {
+        if index > 0 {
+            f.write_str("; ")?;
+        }
+        write!(f, "{:#}", cie)?;
+    }
+    f.write_str("]")
+}
+
+impl fmt::Display for CommonHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "ID {}, ", self.request_id)?;
+            return write_route(f, &self.src_nbma_addr, &self.src_proto_addr, &self.dst_proto_addr);
+        }
+        write!(f, "Request ID: {:#010x} ({})", self.request_id, self.request_id)?;
+        write_addresses(f, &self.src_nbma_addr, &self.src_proto_addr, &self.dst_proto_addr)
+    }
+}
+
+impl fmt::Display for ClientInformationEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", self.code)?;
+            match self.client_proto_addr {
+                Some(addr) if self.prefix_len == PREFIX_LEN_FULL => write!(f, " {}", addr)?,
+                Some(addr) => write!(f, " {}/{}", addr, self.prefix_len)?,
+                None => {},
+            }
+            if let Some(ref addr) = self.client_nbma_addr {
+                write!(f, " at {}", addr)?;
+            }
+            return write!(f, ", holding time {}s", self.holding_time);
+        }
+
+        f.write_str("Client Information Entry")?;
+        let mut w = Indented::new(f);
+        write!(w, "\nCode: {}", Coded(self.code))?;
+        write!(w, "\nPrefix Length: {}", self.prefix_len)?;
+        write!(w, "\nMaximum Transmission Unit: {}", self.mtu)?;
+        write!(w, "\nHolding Time: {}s", self.holding_time)?;
+        write!(w, "\nPreference: {}", self.preference)?;
+        write!(w, "\nClient NBMA Address: {}", Nbma(self.client_nbma_addr.as_ref()))?;
+        match self.client_proto_addr {
+            Some(addr) => write!(w, "\nClient Protocol Address: {}", addr),
+            None => write!(w, "\nClient Protocol Address: none"),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Operation::*;
+        let flags: &dyn fmt::Display = match self {
+            ResolutionRequest(msg) => &msg.flags(),
+            ResolutionReply(msg) => &msg.flags(),
+            RegistrationRequest(msg) => &msg.flags(),
+            RegistrationReply(msg) => &msg.flags(),
+            PurgeRequest(msg) | PurgeReply(msg) => &msg.flags(),
+            ErrorIndication(msg) => return write_error_indication(f, msg),
+            TrafficIndication(msg) => return write_traffic_indication(f, msg),
+        };
+        // Every other operation has a common header
+        let header = self.common_header().unwrap();
+
+        if f.alternate() {
+            write!(f, "{} {:#}, flags {}", self.optype(), header, flags)?;
+            if !self.cies().is_empty() {
+                f.write_str(", CIEs ")?;
+                write_cies(f, self.cies())?;
+            }
+            return Ok(());
+        }
+
+        write!(f, "{}", self.optype())?;
+        let mut w = Indented::new(f);
+        write!(w, "\nFlags: {}", flags)?;
+        write!(w, "\n{}", header)?;
+        for cie in self.cies() {
+            write!(w, "\n{}", cie)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_error_indication(f: &mut fmt::Formatter<'_>, msg: &ErrorIndicationMessage) -> fmt::Result {
+    let header = msg.header();
+    if f.alternate() {
+        write!(f, "Error Indication {} at offset {}, ", header.code, header.offset)?;
+        return write_route(f, &header.src_nbma_addr, &header.src_proto_addr, &header.dst_proto_addr);
+    }
+
+    f.write_str("Error Indication")?;
+    let mut w = Indented::new(f);
+    write!(w, "\nError Code: {}", Coded(header.code))?;
+    write!(w, "\nError Offset: {}", header.offset)?;
+    write_addresses(&mut w, &header.src_nbma_addr, &header.src_proto_addr, &header.dst_proto_addr)?;
+    write!(w, "\nPacket in Error: {} octets", msg.packet().len())?;
+    // The packet in error is an NHRP packet itself, show it if it makes sense
+    if let Ok(inner) = NhrpMessage::from_bytes(msg.packet()) {
+        let mut w = Indented::new(w.f);
+        write!(w, "\n{}", inner)?;
+    }
+    Ok(())
+}
+
+fn write_traffic_indication(f: &mut fmt::Formatter<'_>, msg: &TrafficIndicationMessage) -> fmt::Result {
+    let header = msg.header();
+    if f.alternate() {
+        write!(f, "Traffic Indication {}, ", header.code)?;
+        write_route(f, &header.src_nbma_addr, &header.src_proto_addr, &header.dst_proto_addr)?;
+        return write!(f, ", {} octet packet", msg.packet().len());
+    }
+
+    f.write_str("Traffic Indication")?;
+    let mut w = Indented::new(f);
+    write!(w, "\nTraffic Code: {}", Coded(header.code))?;
+    write_addresses(&mut w, &header.src_nbma_addr, &header.src_proto_addr, &header.dst_proto_addr)?;
+    write!(w, "\nTriggering Packet: {} octets", msg.packet().len())
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Extension::*;
+        let cies: &[ClientInformationEntry] = match self {
+            ResponderAddress { cie, .. } => cie.as_slice(),
+            ForwardTransitNhsRecord { cies, .. }
+            | ReverseTransitNhsRecord { cies, .. }
+            | NatAddress { cies, .. } => cies,
+            _ => &[],
+        };
+        let compulsory = if self.compulsory() && *self != EndOfExtensions {
+            " (compulsory)"
+        } else {
+            ""
+        };
+
+        if f.alternate() {
+            write!(f, "{}{}", self.etype(), compulsory)?;
+            return match self {
+                Authentication { spi, .. } => write!(f, " SPI {}", spi),
+                VendorPrivate { vendor_id, data, .. } => {
+                    f.write_char(' ')?;
+                    write_hex(f, vendor_id)?;
+                    write!(f, ", {} octets", data.len())
+                },
+                Other { data, .. } => write!(f, " {} octets", data.len()),
+                _ if !cies.is_empty() => {
+                    f.write_char(' ')?;
+                    write_cies(f, cies)
+                },
+                _ => Ok(()),
+            };
+        }
+
+        write!(f, "Extension: {}{}", self.etype(), compulsory)?;
+        let mut w = Indented::new(f);
+        match self {
+            Authentication { spi, data, .. } => {
+                write!(w, "\nSPI: {}", spi)?;
+                w.write_str("\nData: ")?;
+                write_hex(&mut w, data)?;
+            },
+            VendorPrivate { vendor_id, data, .. } => {
+                w.write_str("\nVendor ID: ")?;
+                write_hex(&mut w, vendor_id)?;
+                w.write_str("\nData: ")?;
+                write_hex(&mut w, data)?;
+            },
+            Other { data, .. } => {
+                w.write_str("\nData: ")?;
+                write_hex(&mut w, data)?;
+            },
+            _ => {},
+        }
+        for cie in cies {
+            write!(w, "\n{}", cie)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NhrpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#}, hop count {}", self.operation, self.header.hopcount())?;
+            let extensions = self.extensions.iter().filter(|e| **e != Extension::EndOfExtensions);
+            for (index, extension) in extensions.enumerate() {
+                f.write_str(if index == 0 { ", extensions " } else { "; " })?;
+                write!(f, "{:#}", extension)?;
+            }
+            return Ok(());
+        }
+
+        write!(f, "Next Hop Resolution Protocol ({})", self.header.optype())?;
+        let mut w = Indented::new(f);
+        write!(w, "\nAddress Family: {}", Coded(self.header.afn()))?;
+        write!(w, "\nProtocol Type: {}", self.header.protocol_type())?;
+        write!(w, "\nHop Count: {}", self.header.hopcount())?;
+        write!(w, "\nPacket Type: {}", Coded(self.header.optype()))?;
+        write!(w, "\n{}", self.operation)?;
+        for extension in self.extensions.iter() {
+            write!(w, "\n{}", extension)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+use crate::{Emitable, NhrpBuffer, NhrpMessage};
+use super::buffer::{ExtensionBuffer, EXTENSION_HEADER_LEN};
+use super::extension::{Extension, AUTHENTICATION, END_OF_EXTENSIONS};
+
+/// SPI for a cleartext password (RFC 2332, 5.3.4.1)
+pub const SPI_CLEARTEXT: u16 = 1;
+
+/// Length of the Reserved and SPI fields preceding the authentication data
+const AUTH_HEADER_LEN: usize = 4;
+const CHECKSUM: Range<usize> = 12..14;
+/// Output length of HMAC-SHA-256
+const DIGEST_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("message carries no authentication extension")]
+    Missing,
+    #[error("message is authenticated with SPI {found}, expected SPI {expected}")]
+    SpiMismatch { expected: u16, found: u16 },
+    #[error("authentication data does not match")]
+    Mismatch,
+    #[error("message could not be inspected for authentication")]
+    Parse(#[source] #[from] crate::Error),
+}
+
+/// Credentials used to sign outgoing and verify incoming messages.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Authenticator {
+    /// Cleartext password, sent as the authentication data with SPI 1. This is what Cisco and
+    /// opennhrp implement; it keeps out misconfigured peers but not an attacker on the path.
+    Cleartext(Vec<u8>),
+
+    /// HMAC-SHA-256 over the whole packet, keyed with `key`. The digest is calculated with the
+    /// checksum and the authentication data set to zero.
+    KeyedHash { spi: u16, key: Vec<u8> },
+}
+
+impl Authenticator {
+    pub fn spi(&self) -> u16 {
+        match *self {
+            Authenticator::Cleartext(_) => SPI_CLEARTEXT,
+            Authenticator::KeyedHash { spi, .. } => spi,
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        match self {
+            Authenticator::Cleartext(password) => password.len(),
+            Authenticator::KeyedHash { .. } => DIGEST_LEN,
+        }
+    }
+
+    /// Verify the authentication extension of the raw NHRP packet in `packet`.
+    ///
+    /// This works on the bytes as received rather than a parsed `NhrpMessage` since keyed
+    /// hashes have to be calculated over exactly what the peer sent.
+    pub fn verify(&self, packet: &[u8]) -> Result<(), AuthError> {
+        let (spi, data) = find_authentication(packet)?.ok_or(AuthError::Missing)?;
+        if spi != self.spi() {
+            return Err(AuthError::SpiMismatch { expected: self.spi(), found: spi });
+        }
+
+        let valid = match self {
+            Authenticator::Cleartext(password) => packet[data].ct_eq(password),
+            Authenticator::KeyedHash { key, .. } => {
+                let digest = keyed_digest(key, packet, data.clone());
+                packet[data].ct_eq(&digest)
+            },
+        };
+
+        if valid.into() {
+            Ok(())
+        } else {
+            Err(AuthError::Mismatch)
+        }
+    }
+}
+
+impl core::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Never leak secrets into logs
+        match self {
+            Authenticator::Cleartext(_) => f.write_str("Cleartext(..)"),
+            Authenticator::KeyedHash { spi, .. } => f.debug_struct("KeyedHash")
+                .field("spi", spi)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Locate the authentication extension, returning its SPI and the range of the authentication
+/// data in `packet`.
+fn find_authentication(packet: &[u8]) -> crate::Result<Option<(u16, Range<usize>)>> {
+    let buffer = NhrpBuffer::new_checked(packet)?;
+    let mut position = match buffer.extoffset() as usize {
+        0 => return Ok(None),
+        offset => offset,
+    };
+    let end = buffer.length() as usize;
+
+    while position < end {
+        let ext = ExtensionBuffer::new_checked(&packet[position..end])?;
+        let etype = ext.extensiontype();
+        if etype == END_OF_EXTENSIONS {
+            break;
+        }
+        if etype == AUTHENTICATION {
+            let payload = ext.payload();
+            if payload.len() < AUTH_HEADER_LEN {
+                return Err(crate::Error::Truncated);
+            }
+            let spi = u16::from_be_bytes([payload[2], payload[3]]);
+            let start = position + EXTENSION_HEADER_LEN + AUTH_HEADER_LEN;
+            let data = start..(position + ext.length());
+            return Ok(Some((spi, data)));
+        }
+        position += ext.length();
+    }
+
+    Ok(None)
+}
+
+fn keyed_digest(key: &[u8], packet: &[u8], data: Range<usize>) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .expect("HMAC accepts keys of any length");
+    let len = NhrpBuffer::new(packet).length() as usize;
+
+    mac.update(&packet[..CHECKSUM.start]);
+    mac.update(&[0; 2]);
+    mac.update(&packet[CHECKSUM.end..data.start]);
+    mac.update(&vec![0; data.len()]);
+    mac.update(&packet[data.end..len]);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl NhrpMessage {
+    /// Attach an authentication extension, replacing any existing one.
+    ///
+    /// For keyed hashes the digest covers the message as it is now, so this must be the last
+    /// modification before the message is emitted.
+    pub fn authenticate(&mut self, auth: &Authenticator) {
+        self.extensions.retain(|e| e.etype() != AUTHENTICATION);
+
+        let position = self.extensions.iter()
+            .position(|e| *e == Extension::EndOfExtensions)
+            .unwrap_or(self.extensions.len());
+        self.extensions.insert(position, Extension::Authentication {
+            compulsory: true,
+            spi: auth.spi(),
+            data: vec![0; auth.data_len()],
+        });
+
+        let data = match auth {
+            Authenticator::Cleartext(password) => password.clone(),
+            Authenticator::KeyedHash { key, .. } => {
+                let mut packet = vec![0; self.buffer_len()];
+                self.emit(&mut packet);
+                let (_, range) = find_authentication(&packet)
+                    .expect("emitted messages are well-formed")
+                    .expect("authentication extension was just added");
+                keyed_digest(key, &packet, range)
+            },
+        };
+
+        if let Some(Extension::Authentication { data: d, .. }) = self.extensions.get_mut(position) {
+            *d = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Operation;
+
+    fn purge() -> NhrpMessage {
+        let purge = crate::PurgeMessage::builder(
+            core::net::Ipv4Addr::new(198, 51, 100, 5),
+            core::net::Ipv4Addr::new(10, 0, 0, 2).into(),
+            core::net::Ipv4Addr::new(10, 0, 0, 1).into(),
+        ).build().unwrap();
+        NhrpMessage::builder(Operation::PurgeRequest(purge))
+            .extension(Extension::ResponderAddress { compulsory: true, cie: None })
+            .extension(Extension::EndOfExtensions)
+            .build().unwrap()
+    }
+
+    fn emit(msg: &NhrpMessage) -> Vec<u8> {
+        let mut packet = vec![0; msg.buffer_len()];
+        msg.emit(&mut packet);
+        packet
+    }
+
+    #[test]
+    fn finds_authentication_data() {
+        let mut msg = purge();
+        msg.authenticate(&Authenticator::Cleartext(b"secret".to_vec()));
+        let packet = emit(&msg);
+
+        let (spi, data) = find_authentication(&packet).unwrap().unwrap();
+        assert_eq!(spi, SPI_CLEARTEXT);
+        assert_eq!(&packet[data.clone()], b"secret");
+        // Responder Address, then the authentication header, then the data
+        let extoffset = NhrpBuffer::new(&packet[..]).extoffset() as usize;
+        assert_eq!(data.start, extoffset + 2 * EXTENSION_HEADER_LEN + AUTH_HEADER_LEN);
+    }
+
+    #[test]
+    fn finds_no_authentication() {
+        let msg = purge();
+        assert_eq!(find_authentication(&emit(&msg)).unwrap(), None);
+
+        let mut bare = msg;
+        bare.extensions.clear();
+        let packet = emit(&bare);
+        assert_eq!(NhrpBuffer::new(&packet[..]).extoffset(), 0);
+        assert_eq!(find_authentication(&packet).unwrap(), None);
+    }
+
+    #[test]
+    fn stops_at_end_of_extensions() {
+        let mut msg = purge();
+        msg.extensions.push(Extension::Authentication { compulsory: true, spi: SPI_CLEARTEXT, data: vec![1] });
+        assert_eq!(find_authentication(&emit(&msg)).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_authentication_header() {
+        let mut msg = purge();
+        msg.authenticate(&Authenticator::Cleartext(Vec::new()));
+        let mut packet = emit(&msg);
+        // Cut the authentication extension's payload to the Reserved field
+        let (_, data) = find_authentication(&packet).unwrap().unwrap();
+        let length = data.start - EXTENSION_HEADER_LEN - AUTH_HEADER_LEN + 2;
+        packet[length..length + 2].copy_from_slice(&2u16.to_be_bytes());
+        assert!(matches!(find_authentication(&packet), Err(crate::Error::Truncated)));
+    }
+}
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
+use alloc::format;
 use crate::{Field, Rest, Result, Error};
 use super::extension::{ExtensionType, END_OF_EXTENSIONS};
 
 const CUTYPE: Field = 0..2;
-const LENGTH: Field = 2..4;
+pub(crate) const LENGTH: Field = 2..4;
 const PAYLOAD: Rest = 4..;
 
 pub const EXTENSION_HEADER_LEN: usize = PAYLOAD.start;
@@ -14,7 +15,7 @@ pub struct ExtensionBuffer<T> {
 
 impl<T: AsRef<[u8]>> ExtensionBuffer<T> {
     pub fn new(buffer: T) -> ExtensionBuffer<T> {
-        ExtensionBuffer { buffer: buffer }
+        ExtensionBuffer { buffer }
     }
 
     pub fn new_checked(buffer: T) -> Result<ExtensionBuffer<T>> {
@@ -25,11 +26,15 @@ impl<T: AsRef<[u8]>> ExtensionBuffer<T> {
 
     fn check_buffer_length(&self) -> Result<()> {
         let len = self.buffer.as_ref().len();
-        if len < LENGTH.end || len < self.length() as usize {
-            Err(Error::Truncated)
-        } else {
-            Ok(())
+        if len < LENGTH.end {
+            return Err(Error::malformed(0, len, "header",
+                format_args!("at least {} octets", EXTENSION_HEADER_LEN), format_args!("{} octets", len)));
         }
+        if len < self.length() {
+            return Err(Error::malformed(LENGTH.start, LENGTH.len(), "length",
+                format_args!("at most {}", len - EXTENSION_HEADER_LEN), self.payload_length()));
+        }
+        Ok(())
     }
 
     fn cutype(&self) -> u16 {
@@ -38,7 +43,7 @@ impl<T: AsRef<[u8]>> ExtensionBuffer<T> {
     }
 
     pub fn extensiontype(&self) -> ExtensionType {
-        (self.cutype() & 0x3FFF).into()
+        ExtensionType::from_bits(self.cutype())
     }
 
     pub fn compulsory(&self) -> bool {
@@ -49,22 +54,23 @@ impl<T: AsRef<[u8]>> ExtensionBuffer<T> {
         let data = self.buffer.as_ref();
         u16::from_be_bytes(data[LENGTH].try_into().unwrap())
     }
-    pub fn length(&self) -> u16 {
-        self.payload_length() + 4
+    /// Length of the extension including its header
+    pub fn length(&self) -> usize {
+        PAYLOAD.start + self.payload_length() as usize
     }
 }
 
 impl<'a, T: AsRef<[u8]> + ?Sized> ExtensionBuffer<&'a T> {
     pub fn payload(&self) -> &'a [u8] {
-        let range = PAYLOAD.start..(PAYLOAD.start + self.length() as usize);
+        let range = PAYLOAD.start..(PAYLOAD.start + self.payload_length() as usize);
         let data = self.buffer.as_ref();
         &data[range]
     }
 }
 
-impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> ExtensionBuffer<&'a mut T> {
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> ExtensionBuffer<&mut T> {
     pub fn payload_mut(&mut self) -> &mut [u8] {
-        let range = PAYLOAD.start..(PAYLOAD.start + self.length() as usize);
+        let range = PAYLOAD.start..(PAYLOAD.start + self.payload_length() as usize);
         let data = self.buffer.as_mut();
         &mut data[range]
     }
@@ -96,6 +102,7 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> ExtensionBuffer<T> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExtensionIterator<T> {
     position: usize,
+    index: usize,
     buffer: T,
 }
 
@@ -103,7 +110,8 @@ impl<T> ExtensionIterator<T> {
     pub fn new(buffer: T) -> Self {
         ExtensionIterator {
             position: 0,
-            buffer: buffer,
+            index: 0,
+            buffer,
         }
     }
 }
@@ -119,17 +127,19 @@ impl<'a, T: AsRef<[u8]> + ?Sized + 'a> Iterator for ExtensionIterator<&'a T> {
 
         match ExtensionBuffer::new_checked(&self.buffer.as_ref()[self.position..]) {
             Ok(extbuffer) => {
-                self.position += extbuffer.length() as usize;
+                self.position += extbuffer.length();
+                self.index += 1;
                 // Fuse buffer on EoE
                 if extbuffer.extensiontype() == END_OF_EXTENSIONS {
                     self.position = self.buffer.as_ref().len();
                 }
                 Some(Ok(extbuffer))
             },
-            Err(_) => {
+            Err(e) => {
                 // Fuse the iterator, invalid buffers can only happen if stuff goes really wrong.
+                let e = e.within(self.position, &format!("extension[{}]", self.index));
                 self.position = self.buffer.as_ref().len();
-                None
+                Some(Err(e))
             }
         }
     }
@@ -1,23 +1,86 @@
-use crate::{Parseable, Emitable, Result};
+use alloc::vec::Vec;
+use crate::{Parseable, Emitable, Result, Error};
+use crate::cie::{self, ClientInformationEntry};
 
-use super::buffer::{ExtensionBuffer, EXTENSION_HEADER_LEN};
+use super::buffer::{ExtensionBuffer, EXTENSION_HEADER_LEN, LENGTH as LENGTH_FIELD};
+
+const AUTH_SPI_LEN: usize = 4;
+const VENDOR_ID_LEN: usize = 3;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Extension {
     EndOfExtensions,
 
+    /// Address of the station that generated a reply (RFC 2332, 5.3.1)
+    ResponderAddress {
+        compulsory: bool,
+        cie: Option<ClientInformationEntry>,
+    },
+
+    /// NHSes a request passed through on its way to the responder (RFC 2332, 5.3.2)
+    ForwardTransitNhsRecord {
+        compulsory: bool,
+        cies: Vec<ClientInformationEntry>,
+    },
+
+    /// NHSes a reply passed through on its way back to the requester (RFC 2332, 5.3.3)
+    ReverseTransitNhsRecord {
+        compulsory: bool,
+        cies: Vec<ClientInformationEntry>,
+    },
+
+    /// Authentication extension (RFC 2332, 5.3.4)
+    ///
+    /// `data` holds everything following the SPI, i.e. the source address (if the sender
+    /// includes one) and the authentication data proper.
+    Authentication {
+        compulsory: bool,
+        spi: u16,
+        #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
+        data: Vec<u8>,
+    },
+
+    /// Vendor-Private extension (RFC 2332, 5.3.5)
+    VendorPrivate {
+        compulsory: bool,
+        #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
+        vendor_id: [u8; 3],
+        #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
+        data: Vec<u8>,
+    },
+
+    /// Cisco DMVPN NAT address extension
+    ///
+    /// In requests this carries the NBMA address the sender believes the receiver has, in
+    /// replies the NBMA address the responder saw the request coming from. Comparing the two
+    /// lets either side detect NAT on the path.
+    NatAddress {
+        compulsory: bool,
+        cies: Vec<ClientInformationEntry>,
+    },
+
     Other {
         etype: ExtensionType,
         compulsory: bool,
+        #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
         data: Vec<u8>
     }
 }
 
 impl Extension {
+    /// Length of the extension payload, not including the extension header
     pub fn length(&self) -> usize {
         use self::Extension::*;
         match self {
             EndOfExtensions => 0,
+            ResponderAddress { cie, .. } => cie.buffer_len(),
+            ForwardTransitNhsRecord { cies, .. } => cies.buffer_len(),
+            ReverseTransitNhsRecord { cies, .. } => cies.buffer_len(),
+            Authentication { data, .. } => AUTH_SPI_LEN + data.len(),
+            VendorPrivate { data, .. } => VENDOR_ID_LEN + data.len(),
+            NatAddress { cies, .. } => cies.buffer_len(),
             Other { data, .. } => data.len(),
         }
     }
@@ -26,29 +89,55 @@ impl Extension {
         use self::Extension::*;
         match *self {
             EndOfExtensions => true,
+            ResponderAddress { compulsory, .. } => compulsory,
+            ForwardTransitNhsRecord { compulsory, .. } => compulsory,
+            ReverseTransitNhsRecord { compulsory, .. } => compulsory,
+            Authentication { compulsory, .. } => compulsory,
+            VendorPrivate { compulsory, .. } => compulsory,
+            NatAddress { compulsory, .. } => compulsory,
             Other { compulsory, .. } => compulsory
         }
     }
 
     pub fn etype(&self) -> ExtensionType {
         use self::Extension::*;
-        use self::ExtensionType::*;
         match *self {
-            EndOfExtensions => NHRP(0),
+            EndOfExtensions => END_OF_EXTENSIONS,
+            ResponderAddress { .. } => RESPONDER_ADDRESS,
+            ForwardTransitNhsRecord { .. } => FORWARD_TRANSIT_NHS_RECORD,
+            ReverseTransitNhsRecord { .. } => REVERSE_TRANSIT_NHS_RECORD,
+            Authentication { .. } => AUTHENTICATION,
+            VendorPrivate { .. } => VENDOR_PRIVATE,
+            NatAddress { .. } => NAT_ADDRESS,
             Other { etype, .. } => etype
         }
     }
 
-    pub fn data(&self) -> &[u8] {
+    fn emit_payload(&self, buffer: &mut [u8]) {
         use self::Extension::*;
         match self {
-            EndOfExtensions => &[],
-            Other { data, .. } => &data[..]
+            EndOfExtensions => {},
+            ResponderAddress { cie, .. } => cie.emit(buffer),
+            ForwardTransitNhsRecord { cies, .. } => cies.emit(buffer),
+            ReverseTransitNhsRecord { cies, .. } => cies.emit(buffer),
+            Authentication { spi, data, .. } => {
+                buffer[0..2].copy_from_slice(&[0, 0]);
+                buffer[2..AUTH_SPI_LEN].copy_from_slice(&spi.to_be_bytes());
+                buffer[AUTH_SPI_LEN..].copy_from_slice(data);
+            },
+            VendorPrivate { vendor_id, data, .. } => {
+                buffer[0..VENDOR_ID_LEN].copy_from_slice(vendor_id);
+                buffer[VENDOR_ID_LEN..].copy_from_slice(data);
+            },
+            NatAddress { cies, .. } => cies.emit(buffer),
+            Other { data, .. } => buffer.copy_from_slice(data),
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "u16", try_from = "u16"))]
 pub enum ExtensionType {
     NHRP(u16),
     ATM(u16),
@@ -56,17 +145,36 @@ pub enum ExtensionType {
     Experimental(u16),
 }
 pub const END_OF_EXTENSIONS: ExtensionType = ExtensionType::NHRP(0);
+pub const RESPONDER_ADDRESS: ExtensionType = ExtensionType::NHRP(3);
+pub const FORWARD_TRANSIT_NHS_RECORD: ExtensionType = ExtensionType::NHRP(4);
+pub const REVERSE_TRANSIT_NHS_RECORD: ExtensionType = ExtensionType::NHRP(5);
+pub const AUTHENTICATION: ExtensionType = ExtensionType::NHRP(7);
+pub const VENDOR_PRIVATE: ExtensionType = ExtensionType::NHRP(8);
+/// Cisco-specific, not assigned by RFC 2332
+pub const NAT_ADDRESS: ExtensionType = ExtensionType::NHRP(9);
 
-impl From<u16> for ExtensionType {
-    fn from(value: u16) -> ExtensionType {
+impl ExtensionType {
+    /// Decode the type from the lower 14 bits of `value`; the compulsory and reserved bits
+    /// are ignored.
+    pub(crate) fn from_bits(value: u16) -> ExtensionType {
         use self::ExtensionType::*;
+        let value = value & 0x3FFF;
         match value {
             0x0000..=0x0FFF => NHRP(value),
             0x1000..=0x11FF => ATM(value),
             0x1200..=0x37FF => IETF(value),
-            0x3800..=0x3FFF => Experimental(value),
-            _ => panic!("Value of {} is invalid for ExtensionType, maximum is 0x3FFF.", value),
+            _ => Experimental(value),
+        }
+    }
+}
+impl TryFrom<u16> for ExtensionType {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<ExtensionType> {
+        if value > 0x3FFF {
+            return Err(Error::InvalidExtensionType(value));
         }
+        Ok(ExtensionType::from_bits(value))
     }
 }
 impl From<ExtensionType> for u16 {
@@ -81,16 +189,67 @@ impl From<ExtensionType> for u16 {
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<Extension> for ExtensionBuffer<&'a T> {
+/// Parse the CIEs in the payload of an extension, locating errors relative to the extension
+fn parse_cies(payload: &[u8]) -> Result<Vec<ClientInformationEntry>> {
+    cie::parse_cies(payload).map_err(|e| e.within(EXTENSION_HEADER_LEN, ""))
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<Extension> for ExtensionBuffer<&T> {
     fn parse(&self) -> Result<Extension> {
         use self::Extension::*;
-        use self::ExtensionType::*;
+        let compulsory = self.compulsory();
+        let payload = self.payload();
         Ok(match self.extensiontype() {
-            NHRP(0) => EndOfExtensions,
+            END_OF_EXTENSIONS => EndOfExtensions,
+            RESPONDER_ADDRESS => {
+                let mut cies = parse_cies(payload)?;
+                if cies.len() > 1 {
+                    return Err(Error::malformed(LENGTH_FIELD.start, LENGTH_FIELD.len(), "length",
+                        "at most one CIE", format_args!("{} CIEs", cies.len())));
+                }
+                ResponderAddress {
+                    compulsory,
+                    cie: cies.pop(),
+                }
+            },
+            FORWARD_TRANSIT_NHS_RECORD => ForwardTransitNhsRecord {
+                compulsory,
+                cies: parse_cies(payload)?,
+            },
+            REVERSE_TRANSIT_NHS_RECORD => ReverseTransitNhsRecord {
+                compulsory,
+                cies: parse_cies(payload)?,
+            },
+            AUTHENTICATION => {
+                if payload.len() < AUTH_SPI_LEN {
+                    return Err(Error::malformed(LENGTH_FIELD.start, LENGTH_FIELD.len(), "length",
+                        format_args!("at least {}", AUTH_SPI_LEN), payload.len()));
+                }
+                Authentication {
+                    compulsory,
+                    spi: u16::from_be_bytes(payload[2..AUTH_SPI_LEN].try_into().unwrap()),
+                    data: payload[AUTH_SPI_LEN..].to_vec(),
+                }
+            },
+            VENDOR_PRIVATE => {
+                if payload.len() < VENDOR_ID_LEN {
+                    return Err(Error::malformed(LENGTH_FIELD.start, LENGTH_FIELD.len(), "length",
+                        format_args!("at least {}", VENDOR_ID_LEN), payload.len()));
+                }
+                VendorPrivate {
+                    compulsory,
+                    vendor_id: payload[0..VENDOR_ID_LEN].try_into().unwrap(),
+                    data: payload[VENDOR_ID_LEN..].to_vec(),
+                }
+            },
+            NAT_ADDRESS => NatAddress {
+                compulsory,
+                cies: parse_cies(payload)?,
+            },
             etype => Other {
-                etype: etype,
-                compulsory: self.compulsory(),
-                data: self.payload().to_vec(),
+                etype,
+                compulsory,
+                data: payload.to_vec(),
             }
         })
     }
@@ -106,6 +265,6 @@ impl Emitable for Extension {
         buffer.set_extensiontype(self.etype());
         buffer.set_compulsory(self.compulsory());
         buffer.set_length(self.length() as u16);
-        buffer.payload_mut().copy_from_slice(self.data());
+        self.emit_payload(buffer.payload_mut());
     }
 }
@@ -4,3 +4,6 @@ pub use self::buffer::*;
 
 pub mod extension;
 pub use self::extension::*;
+
+pub mod authentication;
+pub use self::authentication::*;
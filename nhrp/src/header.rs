@@ -1,7 +1,107 @@
+use core::fmt;
+use core::net::IpAddr;
+
 use super::{NhrpBuffer, FIXED_HEADER_LEN};
-use crate::{Emitable, Error, Field, Parseable};
+use crate::{Emitable, Error, Parseable};
+
+/// Address Family Number of the NBMA addresses of a packet, as assigned by IANA
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "u16", from = "u16"))]
+pub enum AddressFamily {
+    IPv4,
+    IPv6,
+    NSAP,
+    HDLC,
+    BBN1822,
+    /// All IEEE 802 media, i.e. MAC addresses
+    IEEE802,
+    E163,
+    E164,
+    F69,
+    X121,
+    Unknown(u16),
+}
+
+impl AddressFamily {
+    /// Address family of the NBMA address `addr`
+    pub fn from_ip(addr: &IpAddr) -> AddressFamily {
+        match addr {
+            IpAddr::V4(_) => AddressFamily::IPv4,
+            IpAddr::V6(_) => AddressFamily::IPv6,
+        }
+    }
+
+    /// Length in octets of the addresses of this family, `None` if they vary in length or the
+    /// family is unknown.
+    pub fn address_len(&self) -> Option<usize> {
+        match *self {
+            AddressFamily::IPv4 => Some(4),
+            AddressFamily::IPv6 => Some(16),
+            AddressFamily::NSAP => Some(20),
+            AddressFamily::IEEE802 => Some(6),
+            _ => None,
+        }
+    }
+}
+impl From<u16> for AddressFamily {
+    fn from(value: u16) -> AddressFamily {
+        use AddressFamily::*;
+        match value {
+            1 => IPv4,
+            2 => IPv6,
+            3 => NSAP,
+            4 => HDLC,
+            5 => BBN1822,
+            6 => IEEE802,
+            7 => E163,
+            8 => E164,
+            9 => F69,
+            10 => X121,
+            _ => Unknown(value),
+        }
+    }
+}
+impl From<AddressFamily> for u16 {
+    fn from(value: AddressFamily) -> u16 {
+        use AddressFamily::*;
+        match value {
+            IPv4 => 1,
+            IPv6 => 2,
+            NSAP => 3,
+            HDLC => 4,
+            BBN1822 => 5,
+            IEEE802 => 6,
+            E163 => 7,
+            E164 => 8,
+            F69 => 9,
+            X121 => 10,
+            Unknown(v) => v,
+        }
+    }
+}
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AddressFamily::*;
+        match *self {
+            IPv4 => f.write_str("IPv4"),
+            IPv6 => f.write_str("IPv6"),
+            NSAP => f.write_str("NSAP"),
+            HDLC => f.write_str("HDLC"),
+            BBN1822 => f.write_str("BBN 1822"),
+            IEEE802 => f.write_str("IEEE 802"),
+            E163 => f.write_str("E.163"),
+            E164 => f.write_str("E.164"),
+            F69 => f.write_str("F.69"),
+            X121 => f.write_str("X.121"),
+            Unknown(v) => write!(f, "Unknown ({})", v),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum ProtocolClass {
     NLPID(u8),
     Future(u16),
@@ -13,46 +113,90 @@ impl From<u16> for ProtocolClass {
     fn from(value: u16) -> ProtocolClass {
         use ProtocolClass::*;
 
-        let bytes = value.to_ne_bytes();
+        let bytes = value.to_be_bytes();
         match bytes[0] {
             0x00 => NLPID(bytes[1]),
-            0x01 | 0x02 | 0x03 => Future(u16::from_be_bytes(bytes)),
+            0x01..=0x03 => Future(value),
             0x04 => ATM(bytes[1]),
             0x05 => Private(bytes[1]),
-            _ => Ethertype(u16::from_be_bytes(bytes)),
+            _ => Ethertype(value),
         }
     }
 }
 
-impl From<ProtocolClass> for u16 {
-    fn from(value: ProtocolClass) -> u16 {
+impl ProtocolClass {
+    /// The value as put on the wire. Unlike the `TryFrom` conversion this does not check that
+    /// `Future` and `Ethertype` values are in their range.
+    pub fn value(&self) -> u16 {
         use ProtocolClass::*;
 
-        let bytes: [u8; 2];
-        match value {
-            NLPID(v) => bytes = [0x00, v],
-            Future(v) => {
-                assert!(0x0100 <= v && v <= 0x03FF, "`Future` ProtocolClass has invalid value: {}", v);
-                return v;
-            },
-            ATM(v) => bytes = [0x04, v],
-            Private(v) => bytes = [0x05, v],
-            Ethertype(v) => {
-                assert!(v >= 0x0600, "`Ethertype` ProtocolClass is not a valid Ethertype: {}", v);
-                return v;
-            }
+        match *self {
+            NLPID(v) => u16::from_be_bytes([0x00, v]),
+            Future(v) => v,
+            ATM(v) => u16::from_be_bytes([0x04, v]),
+            Private(v) => u16::from_be_bytes([0x05, v]),
+            Ethertype(v) => v,
         }
+    }
+}
+
+impl TryFrom<ProtocolClass> for u16 {
+    type Error = Error;
 
-        u16::from_be_bytes(bytes)
+    fn try_from(value: ProtocolClass) -> Result<u16, Error> {
+        use ProtocolClass::*;
+
+        match value {
+            Future(v) if !(0x0100..=0x03FF).contains(&v) => Err(Error::InvalidProtocolType(v)),
+            Ethertype(v) if v < 0x0600 => Err(Error::InvalidProtocolType(v)),
+            _ => Ok(value.value()),
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProtocolType {
     pub protype: ProtocolClass,
+    #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
     pub prosnap: [u8; 5],
 }
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ProtocolType> for &'a T {
+impl ProtocolType {
+    pub const IPV4: ProtocolType = ProtocolType::ethertype(0x0800);
+    pub const IPV6: ProtocolType = ProtocolType::ethertype(0x86DD);
+
+    /// Protocol type given by an Ethertype, without a SNAP header
+    pub const fn ethertype(ethertype: u16) -> ProtocolType {
+        ProtocolType {
+            protype: ProtocolClass::Ethertype(ethertype),
+            prosnap: [0; 5],
+        }
+    }
+
+    /// Protocol type of the protocol address `addr`
+    pub fn from_ip(addr: &IpAddr) -> ProtocolType {
+        match addr {
+            IpAddr::V4(_) => ProtocolType::IPV4,
+            IpAddr::V6(_) => ProtocolType::IPV6,
+        }
+    }
+
+    /// Length in octets of the protocol addresses of this type, `None` for protocols other than
+    /// IPv4 and IPv6. These are recognized by their Ethertype or their NLPID.
+    pub fn address_len(&self) -> Option<usize> {
+        match self.protype {
+            ProtocolClass::Ethertype(0x0800) | ProtocolClass::NLPID(0xCC) => Some(4),
+            ProtocolClass::Ethertype(0x86DD) | ProtocolClass::NLPID(0x8E) => Some(16),
+            _ => None,
+        }
+    }
+}
+impl From<IpAddr> for ProtocolType {
+    fn from(value: IpAddr) -> ProtocolType {
+        ProtocolType::from_ip(&value)
+    }
+}
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ProtocolType> for &T {
     fn parse(&self) -> crate::Result<ProtocolType> {
         if self.as_ref().len() < 7 {
             return Err(Error::Truncated);
@@ -65,7 +209,7 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ProtocolType> for &'a T {
 
         Ok(ProtocolType {
             protype: protype.into(),
-            prosnap: prosnap
+            prosnap
         })
     }
 }
@@ -75,13 +219,15 @@ impl Emitable for ProtocolType {
     }
 
     fn emit(&self, buffer: &mut [u8]) {
-        buffer[0..2].copy_from_slice(&u16::to_be_bytes(self.protype.into()));
+        buffer[0..2].copy_from_slice(&u16::to_be_bytes(self.protype.value()));
         let buffer = &mut buffer[2..7];
         buffer.copy_from_slice(&self.prosnap);
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NhrpOp {
     ResolutionRequest,
     ResolutionReply,
@@ -90,6 +236,8 @@ pub enum NhrpOp {
     PurgeRequest,
     PurgeReply,
     ErrorIndication,
+    TrafficIndication,
+    #[cfg_attr(feature = "serde", serde(untagged))]
     Other(u8),
 }
 impl From<u8> for NhrpOp {
@@ -103,6 +251,7 @@ impl From<u8> for NhrpOp {
             5 => PurgeRequest,
             6 => PurgeReply,
             7 => ErrorIndication,
+            8 => TrafficIndication,
             _ => Other(value),
         }
     }
@@ -118,31 +267,34 @@ impl From<NhrpOp> for u8 {
             PurgeRequest => 5,
             PurgeReply => 6,
             ErrorIndication => 7,
+            TrafficIndication => 8,
             Other(value) => value,
         }
     }
 }
 
+/// Owned copy of the fixed header. To inspect a received packet without parsing all of it use
+/// [`NhrpMessageView`](crate::NhrpMessageView) instead.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FixedHeader {
-    afn: u16,
+    afn: AddressFamily,
     protocol_type: ProtocolType,
     hopcount: u8,
     optype: NhrpOp,
 }
 
 impl FixedHeader {
-    pub fn new(afn: u16, protocol_type: ProtocolType, hopcount: u8, optype: NhrpOp) -> FixedHeader {
+    pub fn new(afn: AddressFamily, protocol_type: ProtocolType, hopcount: u8, optype: NhrpOp) -> FixedHeader {
         FixedHeader {
-            afn: afn,
-            protocol_type: protocol_type,
-            hopcount: hopcount,
-            optype: optype,
+            afn,
+            protocol_type,
+            hopcount,
+            optype,
         }
     }
 
-    // FIXME: Use these to do lazy parsing.
-    pub fn afn(&self) -> u16 {
+    pub fn afn(&self) -> AddressFamily {
         self.afn
     }
     pub fn protocol_type(&self) -> ProtocolType {
@@ -160,10 +312,10 @@ impl FixedHeader {
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<FixedHeader> for NhrpBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<FixedHeader> for NhrpBuffer<&T> {
     fn parse(&self) -> crate::Result<FixedHeader> {
         Ok(FixedHeader {
-            afn: self.afn(),
+            afn: self.afn().into(),
             protocol_type: self.protocol_type(),
             hopcount: self.hopcount(),
             optype: self.optype(),
@@ -178,7 +330,7 @@ impl Emitable for FixedHeader {
 
     fn emit(&self, buffer: &mut [u8]) {
         let mut buffer = NhrpBuffer::new(buffer);
-        buffer.set_afn(self.afn);
+        buffer.set_afn(self.afn.into());
         buffer.set_protocol_type(self.protocol_type);
         buffer.set_hopcount(self.hopcount);
         buffer.set_optype(self.optype);
@@ -0,0 +1,31 @@
+//! Serialize raw octets as a hex string, for `#[serde(with = "crate::hex")]`
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer, T: AsRef<[u8]>>(octets: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut hex = String::with_capacity(octets.as_ref().len() * 2);
+    for octet in octets.as_ref() {
+        write!(hex, "{:02x}", octet).unwrap();
+    }
+    serializer.serialize_str(&hex)
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>,
+          T: TryFrom<Vec<u8>>,
+{
+    let hex = String::deserialize(deserializer)?;
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(D::Error::custom(format_args!("{:?} is not a hex string", hex)));
+    }
+    let octets: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect();
+    let len = octets.len();
+    T::try_from(octets).map_err(|_| D::Error::invalid_length(len, &"the length of the field"))
+}
@@ -4,6 +4,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+//! Parsing and emitting NHRP (RFC 2332) messages
+//!
+//! The crate is `no_std` and only needs `alloc`. Addresses are `core::net` types, which are the
+//! same types as in `std::net`. The default `std` feature only enables `std` support in the
+//! dependencies.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+use core::fmt;
 use core::ops::{Range, RangeFrom};
 use thiserror::Error;
 
@@ -26,12 +38,24 @@ pub mod header;
 pub use self::header::*;
 pub mod message;
 pub use self::message::*;
+pub mod view;
+pub use self::view::*;
+pub mod builder;
+pub use self::builder::*;
+pub mod validate;
+pub use self::validate::*;
 pub mod operation;
 pub use self::operation::*;
 
 pub mod extensions;
 pub use self::extensions::*;
 
+mod display;
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "serde")]
+mod hex;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("given buffer does not contain a full message")]
@@ -40,9 +64,87 @@ pub enum Error {
     Exhausted,
     #[error("this functionality is not yet implemented")]
     NotImplemented,
+    #[error("checksum mismatch, packet has {found:#06x} but contents sum to {expected:#06x}")]
+    Checksum { expected: u16, found: u16 },
+    #[error(transparent)]
+    Malformed(Malformed),
+    #[error("operation type {0} is not supported")]
+    UnknownOperation(u8),
+    #[error("address type & length {0:#04x} has the reserved bit set")]
+    InvalidAddrTL(u8),
+    #[error("extension type {0:#06x} is out of range, the maximum is 0x3fff")]
+    InvalidExtensionType(u16),
+    #[error("protocol type {0:#06x} is not valid for its protocol class")]
+    InvalidProtocolType(u16),
+    #[error("{0:?} is not a valid NBMA address")]
+    InvalidNbmaAddress(String),
+    #[error(transparent)]
+    Build(BuildError),
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+impl Error {
+    pub(crate) fn malformed(offset: usize,
+                            len: usize,
+                            field: impl Into<String>,
+                            expected: impl fmt::Display,
+                            found: impl fmt::Display,
+    ) -> Error {
+        Error::Malformed(Malformed {
+            offset,
+            len,
+            field: field.into(),
+            expected: expected.to_string(),
+            found: found.to_string(),
+        })
+    }
+
+    /// Locate an error of a structure nested at `offset` in its parent, prefixing the field
+    /// with `parent` if that is not empty.
+    pub(crate) fn within(self, offset: usize, parent: &str) -> Error {
+        match self {
+            Error::Malformed(mut m) => {
+                m.offset += offset;
+                if !parent.is_empty() {
+                    m.field = format!("{}.{}", parent, m.field);
+                }
+                Error::Malformed(m)
+            },
+            e => e,
+        }
+    }
+
+    /// Octets of the packet the error refers to, if it can be pinned down
+    pub fn span(&self) -> Option<Range<usize>> {
+        match *self {
+            Error::Malformed(ref m) => Some(m.span()),
+            Error::Checksum { .. } => Some(buffer::CHECKSUM),
+            Error::UnknownOperation(_) => Some(buffer::OPTYPE..(buffer::OPTYPE + 1)),
+            _ => None,
+        }
+    }
+}
+
+/// A field of a packet that failed to parse
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid {field} at offset {offset}: expected {expected}, found {found}")]
+pub struct Malformed {
+    /// Offset of the field in octets, counted from the start of the packet
+    pub offset: usize,
+    /// Length of the field in octets
+    pub len: usize,
+    /// Path to the field, e.g. `CIE[2].cli_proto_addr_len`
+    pub field: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl Malformed {
+    pub fn span(&self) -> Range<usize> {
+        self.offset..(self.offset + self.len)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
 pub trait Parseable<T> {
     fn parse(&self) -> Result<T>;
 }
@@ -61,6 +163,14 @@ pub trait Emitable {
     /// [`buffer_len()`](trait.Emitable.html#method.buffer_len) to check how big the storage needs
     /// to be.
     fn emit(&self, buffer: &mut [u8]);
+
+    /// Serialize this type and append the serialized data to `buffer`, growing it as needed.
+    #[cfg(feature = "bytes")]
+    fn emit_to(&self, buffer: &mut bytes::BytesMut) {
+        let start = buffer.len();
+        buffer.resize(start + self.buffer_len(), 0);
+        self.emit(&mut buffer[start..]);
+    }
 }
 
 impl<T: Emitable> Emitable for Option<T> {
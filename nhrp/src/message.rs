@@ -1,9 +1,15 @@
+use alloc::{format, vec::Vec};
+use core::fmt;
 use crate::{Error, Parseable, Emitable, Result};
-use super::{NhrpBuffer, FixedHeader};
-use super::extensions::{Extension, ExtensionIterator};
-use super::operation::Operation;
+use super::{ChecksumPolicy, NhrpBuffer, FixedHeader, NhrpOp, FIXED_HEADER_LEN};
+use super::extensions::{Extension, ExtensionIterator, EXTENSION_HEADER_LEN};
+use super::extensions::{FORWARD_TRANSIT_NHS_RECORD, NAT_ADDRESS, RESPONDER_ADDRESS, REVERSE_TRANSIT_NHS_RECORD};
+use super::operation::{AddrTL, Operation, ErrorIndicationBuffer, OperationBuffer};
+use super::operation::buffer::{SHTL, SRC_PROTO_LEN, DST_PROTO_LEN};
+use super::cie::buffer::{CieIterator, CLI_ADDR_TL, CLI_PROTO_LEN};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NhrpMessage {
     pub header: FixedHeader,
     pub operation: Operation,
@@ -23,25 +29,41 @@ impl NhrpMessage {
         (self.header, self.operation, self.extensions)
     }
 
+    /// Extensions must be terminated by an End of Extensions; add one when emitting unless the
+    /// caller already did.
+    fn needs_end_of_extensions(&self) -> bool {
+        !self.extensions.is_empty()
+            && self.extensions.last() != Some(&Extension::EndOfExtensions)
+    }
+
     pub fn to_bytes(&self, buffer: &mut [u8]) -> crate::Result<usize> {
-        if self.buffer_len() as usize > buffer.len() {
+        if self.buffer_len() > buffer.len() {
             Err(Error::Exhausted)
         } else {
             self.emit(buffer);
-            Ok(self.buffer_len() as usize)
+            Ok(self.buffer_len())
         }
     }
 
+    /// Parse a message without looking at its checksum.
     pub fn from_bytes(buffer: &[u8]) -> Result<Self> {
         match NhrpBuffer::new_checked(buffer) {
             Ok(buffer) => buffer.parse(),
             Err(e) => Err(e),
         }
     }
+
+    /// Parse a message, rejecting it with [`Error::Checksum`] if its checksum does not satisfy
+    /// `policy`.
+    pub fn from_bytes_checked(buffer: &[u8], policy: ChecksumPolicy) -> Result<Self> {
+        let buffer = NhrpBuffer::new_checked(buffer)?;
+        buffer.verify_checksum(policy)?;
+        buffer.parse()
+    }
 }
 
 use super::NhrpOp::*;
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NhrpMessage> for NhrpBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<NhrpMessage> for NhrpBuffer<&T> {
     fn parse(&self) -> crate::Result<NhrpMessage> {
         let header = <Self as Parseable<FixedHeader>>::parse(self)?;
 
@@ -49,91 +71,198 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NhrpMessage> for NhrpBuffer<&'a T> {
         let operation = match header.optype() {
             ResolutionRequest => {
                 let msg: ResolutionRequestMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::ResolutionRequest(msg)
             },
             ResolutionReply => {
                 let msg: ResolutionReplyMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::ResolutionReply(msg)
             },
             RegistrationRequest => {
                 let msg: RegistrationRequestMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::RegistrationRequest(msg)
             },
             RegistrationReply => {
                 let msg: RegistrationReplyMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::RegistrationReply(msg)
             },
             PurgeRequest => {
                 let msg: PurgeMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::PurgeRequest(msg)
             },
             PurgeReply => {
                 let msg: PurgeMessage
-                    = OperationBuffer::new(&self.payload()).parse()?;
+                    = OperationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
                 Operation::PurgeReply(msg)
             },
-            _ => unimplemented!(),
+            ErrorIndication => {
+                let msg: ErrorIndicationMessage
+                    = ErrorIndicationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
+                Operation::ErrorIndication(msg)
+            },
+            TrafficIndication => {
+                let msg: TrafficIndicationMessage
+                    = ErrorIndicationBuffer::new_checked(self.payload()).and_then(|b| b.parse())
+                        .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
+                Operation::TrafficIndication(msg)
+            },
+            Other(op) => return Err(Error::UnknownOperation(op)),
         };
+        let lengths = AddressLengths::new(&header);
+        lengths.check_operation(header.optype(), self.payload())
+            .map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
 
-        let extensioni = ExtensionIterator::new(self.extensions());
+        let offset = FIXED_HEADER_LEN + self.payload().len();
+        let mut position = 0;
         let mut extensions = Vec::new();
-        for e in extensioni {
-            match e {
-                // FIXME: Gracefully handle extensions we don't recognice but aren't compulsory
-                Ok(e) => extensions.push(e.parse()?),
-                Err(e) => return Err(e),
-            }
+        for (index, e) in ExtensionIterator::new(self.extensions()).enumerate() {
+            let e = e.map_err(|e| e.within(offset, ""))?;
+            // FIXME: Gracefully handle extensions we don't recognice but aren't compulsory
+            let parsed = e.parse()
+                .and_then(|parsed| {
+                    let etype = e.extensiontype();
+                    if etype == RESPONDER_ADDRESS || etype == FORWARD_TRANSIT_NHS_RECORD
+                        || etype == REVERSE_TRANSIT_NHS_RECORD || etype == NAT_ADDRESS
+                    {
+                        lengths.check_cies(e.payload())
+                            .map_err(|e| e.within(EXTENSION_HEADER_LEN, ""))?;
+                    }
+                    Ok(parsed)
+                })
+                .map_err(|e| e.within(offset + position, &format!("extension[{}]", index)))?;
+            position += e.length();
+            extensions.push(parsed);
         }
 
         Ok(NhrpMessage::new(header, operation, extensions))
     }
 }
 
+/// Lengths of NBMA and protocol addresses implied by the address family and protocol type of a
+/// packet, `None` where they can vary.
+struct AddressLengths {
+    header: FixedHeader,
+    nbma: Option<usize>,
+    proto: Option<usize>,
+}
+
+impl AddressLengths {
+    fn new(header: &FixedHeader) -> Self {
+        AddressLengths {
+            header: *header,
+            nbma: header.afn().address_len(),
+            proto: header.protocol_type().address_len(),
+        }
+    }
+
+    /// Check the addresses of the mandatory part of an operation and its CIEs. Errors are
+    /// located relative to the start of `payload`, which must have been parsed successfully.
+    fn check_operation(&self, optype: NhrpOp, payload: &[u8]) -> Result<()> {
+        match optype {
+            ErrorIndication | TrafficIndication => {
+                let buffer = ErrorIndicationBuffer::new(payload);
+                self.check_nbma(buffer.src_nbma_addr_tl(), SHTL, "src_nbma_addr_tl")?;
+                self.check_proto(buffer.src_proto_addr_len(), SRC_PROTO_LEN, "src_proto_addr_len", false)?;
+                self.check_proto(buffer.dst_proto_addr_len(), DST_PROTO_LEN, "dst_proto_addr_len", false)
+            },
+            _ => {
+                let buffer = OperationBuffer::new(payload);
+                self.check_nbma(buffer.src_nbma_addr_tl(), SHTL, "src_nbma_addr_tl")?;
+                self.check_proto(buffer.src_proto_addr_len(), SRC_PROTO_LEN, "src_proto_addr_len", false)?;
+                self.check_proto(buffer.dst_proto_addr_len(), DST_PROTO_LEN, "dst_proto_addr_len", false)?;
+                self.check_cies(buffer.payload()).map_err(|e| e.within(buffer.length(), ""))
+            },
+        }
+    }
+
+    /// Check the addresses of the CIEs in `payload`, which must have been parsed successfully.
+    fn check_cies(&self, payload: &[u8]) -> Result<()> {
+        let mut position = 0;
+        for (index, cie) in CieIterator::new(payload).enumerate() {
+            let cie = cie?;
+            self.check_nbma(cie.cli_nbma_addr_tl(), CLI_ADDR_TL, "cli_nbma_addr_tl")
+                .and_then(|_| self.check_proto(cie.cli_proto_addr_len(), CLI_PROTO_LEN, "cli_proto_addr_len", true))
+                .map_err(|e| e.within(position, &format!("CIE[{}]", index)))?;
+            position += cie.length() as usize;
+        }
+        Ok(())
+    }
+
+    /// An NBMA address must be as long as the addresses of the address family, if it is present
+    fn check_nbma(&self, tl: AddrTL, index: usize, field: &str) -> Result<()> {
+        let afn = self.header.afn();
+        check_len(self.nbma, tl.length(), true, index, field, format_args!("address family {}", afn))
+    }
+
+    /// A protocol address must be as long as the addresses of the protocol type. Only the one of
+    /// a CIE may be left out, those in the mandatory part are always present.
+    fn check_proto(&self, len: u8, index: usize, field: &str, optional: bool) -> Result<()> {
+        let protype = self.header.protocol_type().protype.value();
+        check_len(self.proto, len as usize, optional, index, field, format_args!("protocol type {:#06x}", protype))
+    }
+}
+
+fn check_len(expected: Option<usize>, found: usize, optional: bool, index: usize, field: &str,
+             of: impl fmt::Display) -> Result<()>
+{
+    match expected {
+        Some(len) if found == len || (optional && found == 0) => Ok(()),
+        Some(len) if optional => {
+            Err(Error::malformed(index, 1, field, format_args!("0 or {} for {}", len, of), found))
+        },
+        Some(len) => Err(Error::malformed(index, 1, field, format_args!("{} for {}", len, of), found)),
+        None => Ok(()),
+    }
+}
+
 impl Emitable for NhrpMessage {
     fn buffer_len(&self) -> usize {
-        use crate::operation::Operation::*;
-        let payload_len = match self.operation {
-            ResolutionRequest(ref msg) => msg.buffer_len(),
-            ResolutionReply(ref msg) => msg.buffer_len(),
-            RegistrationRequest(ref msg) => msg.buffer_len(),
-            RegistrationReply(ref msg) => msg.buffer_len(),
-            PurgeRequest(ref msg) => msg.buffer_len(),
-            PurgeReply(ref msg) => msg.buffer_len(),
+        let eoe_len = if self.needs_end_of_extensions() {
+            Extension::EndOfExtensions.buffer_len()
+        } else {
+            0
         };
-        payload_len + self.extensions.iter().fold(0, |s,e| s + e.length()) + self.header.buffer_len()
+        self.header.buffer_len()
+            + self.operation.buffer_len()
+            + self.extensions.buffer_len()
+            + eoe_len
     }
 
     fn emit(&self, buffer: &mut [u8]) {
+        let len = self.buffer_len();
+        let buffer = &mut buffer[..len];
         self.header.emit(buffer);
 
-        let eoff = self.extensions.iter().fold(0, |s,e| s + e.length());
-        let end = if eoff == 0 { self.buffer_len() } else { eoff };
-        {
-            let payload = &mut buffer[self.header.buffer_len()..end];
-
-            use crate::operation::Operation::*;
-            match self.operation {
-                ResolutionRequest(ref msg) => msg.emit(payload),
-                ResolutionReply(ref msg) => msg.emit(payload),
-                RegistrationRequest(ref msg) => msg.emit(payload),
-                RegistrationReply(ref msg) => msg.emit(payload),
-                PurgeRequest(ref msg) => msg.emit(payload),
-                PurgeReply(ref msg) => msg.emit(payload),
-            }
-        }
+        let payload_start = self.header.buffer_len();
+        let payload_end = payload_start + self.operation.buffer_len();
+        self.operation.emit(&mut buffer[payload_start..payload_end]);
 
-        {
-            let buffer = &mut buffer[eoff..self.buffer_len()];
+        // An extension offset of zero signals that no extensions are present.
+        let eoff = if self.extensions.is_empty() {
+            0
+        } else {
+            let buffer = &mut buffer[payload_end..];
             self.extensions.emit(buffer);
-        }
+            if self.needs_end_of_extensions() {
+                let buffer = &mut buffer[self.extensions.buffer_len()..];
+                Extension::EndOfExtensions.emit(buffer);
+            }
+            payload_end
+        };
 
         let mut mbuffer = NhrpBuffer::new(buffer);
-        mbuffer.set_length(self.buffer_len() as u16);
+        mbuffer.set_length(len as u16);
         mbuffer.set_extoffset(eoff as u16);
         mbuffer.set_checksum(0);
         let chksum = mbuffer.calculate_checksum();
@@ -2,21 +2,53 @@
 use super::header::*;
 use crate::{Field, Index, Rest, Result, Error};
 
-const SHTL: Index = 0;
+pub(crate) const SHTL: Index = 0;
 const SSTL: Index = 1;
-const SRC_PROTO_LEN: Index = 2;
-const DST_PROTO_LEN: Index = 3;
+pub(crate) const SRC_PROTO_LEN: Index = 2;
+pub(crate) const DST_PROTO_LEN: Index = 3;
 const FLAGS: Field = 4..6;
 const REQUEST_ID: Field = 6..10;
 const ADDRS: Rest = 10..;
 
+/// A field giving the length of one of the variable-length addresses
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AddrLen {
+    /// Type & length field of an NBMA address or subaddress
+    TypeLength(Index, &'static str),
+    /// Length of a protocol address
+    Length(Index, &'static str),
+}
+
+/// Check that the addresses whose lengths are given by `fields` fit into `data`, the first one
+/// starting at `start`. `data` must be at least `start` octets long.
+pub(crate) fn check_addresses(data: &[u8], start: usize, fields: &[AddrLen]) -> Result<()> {
+    let mut end = start;
+    for field in fields {
+        let (index, name, length) = match *field {
+            AddrLen::TypeLength(index, name) => {
+                let tl = AddrTL::try_from(data[index]).map_err(|_| {
+                    Error::malformed(index, 1, name, "reserved bit clear", format_args!("{:#04x}", data[index]))
+                })?;
+                (index, name, tl.length())
+            },
+            AddrLen::Length(index, name) => (index, name, data[index] as usize),
+        };
+        end += length;
+        if end > data.len() {
+            let available = length - (end - data.len());
+            return Err(Error::malformed(index, 1, name, format_args!("at most {}", available), length));
+        }
+    }
+    Ok(())
+}
+
 pub struct OperationBuffer<T> {
     buffer: T,
 }
 
 impl<T: AsRef<[u8]>> OperationBuffer<T> {
     pub fn new(buffer: T) -> OperationBuffer<T> {
-        Self { buffer: buffer }
+        Self { buffer }
     }
 
     pub fn new_checked(buffer: T) -> Result<OperationBuffer<T>> {
@@ -27,19 +59,26 @@ impl<T: AsRef<[u8]>> OperationBuffer<T> {
 
     fn check_buffer_length(&self) -> Result<()> {
         let len = self.buffer.as_ref().len();
-        if len < DST_PROTO_LEN || len < self.length() as usize {
-            Err(Error::Truncated)
-        } else {
-            Ok(())
+        if len < ADDRS.start {
+            return Err(Error::malformed(0, len, "common_header",
+                format_args!("at least {} octets", ADDRS.start), format_args!("{} octets", len)));
         }
+        check_addresses(self.buffer.as_ref(), ADDRS.start, &[
+            AddrLen::TypeLength(SHTL, "src_nbma_addr_tl"),
+            AddrLen::TypeLength(SSTL, "src_nbma_saddr_tl"),
+            AddrLen::Length(SRC_PROTO_LEN, "src_proto_addr_len"),
+            AddrLen::Length(DST_PROTO_LEN, "dst_proto_addr_len"),
+        ])
     }
 
+    /// Length of the common header including its addresses, not including any CIEs.
     pub fn length(&self) -> usize {
-        let shtl: u8 = self.src_nbma_addr_tl().into();
-        let sstl: u8 = self.src_nbma_saddr_tl().into();
+        let shtl = self.src_nbma_addr_tl().length();
+        let sstl = self.src_nbma_saddr_tl().length();
 
-        8 + shtl as usize
-          + sstl as usize
+        ADDRS.start
+          + shtl
+          + sstl
           + self.src_proto_addr_len() as usize
           + self.dst_proto_addr_len() as usize
     }
@@ -50,7 +89,7 @@ impl<T: AsRef<[u8]>> OperationBuffer<T> {
 
     pub fn src_nbma_addr_tl(&self) -> AddrTL {
         let data = self.buffer.as_ref();
-        data[SHTL].into()
+        AddrTL::from_bits(data[SHTL])
     }
     pub fn src_nbma_addr_offset(&self) -> usize {
         ADDRS.start
@@ -58,11 +97,11 @@ impl<T: AsRef<[u8]>> OperationBuffer<T> {
 
     pub fn src_nbma_saddr_tl(&self) -> AddrTL {
         let data = self.buffer.as_ref();
-        data[SSTL].into()
+        AddrTL::from_bits(data[SSTL])
     }
     pub fn src_nbma_saddr_offset(&self) -> usize {
-        let shtl: u8 = self.src_nbma_addr_tl().into();
-        self.src_nbma_addr_offset() + shtl as usize
+        let shtl = self.src_nbma_addr_tl().length();
+        self.src_nbma_addr_offset() + shtl
     }
 
     pub fn src_proto_addr_len(&self) -> u8 {
@@ -70,8 +109,8 @@ impl<T: AsRef<[u8]>> OperationBuffer<T> {
         data[SRC_PROTO_LEN]
     }
     pub fn src_proto_addr_offset(&self) -> usize {
-        let sstl: u8 = self.src_nbma_saddr_tl().into();
-        self.src_nbma_saddr_offset() + sstl as usize
+        let sstl = self.src_nbma_saddr_tl().length();
+        self.src_nbma_saddr_offset() + sstl
     }
 
     pub fn dst_proto_addr_len(&self) -> u8 {
@@ -95,15 +134,15 @@ impl<T: AsRef<[u8]>> OperationBuffer<T> {
 
 impl<'a, T: AsRef<[u8]> + ?Sized> OperationBuffer<&'a T> {
     pub fn src_nbma_addr(&self) -> &'a [u8] {
-        let shtl: u8 = self.src_nbma_addr_tl().into();
-        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl as usize);
+        let shtl = self.src_nbma_addr_tl().length();
+        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl);
         let data = self.buffer.as_ref();
         &data[range]
     }
 
     pub fn src_nbma_saddr(&self) -> &'a [u8] {
-        let sstl: u8 = self.src_nbma_saddr_tl().into();
-        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl as usize);
+        let sstl = self.src_nbma_saddr_tl().length();
+        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl);
         let data = self.buffer.as_ref();
         &data[range]
     }
@@ -131,17 +170,17 @@ impl<'a, T: AsRef<[u8]> + ?Sized> OperationBuffer<&'a T> {
     }
 }
 
-impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> OperationBuffer<&'a mut T> {
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> OperationBuffer<&mut T> {
     pub fn src_nbma_addr_mut(&mut self) -> &mut [u8]{
-        let shtl: u8 = self.src_nbma_addr_tl().into();
-        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl as usize);
+        let shtl = self.src_nbma_addr_tl().length();
+        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl);
         let data = self.buffer.as_mut();
         &mut data[range]
     }
 
     pub fn src_nbma_saddr_mut(&mut self) -> &mut [u8]{
-        let sstl: u8 = self.src_nbma_saddr_tl().into();
-        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl as usize);
+        let sstl = self.src_nbma_saddr_tl().length();
+        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl);
         let data = self.buffer.as_mut();
         &mut data[range]
     }
@@ -194,3 +233,221 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> OperationBuffer<T> {
         data[REQUEST_ID].copy_from_slice(&value.to_be_bytes());
     }
 }
+
+const ERR_UNUSED: Field = 4..6;
+const ERR_CODE: Field = 6..8;
+pub(crate) const ERR_OFFSET: Field = 8..10;
+pub(crate) const ERR_ADDRS: Rest = 10..;
+
+/// Buffer over the mandatory part of an Error Indication.
+///
+/// Error Indications don't use the common header layout of the other operations; flags and
+/// request ID are replaced by two unused octets, the error code and the error offset (RFC 2332
+/// §5.2.7). The addresses start at the same offset as in the common header.
+///
+/// Traffic Indications use the same layout with the traffic code in place of the error code and
+/// the error offset unused.
+pub struct ErrorIndicationBuffer<T> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> ErrorIndicationBuffer<T> {
+    pub fn new(buffer: T) -> ErrorIndicationBuffer<T> {
+        Self { buffer }
+    }
+
+    pub fn new_checked(buffer: T) -> Result<ErrorIndicationBuffer<T>> {
+        let packet = Self::new(buffer);
+        packet.check_buffer_length()?;
+        Ok(packet)
+    }
+
+    fn check_buffer_length(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < ERR_ADDRS.start {
+            return Err(Error::malformed(0, len, "error_header",
+                format_args!("at least {} octets", ERR_ADDRS.start), format_args!("{} octets", len)));
+        }
+        check_addresses(self.buffer.as_ref(), ERR_ADDRS.start, &[
+            AddrLen::TypeLength(SHTL, "src_nbma_addr_tl"),
+            AddrLen::TypeLength(SSTL, "src_nbma_saddr_tl"),
+            AddrLen::Length(SRC_PROTO_LEN, "src_proto_addr_len"),
+            AddrLen::Length(DST_PROTO_LEN, "dst_proto_addr_len"),
+        ])
+    }
+
+    /// Length of the error header, not including the contents of the packet in error.
+    pub fn length(&self) -> usize {
+        let shtl = self.src_nbma_addr_tl().length();
+        let sstl = self.src_nbma_saddr_tl().length();
+
+        ERR_ADDRS.start
+            + shtl
+            + sstl
+            + self.src_proto_addr_len() as usize
+            + self.dst_proto_addr_len() as usize
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    pub fn src_nbma_addr_tl(&self) -> AddrTL {
+        let data = self.buffer.as_ref();
+        AddrTL::from_bits(data[SHTL])
+    }
+    pub fn src_nbma_addr_offset(&self) -> usize {
+        ERR_ADDRS.start
+    }
+
+    pub fn src_nbma_saddr_tl(&self) -> AddrTL {
+        let data = self.buffer.as_ref();
+        AddrTL::from_bits(data[SSTL])
+    }
+    pub fn src_nbma_saddr_offset(&self) -> usize {
+        let shtl = self.src_nbma_addr_tl().length();
+        self.src_nbma_addr_offset() + shtl
+    }
+
+    pub fn src_proto_addr_len(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[SRC_PROTO_LEN]
+    }
+    pub fn src_proto_addr_offset(&self) -> usize {
+        let sstl = self.src_nbma_saddr_tl().length();
+        self.src_nbma_saddr_offset() + sstl
+    }
+
+    pub fn dst_proto_addr_len(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[DST_PROTO_LEN]
+    }
+    pub fn dst_proto_addr_offset(&self) -> usize {
+        self.src_proto_addr_offset() + self.src_proto_addr_len() as usize
+    }
+
+    pub fn error_code(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        u16::from_be_bytes(data[ERR_CODE].try_into().unwrap())
+    }
+
+    pub fn error_offset(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        u16::from_be_bytes(data[ERR_OFFSET].try_into().unwrap())
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> ErrorIndicationBuffer<&'a T> {
+    pub fn src_nbma_addr(&self) -> &'a [u8] {
+        let shtl = self.src_nbma_addr_tl().length();
+        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl);
+        let data = self.buffer.as_ref();
+        &data[range]
+    }
+
+    pub fn src_nbma_saddr(&self) -> &'a [u8] {
+        let sstl = self.src_nbma_saddr_tl().length();
+        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl);
+        let data = self.buffer.as_ref();
+        &data[range]
+    }
+
+    pub fn src_proto_addr(&self) -> &'a [u8] {
+        let offset = self.src_proto_addr_offset();
+        let len = self.src_proto_addr_len() as usize;
+        let range = offset..(offset+len);
+        let data = self.buffer.as_ref();
+        &data[range]
+    }
+
+    pub fn dst_proto_addr(&self) -> &'a [u8] {
+        let offset = self.dst_proto_addr_offset();
+        let len = self.dst_proto_addr_len() as usize;
+        let range = offset..(offset+len);
+        let data = self.buffer.as_ref();
+        &data[range]
+    }
+
+    /// The contents of the NHRP packet that caused the error.
+    pub fn payload(&self) -> &'a [u8] {
+        let range = (self.dst_proto_addr_offset() + self.dst_proto_addr_len() as usize)..;
+        let data = self.buffer.as_ref();
+        &data[range]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> ErrorIndicationBuffer<&mut T> {
+    pub fn src_nbma_addr_mut(&mut self) -> &mut [u8]{
+        let shtl = self.src_nbma_addr_tl().length();
+        let range = self.src_nbma_addr_offset()..(self.src_nbma_addr_offset() + shtl);
+        let data = self.buffer.as_mut();
+        &mut data[range]
+    }
+
+    pub fn src_nbma_saddr_mut(&mut self) -> &mut [u8]{
+        let sstl = self.src_nbma_saddr_tl().length();
+        let range = self.src_nbma_saddr_offset()..(self.src_nbma_saddr_offset() + sstl);
+        let data = self.buffer.as_mut();
+        &mut data[range]
+    }
+
+    pub fn src_proto_addr_mut(&mut self) -> &mut [u8]{
+        let offset = self.src_proto_addr_offset();
+        let len = self.src_proto_addr_len() as usize;
+        let range = offset..(offset+len);
+        let data = self.buffer.as_mut();
+        &mut data[range]
+    }
+
+    pub fn dst_proto_addr_mut(&mut self) -> &mut [u8]{
+        let offset = self.dst_proto_addr_offset();
+        let len = self.dst_proto_addr_len() as usize;
+        let range = offset..(offset+len);
+        let data = self.buffer.as_mut();
+        &mut data[range]
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let range = (self.dst_proto_addr_offset() + self.dst_proto_addr_len() as usize)..;
+        let data = self.buffer.as_mut();
+        &mut data[range]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ErrorIndicationBuffer<T> {
+    /// Zero the unused octets following the protocol address lengths
+    pub fn clear_unused(&mut self) {
+        let data = self.buffer.as_mut();
+        data[ERR_UNUSED].fill(0);
+    }
+
+    pub fn set_src_nbma_addr_tl(&mut self, value: AddrTL) {
+        let data = self.buffer.as_mut();
+        data[SHTL] = value.into()
+    }
+
+    pub fn set_src_nbma_saddr_tl(&mut self, value: AddrTL) {
+        let data = self.buffer.as_mut();
+        data[SSTL] = value.into()
+    }
+
+    pub fn set_src_proto_addr_len(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[SRC_PROTO_LEN] = value
+    }
+
+    pub fn set_dst_proto_addr_len(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[DST_PROTO_LEN] = value
+    }
+
+    pub fn set_error_code(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        data[ERR_CODE].copy_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn set_error_offset(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        data[ERR_OFFSET].copy_from_slice(&value.to_be_bytes());
+    }
+}
@@ -1,13 +1,16 @@
 #![allow(dead_code)]
+use alloc::format;
 use crate::{Field, Index, Rest, Result, Error};
+use crate::operation::AddrTL;
+use crate::operation::buffer::{check_addresses, AddrLen};
 
-const CODE: Index = 0;
-const PREFIX_LEN: Index = 1;
+pub(crate) const CODE: Index = 0;
+pub(crate) const PREFIX_LEN: Index = 1;
 const MTU: Field = 4..6;
 const HOLDING_TIME: Field = 6..8;
-const CLI_ADDR_TL: Index = 8;
+pub(crate) const CLI_ADDR_TL: Index = 8;
 const CLI_SADDR_TL: Index = 9;
-const CLI_PROTO_LEN: Index = 10;
+pub(crate) const CLI_PROTO_LEN: Index = 10;
 const PREFERENCE: Index = 11;
 const ADDRS: Rest = 12..;
 
@@ -17,7 +20,7 @@ pub struct CieBuffer<T> {
 
 impl<T: AsRef<[u8]>> CieBuffer<T> {
     pub fn new(buffer: T) -> CieBuffer<T> {
-        CieBuffer { buffer: buffer }
+        CieBuffer { buffer }
     }
 
     pub fn new_checked(buffer: T) -> Result<CieBuffer<T>> {
@@ -28,17 +31,21 @@ impl<T: AsRef<[u8]>> CieBuffer<T> {
 
     fn check_buffer_length(&self) -> Result<()> {
         let len = self.buffer.as_ref().len();
-        if len < CLI_PROTO_LEN || len < self.length() as usize {
-            Err(Error::Truncated)
-        } else {
-            Ok(())
+        if len < ADDRS.start {
+            return Err(Error::malformed(0, len, "header",
+                format_args!("at least {} octets", ADDRS.start), format_args!("{} octets", len)));
         }
+        check_addresses(self.buffer.as_ref(), ADDRS.start, &[
+            AddrLen::TypeLength(CLI_ADDR_TL, "cli_nbma_addr_tl"),
+            AddrLen::TypeLength(CLI_SADDR_TL, "cli_nbma_saddr_tl"),
+            AddrLen::Length(CLI_PROTO_LEN, "cli_proto_addr_len"),
+        ])
     }
 
     // FIXME: Actually CLI_[S]ADDR_TL is context-specific
     pub fn length(&self) -> u32 {
-        12 + self.cli_nbma_addr_tl() as u32
-           + self.cli_nbma_saddr_tl() as u32
+        12 + self.cli_nbma_addr_tl().length() as u32
+           + self.cli_nbma_saddr_tl().length() as u32
            + self.cli_proto_addr_len() as u32
     }
 
@@ -67,21 +74,21 @@ impl<T: AsRef<[u8]>> CieBuffer<T> {
     }
 
     // FIXME: Actually CLI_[S]ADDR_TL is context-specific
-    pub fn cli_nbma_addr_tl(&self) -> u8 {
+    pub fn cli_nbma_addr_tl(&self) -> AddrTL {
         let data = self.buffer.as_ref();
-        data[CLI_ADDR_TL]
+        AddrTL::from_bits(data[CLI_ADDR_TL])
     }
     pub fn cli_nbma_addr_offset(&self) -> usize {
         ADDRS.start
     }
 
     // FIXME: Actually CLI_[S]ADDR_TL is context-specific
-    pub fn cli_nbma_saddr_tl(&self) -> u8 {
+    pub fn cli_nbma_saddr_tl(&self) -> AddrTL {
         let data = self.buffer.as_ref();
-        data[CLI_SADDR_TL]
+        AddrTL::from_bits(data[CLI_SADDR_TL])
     }
     pub fn cli_nbma_saddr_offset(&self) -> usize {
-        self.cli_nbma_addr_offset() + self.cli_nbma_addr_tl() as usize
+        self.cli_nbma_addr_offset() + self.cli_nbma_addr_tl().length()
     }
 
     pub fn cli_proto_addr_len(&self) -> u8 {
@@ -90,7 +97,7 @@ impl<T: AsRef<[u8]>> CieBuffer<T> {
     }
     pub fn cli_proto_addr_offset(&self) -> usize {
         // FIXME: Actually CLI_[S]ADDR_TL is context-specific
-        self.cli_nbma_saddr_offset() + self.cli_nbma_saddr_tl() as usize
+        self.cli_nbma_saddr_offset() + self.cli_nbma_saddr_tl().length()
     }
 
     pub fn preference(&self) -> u8 {
@@ -103,14 +110,14 @@ impl<'a, T: AsRef<[u8]> + ?Sized> CieBuffer<&'a T> {
     // FIXME: Also actually context-specific
     pub fn cli_nbma_addr(&self) -> &'a [u8] {
         let offset = self.cli_nbma_addr_offset();
-        let len = self.cli_nbma_addr_tl() as usize;
+        let len = self.cli_nbma_addr_tl().length();
         let range = offset..(offset+len);
         let data = self.buffer.as_ref();
         &data[range]
     }
     pub fn cli_nbma_saddr(&self) -> &'a [u8] {
         let offset = self.cli_nbma_saddr_offset();
-        let len = self.cli_nbma_saddr_tl() as usize;
+        let len = self.cli_nbma_saddr_tl().length();
         let range = offset..(offset+len);
         let data = self.buffer.as_ref();
         &data[range]
@@ -125,18 +132,18 @@ impl<'a, T: AsRef<[u8]> + ?Sized> CieBuffer<&'a T> {
     }
 }
 
-impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> CieBuffer<&'a mut T> {
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> CieBuffer<&mut T> {
     // FIXME: Also actually context-specific
     pub fn cli_nbma_addr_mut(&mut self) -> &mut [u8] {
         let offset = self.cli_nbma_addr_offset();
-        let len = self.cli_nbma_addr_tl() as usize;
+        let len = self.cli_nbma_addr_tl().length();
         let range = offset..(offset+len);
         let data = self.buffer.as_mut();
         &mut data[range]
     }
     pub fn cli_nbma_saddr_mut(&mut self) -> &mut [u8] {
         let offset = self.cli_nbma_saddr_offset();
-        let len = self.cli_nbma_saddr_tl() as usize;
+        let len = self.cli_nbma_saddr_tl().length();
         let range = offset..(offset+len);
         let data = self.buffer.as_mut();
         &mut data[range]
@@ -173,15 +180,15 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> CieBuffer<T> {
     }
 
     // FIXME: Actually CLI_[S]ADDR_TL is context-specific
-    pub fn set_cli_nbma_addr_tl(&mut self, value: u8) {
+    pub fn set_cli_nbma_addr_tl(&mut self, value: AddrTL) {
         let data = self.buffer.as_mut();
-        data[CLI_ADDR_TL] = value
+        data[CLI_ADDR_TL] = value.into()
     }
 
     // FIXME: Actually CLI_[S]ADDR_TL is context-specific
-    pub fn set_cli_nbma_saddr_tl(&mut self, value: u8) {
+    pub fn set_cli_nbma_saddr_tl(&mut self, value: AddrTL) {
         let data = self.buffer.as_mut();
-        data[CLI_SADDR_TL] = value
+        data[CLI_SADDR_TL] = value.into()
     }
 
     pub fn set_cli_proto_addr_len(&mut self, value: u8) {
@@ -198,6 +205,7 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> CieBuffer<T> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CieIterator<T> {
     position: usize,
+    index: usize,
     buffer: T,
 }
 
@@ -205,7 +213,8 @@ impl<T> CieIterator<T> {
     pub fn new(buffer: T) -> Self {
         CieIterator {
             position: 0,
-            buffer: buffer
+            index: 0,
+            buffer
         }
     }
 }
@@ -222,12 +231,14 @@ impl<'a, T: AsRef<[u8]> + ?Sized + 'a> Iterator for CieIterator<&'a T> {
         match CieBuffer::new_checked(&self.buffer.as_ref()[self.position..]) {
             Ok(ciebuffer) => {
                 self.position += ciebuffer.length() as usize;
+                self.index += 1;
                 Some(Ok(ciebuffer))
             },
-            Err(_) => {
+            Err(e) => {
                 // Fuse the iterator. We currently really can't recover from an invalid CIE.
+                let e = e.within(self.position, &format!("CIE[{}]", self.index));
                 self.position = self.buffer.as_ref().len();
-                None
+                Some(Err(e))
             }
         }
     }
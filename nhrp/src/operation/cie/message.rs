@@ -1,53 +1,98 @@
-use crate::cie::buffer::CieBuffer;
+use alloc::{format, vec::Vec};
+use crate::cie::buffer::{CieBuffer, CieIterator, CLI_PROTO_LEN};
+use crate::operation::{AddrTL, NbmaAddress};
 
-use std::net::IpAddr::{self, *};
-use std::net::Ipv4Addr;
+use core::net::IpAddr::{self, *};
+use core::net::Ipv4Addr;
 
 use crate::{Parseable, Emitable, Result, Error};
 
+/// Outcome reported in a CIE (RFC 2332, 5.2.0.1)
+///
+/// Requests always carry `Success`. Replies only use the codes that apply to their operation,
+/// which [`ResolutionCode`](crate::ResolutionCode) and
+/// [`RegistrationCode`](crate::RegistrationCode) narrow this down to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CieCode {
+    Success,
+    /// Administratively prohibited
+    Prohibited,
+    InsufficientResources,
+    /// No binding of the protocol address to an NBMA address exists
+    NoBindingExists,
+    /// A binding exists but is not unique
+    BindingNotUnique,
+    /// The protocol address is already registered with a different NBMA address
+    AlreadyRegistered,
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Unknown(u8),
+}
+impl From<u8> for CieCode {
+    fn from(value: u8) -> CieCode {
+        use CieCode::*;
+        match value {
+            0 => Success,
+            4 => Prohibited,
+            5 => InsufficientResources,
+            12 => NoBindingExists,
+            13 => BindingNotUnique,
+            14 => AlreadyRegistered,
+            _ => Unknown(value),
+        }
+    }
+}
+impl From<CieCode> for u8 {
+    fn from(value: CieCode) -> u8 {
+        use CieCode::*;
+        match value {
+            Success => 0,
+            Prohibited => 4,
+            InsufficientResources => 5,
+            NoBindingExists => 12,
+            BindingNotUnique => 13,
+            AlreadyRegistered => 14,
+            Unknown(v) => v,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClientInformationEntry {
-    pub code: u8,
+    pub code: CieCode,
     pub prefix_len: u8,
     pub mtu: u16,
     pub holding_time: u16,
     pub preference: u8,
-    pub client_nbma_addr: Option<IpAddr>,
+    pub client_nbma_addr: Option<NbmaAddress>,
     pub client_proto_addr: Option<IpAddr>,
 }
 impl ClientInformationEntry {
     #[allow(dead_code)]
-    pub fn new(code: u8, prefix_len: u8, mtu: u16, holding_time: u16, preference: u8, client_nbma_addr: Option<IpAddr>, client_proto_addr: Option<IpAddr>) -> Self {
+    pub fn new(code: CieCode, prefix_len: u8, mtu: u16, holding_time: u16, preference: u8, client_nbma_addr: Option<NbmaAddress>, client_proto_addr: Option<IpAddr>) -> Self {
         ClientInformationEntry {
-            code: code,
-            prefix_len: prefix_len,
-            mtu: mtu,
-            holding_time: holding_time,
-            preference: preference,
-            client_nbma_addr: client_nbma_addr,
-            client_proto_addr: client_proto_addr,
+            code,
+            prefix_len,
+            mtu,
+            holding_time,
+            preference,
+            client_nbma_addr,
+            client_proto_addr,
         }
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ClientInformationEntry> for CieBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ClientInformationEntry> for CieBuffer<&T> {
     fn parse(&self) -> Result<ClientInformationEntry> {
-        let client_nbma_addr = match self.cli_nbma_addr_tl() {
-            0 => Ok(None),
-            4 => {
-                let a = self.cli_nbma_addr();
-                let addr = Ipv4Addr::new(a[0], a[1], a[2], a[3]);
-                Ok(Some(IpAddr::V4(addr)))
-            },
-            16 => {
-                let a = self.cli_nbma_addr();
-                let addr: [u8; 16] = a.try_into().unwrap();
-                Ok(Some(IpAddr::V6(addr.into())))
-            },
-            _ => {
-                Err(Error::NotImplemented)
-            }
-        }?;
+        let client_nbma_addr = NbmaAddress::from_parts(self.cli_nbma_addr_tl(), self.cli_nbma_addr(),
+            self.cli_nbma_saddr_tl(), self.cli_nbma_saddr());
+        let client_nbma_addr = if client_nbma_addr.is_empty() {
+            None
+        } else {
+            Some(client_nbma_addr)
+        };
         let client_proto_addr = match self.cli_proto_addr_len() {
             0 => Ok(None),
             4 => {
@@ -56,53 +101,64 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ClientInformationEntry> for CieBuffe
                 Ok(Some(IpAddr::V4(addr)))
             },
             16 => {
-                let a = self.cli_proto_addr();
-                let mut addr: [u16; 8] = [0;8];
-                Ok(Some(IpAddr::V6(addr.into())))
+                let a: [u8; 16] = self.cli_proto_addr().try_into().unwrap();
+                Ok(Some(IpAddr::V6(a.into())))
             },
-            _ => {
-                Err(Error::NotImplemented)
+            len => {
+                Err(Error::malformed(CLI_PROTO_LEN, 1, "cli_proto_addr_len", "0, 4 or 16", len))
             }
         }?;
         Ok(ClientInformationEntry {
-            code: self.code(),
+            code: self.code().into(),
             prefix_len: self.prefix_len(),
             mtu: self.mtu(),
             holding_time: self.holding_time(),
             preference: self.preference(),
-            client_nbma_addr: client_nbma_addr,
-            client_proto_addr: client_proto_addr,
+            client_nbma_addr,
+            client_proto_addr,
         })
     }
 }
 
+/// Parse all CIEs in `payload`. Errors are located relative to the start of `payload` and
+/// name the CIE they occurred in.
+pub(crate) fn parse_cies(payload: &[u8]) -> Result<Vec<ClientInformationEntry>> {
+    let mut ciev = Vec::new();
+    let mut position = 0;
+    for (index, cie) in CieIterator::new(payload).enumerate() {
+        let cie = cie?;
+        let parsed = cie.parse()
+            .map_err(|e| e.within(position, &format!("CIE[{}]", index)))?;
+        position += cie.length() as usize;
+        ciev.push(parsed);
+    }
+    Ok(ciev)
+}
+
 impl Emitable for ClientInformationEntry {
     fn buffer_len(&self) -> usize {
-        let cnal = match self.client_nbma_addr { None => 0, Some(V4(_)) => 4, Some(V6(_)) => 16 };
+        let cnal = self.client_nbma_addr.as_ref().map_or(0, NbmaAddress::len);
         let cpal = match self.client_proto_addr { None => 0, Some(V4(_)) => 4, Some(V6(_)) => 16 };
         12 + cnal + cpal
     }
 
     fn emit(&self, buffer: &mut [u8]) {
         let mut buffer = CieBuffer::new(buffer);
-        buffer.set_code(self.code);
+        buffer.set_code(self.code.into());
         buffer.set_prefix_len(self.prefix_len);
         buffer.set_mtu(self.mtu);
         buffer.set_holding_time(self.holding_time);
         buffer.set_preference(self.preference);
-        buffer.set_cli_nbma_saddr_tl(0);
-
         match self.client_nbma_addr {
-            None => buffer.set_cli_nbma_addr_tl(0),
-            Some(V4(a)) => {
-                let a = a.octets();
-                buffer.set_cli_nbma_addr_tl(a.len() as u8);
-                buffer.cli_nbma_addr_mut().copy_from_slice(&a);
-            }
-            Some(V6(a)) => {
-                let a = a.octets();
-                buffer.set_cli_nbma_addr_tl(a.len() as u8);
-                buffer.cli_nbma_addr_mut().copy_from_slice(&a);
+            None => {
+                buffer.set_cli_nbma_addr_tl(AddrTL::NSAP(0));
+                buffer.set_cli_nbma_saddr_tl(AddrTL::NSAP(0));
+            },
+            Some(ref a) => {
+                buffer.set_cli_nbma_addr_tl(a.addr_tl());
+                buffer.set_cli_nbma_saddr_tl(a.saddr_tl());
+                buffer.cli_nbma_addr_mut().copy_from_slice(a.address());
+                buffer.cli_nbma_saddr_mut().copy_from_slice(a.subaddress());
             }
         };
         match self.client_proto_addr {
@@ -0,0 +1,76 @@
+use alloc::vec::Vec;
+use crate::{Parseable, Emitable, Result};
+use super::*;
+
+use core::net::IpAddr;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorIndicationMessage {
+    header: ErrorHeader,
+    #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
+    packet: Vec<u8>,
+}
+
+impl ErrorIndicationMessage {
+    pub fn new(code: ErrorCode,
+               offset: u16,
+               src_nbma_addr: NbmaAddress,
+               src_proto_addr: IpAddr,
+               dst_proto_addr: IpAddr,
+               packet: Vec<u8>,
+    ) -> Self {
+        let header = ErrorHeader {
+            code,
+            offset,
+            src_nbma_addr,
+            src_proto_addr,
+            dst_proto_addr,
+        };
+
+        ErrorIndicationMessage {
+            header, packet,
+        }
+    }
+
+    pub fn header(&self) -> &ErrorHeader {
+        &self.header
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.header.code
+    }
+
+    /// The raw bytes of the NHRP packet that caused this error
+    pub fn packet(&self) -> &[u8] {
+        &self.packet
+    }
+
+    #[allow(dead_code)]
+    pub fn into_parts(self) -> (ErrorHeader, Vec<u8>) {
+        (self.header, self.packet)
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ErrorIndicationMessage> for ErrorIndicationBuffer<&T> {
+    fn parse(&self) -> Result<ErrorIndicationMessage> {
+        let header = <Self as Parseable<ErrorHeader>>::parse(self)?;
+
+        Ok(ErrorIndicationMessage {
+            header,
+            packet: self.payload().to_vec(),
+        })
+    }
+}
+
+impl Emitable for ErrorIndicationMessage {
+    fn buffer_len(&self) -> usize {
+        self.header.buffer_len() + self.packet.len()
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        self.header.emit(buffer);
+        let buffer = &mut buffer[self.header.buffer_len()..self.buffer_len()];
+        buffer.copy_from_slice(&self.packet);
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * The meaning of the flags field of the common header depends on the operation. Bits are
+ * numbered from the most significant one in RFC 2332, so its bit 0 is 0x8000 here.
+ */
+
+const RESOLUTION_REQUESTER_ROUTER: u16 = 0x8000;
+const RESOLUTION_AUTHORITATIVE: u16 = 0x4000;
+const RESOLUTION_DESTINATION_STABLE: u16 = 0x2000;
+const RESOLUTION_UNIQUE: u16 = 0x1000;
+const RESOLUTION_SOURCE_STABLE: u16 = 0x0800;
+/// Cisco-specific, not assigned by RFC 2332
+const RESOLUTION_NAT: u16 = 0x0002;
+const RESOLUTION_KNOWN: u16 = RESOLUTION_REQUESTER_ROUTER | RESOLUTION_AUTHORITATIVE
+    | RESOLUTION_DESTINATION_STABLE | RESOLUTION_UNIQUE | RESOLUTION_SOURCE_STABLE | RESOLUTION_NAT;
+
+const REGISTRATION_UNIQUE: u16 = 0x8000;
+/// Cisco-specific, not assigned by RFC 2332
+const REGISTRATION_NAT: u16 = 0x0002;
+const REGISTRATION_KNOWN: u16 = REGISTRATION_UNIQUE | REGISTRATION_NAT;
+
+const PURGE_NO_REPLY: u16 = 0x8000;
+const PURGE_KNOWN: u16 = PURGE_NO_REPLY;
+
+/// Flags of Resolution Requests and Replies (RFC 2332, 5.2.1 and 5.2.2)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ResolutionFlags {
+    /// Q: the requester is a router rather than a host
+    pub requester_router: bool,
+    /// A: the reply was sent by the NHS serving the destination
+    pub authoritative: bool,
+    /// D: the binding of the destination is stable
+    pub destination_stable: bool,
+    /// U: the binding of the destination is unique
+    pub unique: bool,
+    /// S: the binding of the source is stable
+    pub source_stable: bool,
+    /// The sender understands the Cisco NAT extension
+    pub nat: bool,
+    /// Bits without an assigned meaning, kept so messages can be forwarded unchanged
+    pub other: u16,
+}
+impl From<u16> for ResolutionFlags {
+    fn from(value: u16) -> ResolutionFlags {
+        ResolutionFlags {
+            requester_router: value & RESOLUTION_REQUESTER_ROUTER != 0,
+            authoritative: value & RESOLUTION_AUTHORITATIVE != 0,
+            destination_stable: value & RESOLUTION_DESTINATION_STABLE != 0,
+            unique: value & RESOLUTION_UNIQUE != 0,
+            source_stable: value & RESOLUTION_SOURCE_STABLE != 0,
+            nat: value & RESOLUTION_NAT != 0,
+            other: value & !RESOLUTION_KNOWN,
+        }
+    }
+}
+impl From<ResolutionFlags> for u16 {
+    fn from(value: ResolutionFlags) -> u16 {
+        let mut flags = value.other & !RESOLUTION_KNOWN;
+        if value.requester_router { flags |= RESOLUTION_REQUESTER_ROUTER }
+        if value.authoritative { flags |= RESOLUTION_AUTHORITATIVE }
+        if value.destination_stable { flags |= RESOLUTION_DESTINATION_STABLE }
+        if value.unique { flags |= RESOLUTION_UNIQUE }
+        if value.source_stable { flags |= RESOLUTION_SOURCE_STABLE }
+        if value.nat { flags |= RESOLUTION_NAT }
+        flags
+    }
+}
+
+/// Flags of Registration Requests and Replies (RFC 2332, 5.2.3 and 5.2.4)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RegistrationFlags {
+    /// U: the registered binding must be unique, i.e. no other NBMA address may register the
+    /// same protocol address
+    pub unique: bool,
+    /// The sender understands the Cisco NAT extension
+    pub nat: bool,
+    /// Bits without an assigned meaning, kept so messages can be forwarded unchanged
+    pub other: u16,
+}
+impl From<u16> for RegistrationFlags {
+    fn from(value: u16) -> RegistrationFlags {
+        RegistrationFlags {
+            unique: value & REGISTRATION_UNIQUE != 0,
+            nat: value & REGISTRATION_NAT != 0,
+            other: value & !REGISTRATION_KNOWN,
+        }
+    }
+}
+impl From<RegistrationFlags> for u16 {
+    fn from(value: RegistrationFlags) -> u16 {
+        let mut flags = value.other & !REGISTRATION_KNOWN;
+        if value.unique { flags |= REGISTRATION_UNIQUE }
+        if value.nat { flags |= REGISTRATION_NAT }
+        flags
+    }
+}
+
+/// Flags of Purge Requests and Replies (RFC 2332, 5.2.5 and 5.2.6)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PurgeFlags {
+    /// N: the sender does not expect a Purge Reply
+    pub no_reply: bool,
+    /// Bits without an assigned meaning, kept so messages can be forwarded unchanged
+    pub other: u16,
+}
+impl From<u16> for PurgeFlags {
+    fn from(value: u16) -> PurgeFlags {
+        PurgeFlags {
+            no_reply: value & PURGE_NO_REPLY != 0,
+            other: value & !PURGE_KNOWN,
+        }
+    }
+}
+impl From<PurgeFlags> for u16 {
+    fn from(value: PurgeFlags) -> u16 {
+        let mut flags = value.other & !PURGE_KNOWN;
+        if value.no_reply { flags |= PURGE_NO_REPLY }
+        flags
+    }
+}
@@ -1,78 +1,187 @@
 use super::*;
+use super::buffer::{SRC_PROTO_LEN, DST_PROTO_LEN, ERR_ADDRS};
 use crate::{Parseable, Emitable, Result, Error};
 
-use std::net::IpAddr::{self, *};
-use std::net::Ipv4Addr;
+use core::net::IpAddr::{self, *};
+use core::net::Ipv4Addr;
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum AddrTL {
     NSAP(u8),
     E164(u8),
 }
-impl From<u8> for AddrTL {
-    fn from(value: u8) -> AddrTL {
-        assert!(value < 64);
+impl AddrTL {
+    /// Length of the address in octets
+    pub fn length(&self) -> usize {
+        match *self {
+            AddrTL::NSAP(len) | AddrTL::E164(len) => len as usize,
+        }
+    }
+
+    /// Decode a type & length field, ignoring the reserved bit. The checked buffers reject
+    /// fields with that bit set, so their accessors use this to stay infallible.
+    pub(crate) fn from_bits(value: u8) -> AddrTL {
         use self::AddrTL::*;
+        let len = value & 0b00111111;
         if value & 64 == 64 {
-            E164(value ^ 64) // Unset the 6th bit so the contained u8 is the length
+            E164(len)
         } else {
-            NSAP(value)
+            NSAP(len)
         }
     }
 }
+impl TryFrom<u8> for AddrTL {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<AddrTL> {
+        if value & 0x80 != 0 {
+            return Err(Error::InvalidAddrTL(value));
+        }
+        Ok(AddrTL::from_bits(value))
+    }
+}
 impl From<AddrTL> for u8 {
-    // FIXME: Technically only valid for values <64
+    /// The length must be below 64, which [`NbmaAddress`] guarantees for its addresses
     fn from(value: AddrTL) -> u8 {
         use self::AddrTL::*;
         match value {
             E164(v) => (v & 0b00111111) | 64,
-            NSAP(v) => (v & 0b00111111),
+            NSAP(v) => v & 0b00111111,
         }
     }
 }
 
+/// Common part of all operations but Error and Traffic Indications (RFC 2332, 5.2.0.1)
+///
+/// The flags field is part of the common header as well, but its meaning depends on the
+/// operation. Messages carry it typed, see [`ResolutionFlags`], [`RegistrationFlags`] and
+/// [`PurgeFlags`].
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CommonHeader {
-    pub flags: u16,
     pub request_id: u32,
-    pub src_nbma_addr: IpAddr,
+    pub src_nbma_addr: NbmaAddress,
     pub src_proto_addr: IpAddr,
     pub dst_proto_addr: IpAddr,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ErrorCode {
+    UnrecognizedExtension,
+    LoopDetected,
+    ProtocolAddressUnreachable,
+    ProtocolError,
+    SduSizeExceeded,
+    InvalidExtension,
+    InvalidResolutionReply,
+    AuthenticationFailure,
+    HopCountExceeded,
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Unknown(u16),
+}
+impl From<u16> for ErrorCode {
+    fn from(value: u16) -> ErrorCode {
+        use ErrorCode::*;
+        match value {
+            1 => UnrecognizedExtension,
+            3 => LoopDetected,
+            6 => ProtocolAddressUnreachable,
+            7 => ProtocolError,
+            8 => SduSizeExceeded,
+            9 => InvalidExtension,
+            10 => InvalidResolutionReply,
+            11 => AuthenticationFailure,
+            15 => HopCountExceeded,
+            _ => Unknown(value),
+        }
+    }
+}
+impl From<ErrorCode> for u16 {
+    fn from(value: ErrorCode) -> u16 {
+        use ErrorCode::*;
+        match value {
+            UnrecognizedExtension => 1,
+            LoopDetected => 3,
+            ProtocolAddressUnreachable => 6,
+            ProtocolError => 7,
+            SduSizeExceeded => 8,
+            InvalidExtension => 9,
+            InvalidResolutionReply => 10,
+            AuthenticationFailure => 11,
+            HopCountExceeded => 15,
+            Unknown(v) => v,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TrafficCode {
+    /// Traffic was forwarded out the interface it arrived on; the receiver should resolve a
+    /// shortcut to its destination.
+    Redirect,
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Unknown(u16),
+}
+impl From<u16> for TrafficCode {
+    fn from(value: u16) -> TrafficCode {
+        use TrafficCode::*;
+        match value {
+            0 => Redirect,
+            _ => Unknown(value),
+        }
+    }
+}
+impl From<TrafficCode> for u16 {
+    fn from(value: TrafficCode) -> u16 {
+        use TrafficCode::*;
+        match value {
+            Redirect => 0,
+            Unknown(v) => v,
+        }
+    }
+}
+
+/// Mandatory part of an Error Indication (RFC 2332, 5.2.7)
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ErrorHeader {
+    pub code: ErrorCode,
+    /// Offset in octets into the packet in error, counted from the start of its fixed header.
+    pub offset: u16,
+    pub src_nbma_addr: NbmaAddress,
+    pub src_proto_addr: IpAddr,
+    pub dst_proto_addr: IpAddr,
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<CommonHeader> for OperationBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<CommonHeader> for OperationBuffer<&T> {
     fn parse(&self) -> Result<CommonHeader> {
         Ok(CommonHeader {
-            flags: self.flags(),
             request_id: self.request_id(),
-            src_nbma_addr: parse_ip(self.src_nbma_addr())?,
-            src_proto_addr: parse_ip(self.src_proto_addr())?,
-            dst_proto_addr: parse_ip(self.dst_proto_addr())?,
+            src_nbma_addr: NbmaAddress::from_parts(self.src_nbma_addr_tl(), self.src_nbma_addr(),
+                self.src_nbma_saddr_tl(), self.src_nbma_saddr()),
+            src_proto_addr: parse_ip(self.src_proto_addr(), SRC_PROTO_LEN, "src_proto_addr_len")?,
+            dst_proto_addr: parse_ip(self.dst_proto_addr(), DST_PROTO_LEN, "dst_proto_addr_len")?,
         })
     }
 }
 
-fn parse_ip(a: &[u8]) -> Result<IpAddr> {
-        match a.len() {
-            4 => {
-                let addr = Ipv4Addr::new(a[0], a[1], a[2], a[3]);
-                Ok(IpAddr::V4(addr))
-            },
-            16 => {
-                let mut addr: [u16; 8] = [0;8];
-                for i in 0..8 {
-                    let start = i*2;
-                    let end = start + 2;
-                    addr[i] = u16::from_be_bytes(a[start..end].try_into().unwrap())
-                }
-                Ok(IpAddr::V6(addr.into()))
-            },
-            _ => Err(Error::NotImplemented),
-        }
+/// Parse a protocol address, `index` and `field` locating its length field for errors
+fn parse_ip(a: &[u8], index: usize, field: &str) -> Result<IpAddr> {
+    match a.len() {
+        4 => {
+            let addr = Ipv4Addr::new(a[0], a[1], a[2], a[3]);
+            Ok(IpAddr::V4(addr))
+        },
+        16 => {
+            let addr: [u8; 16] = a.try_into().unwrap();
+            Ok(IpAddr::V6(addr.into()))
+        },
+        len => Err(Error::malformed(index, 1, field, "4 or 16", len)),
+    }
 }
 
 fn iplen(i: &IpAddr) -> usize {
@@ -96,21 +205,105 @@ fn write_ip(buf: &mut [u8], addr: IpAddr) {
 
 impl Emitable for CommonHeader {
     fn buffer_len(&self) -> usize {
-        10 + iplen(&self.src_nbma_addr)
+        10 + self.src_nbma_addr.len()
            + iplen(&self.src_proto_addr)
            + iplen(&self.dst_proto_addr)
     }
 
     fn emit(&self, buffer: &mut [u8]) {
-        use self::AddrTL::*;
         let mut buffer = OperationBuffer::new(buffer);
-        buffer.set_src_nbma_addr_tl(NSAP(iplen(&self.src_nbma_addr) as u8));
-        buffer.set_src_nbma_saddr_tl(NSAP(0));
+        buffer.set_src_nbma_addr_tl(self.src_nbma_addr.addr_tl());
+        buffer.set_src_nbma_saddr_tl(self.src_nbma_addr.saddr_tl());
         buffer.set_src_proto_addr_len(iplen(&self.src_proto_addr) as u8);
         buffer.set_dst_proto_addr_len(iplen(&self.dst_proto_addr) as u8);
-        buffer.set_flags(self.flags);
         buffer.set_request_id(self.request_id);
-        write_ip(buffer.src_nbma_addr_mut(), self.src_nbma_addr);
+        buffer.src_nbma_addr_mut().copy_from_slice(self.src_nbma_addr.address());
+        buffer.src_nbma_saddr_mut().copy_from_slice(self.src_nbma_addr.subaddress());
+        write_ip(buffer.src_proto_addr_mut(), self.src_proto_addr);
+        write_ip(buffer.dst_proto_addr_mut(), self.dst_proto_addr);
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ErrorHeader> for ErrorIndicationBuffer<&T> {
+    fn parse(&self) -> Result<ErrorHeader> {
+        Ok(ErrorHeader {
+            code: self.error_code().into(),
+            offset: self.error_offset(),
+            src_nbma_addr: NbmaAddress::from_parts(self.src_nbma_addr_tl(), self.src_nbma_addr(),
+                self.src_nbma_saddr_tl(), self.src_nbma_saddr()),
+            src_proto_addr: parse_ip(self.src_proto_addr(), SRC_PROTO_LEN, "src_proto_addr_len")?,
+            dst_proto_addr: parse_ip(self.dst_proto_addr(), DST_PROTO_LEN, "dst_proto_addr_len")?,
+        })
+    }
+}
+
+impl Emitable for ErrorHeader {
+    fn buffer_len(&self) -> usize {
+        ERR_ADDRS.start + self.src_nbma_addr.len()
+          + iplen(&self.src_proto_addr)
+          + iplen(&self.dst_proto_addr)
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        let mut buffer = ErrorIndicationBuffer::new(buffer);
+        buffer.set_src_nbma_addr_tl(self.src_nbma_addr.addr_tl());
+        buffer.set_src_nbma_saddr_tl(self.src_nbma_addr.saddr_tl());
+        buffer.set_src_proto_addr_len(iplen(&self.src_proto_addr) as u8);
+        buffer.set_dst_proto_addr_len(iplen(&self.dst_proto_addr) as u8);
+        buffer.clear_unused();
+        buffer.set_error_code(self.code.into());
+        buffer.set_error_offset(self.offset);
+        buffer.src_nbma_addr_mut().copy_from_slice(self.src_nbma_addr.address());
+        buffer.src_nbma_saddr_mut().copy_from_slice(self.src_nbma_addr.subaddress());
+        write_ip(buffer.src_proto_addr_mut(), self.src_proto_addr);
+        write_ip(buffer.dst_proto_addr_mut(), self.dst_proto_addr);
+    }
+}
+
+/// Mandatory part of a Traffic Indication
+///
+/// Traffic Indications are not part of RFC 2332; this follows the Cisco DMVPN layout that
+/// opennhrp and FRR nhrpd implement as well.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrafficHeader {
+    pub code: TrafficCode,
+    pub src_nbma_addr: NbmaAddress,
+    pub src_proto_addr: IpAddr,
+    /// Source address of the offending packet
+    pub dst_proto_addr: IpAddr,
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<TrafficHeader> for ErrorIndicationBuffer<&T> {
+    fn parse(&self) -> Result<TrafficHeader> {
+        Ok(TrafficHeader {
+            code: self.error_code().into(),
+            src_nbma_addr: NbmaAddress::from_parts(self.src_nbma_addr_tl(), self.src_nbma_addr(),
+                self.src_nbma_saddr_tl(), self.src_nbma_saddr()),
+            src_proto_addr: parse_ip(self.src_proto_addr(), SRC_PROTO_LEN, "src_proto_addr_len")?,
+            dst_proto_addr: parse_ip(self.dst_proto_addr(), DST_PROTO_LEN, "dst_proto_addr_len")?,
+        })
+    }
+}
+
+impl Emitable for TrafficHeader {
+    fn buffer_len(&self) -> usize {
+        ERR_ADDRS.start + self.src_nbma_addr.len()
+          + iplen(&self.src_proto_addr)
+          + iplen(&self.dst_proto_addr)
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        let mut buffer = ErrorIndicationBuffer::new(buffer);
+        buffer.set_src_nbma_addr_tl(self.src_nbma_addr.addr_tl());
+        buffer.set_src_nbma_saddr_tl(self.src_nbma_addr.saddr_tl());
+        buffer.set_src_proto_addr_len(iplen(&self.src_proto_addr) as u8);
+        buffer.set_dst_proto_addr_len(iplen(&self.dst_proto_addr) as u8);
+        buffer.clear_unused();
+        buffer.set_error_code(self.code.into());
+        buffer.set_error_offset(0);
+        buffer.src_nbma_addr_mut().copy_from_slice(self.src_nbma_addr.address());
+        buffer.src_nbma_saddr_mut().copy_from_slice(self.src_nbma_addr.subaddress());
         write_ip(buffer.src_proto_addr_mut(), self.src_proto_addr);
         write_ip(buffer.dst_proto_addr_mut(), self.dst_proto_addr);
     }
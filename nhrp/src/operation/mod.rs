@@ -2,10 +2,15 @@ pub use self::buffer::*;
 pub mod buffer;
 pub use self::header::*;
 pub mod header;
+#[allow(clippy::module_inception)]
 pub mod operation;
 pub use self::operation::*;
 pub use self::cie::*;
 pub mod cie;
+mod nbma;
+pub use self::nbma::*;
+mod flags;
+pub use self::flags::*;
 
 mod resolution_request;
 pub use self::resolution_request::*;
@@ -17,3 +22,7 @@ mod registration_reply;
 pub use self::registration_reply::*;
 mod purge_message;
 pub use self::purge_message::*;
+mod error_indication;
+pub use self::error_indication::*;
+mod traffic_indication;
+pub use self::traffic_indication::*;
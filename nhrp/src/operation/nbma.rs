@@ -0,0 +1,276 @@
+use alloc::{string::ToString, vec::Vec};
+use core::fmt;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
+
+use super::AddrTL;
+use crate::{BuildError, Error, Result};
+
+/// Longest NBMA address or subaddress the type & length fields can describe
+pub const MAX_NBMA_LEN: usize = 63;
+
+/// Encoding of an NBMA address or subaddress, given by bit 6 of its type & length field
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum NbmaAddrType {
+    NSAP,
+    E164,
+}
+
+impl NbmaAddrType {
+    fn tl(self, len: usize) -> AddrTL {
+        match self {
+            NbmaAddrType::NSAP => AddrTL::NSAP(len as u8),
+            NbmaAddrType::E164 => AddrTL::E164(len as u8),
+        }
+    }
+}
+
+impl From<AddrTL> for NbmaAddrType {
+    fn from(value: AddrTL) -> NbmaAddrType {
+        match value {
+            AddrTL::NSAP(_) => NbmaAddrType::NSAP,
+            AddrTL::E164(_) => NbmaAddrType::E164,
+        }
+    }
+}
+
+/// An NBMA subaddress, i.e. the contents of the `sstl`-described field
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct NbmaSubaddress {
+    addr_type: NbmaAddrType,
+    address: Vec<u8>,
+}
+
+fn check_len(address: &[u8], field: &str) -> Result<()> {
+    if address.len() > MAX_NBMA_LEN {
+        return Err(BuildError::AddressTooLong { field: field.to_string(), len: address.len() }.into());
+    }
+    Ok(())
+}
+
+/// An NBMA address and its optional subaddress.
+///
+/// The address is kept as raw octets since its interpretation depends on the address family of
+/// the packet. For the GRE tunnels NHRP is mostly used with today this is an IPv4 or IPv6
+/// address, which [`NbmaAddress::ip`] and the `From` impls convert from and to. Addresses and
+/// subaddresses are limited to [`MAX_NBMA_LEN`] octets by the wire format; the constructors
+/// refuse longer ones, so every `NbmaAddress` can be emitted.
+///
+/// The `Display` and `FromStr` impls use a textual form that keeps all of the address: IP
+/// addresses as usual, E.164 numbers as `+` and their digits, anything else as hex octets
+/// (prefixed with `e164:` for E.164 addresses that aren't digits), followed by `/` and the
+/// subaddress in the same form if there is one.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct NbmaAddress {
+    addr_type: NbmaAddrType,
+    address: Vec<u8>,
+    subaddress: Option<NbmaSubaddress>,
+}
+
+impl NbmaAddress {
+    /// Fails with [`BuildError::AddressTooLong`] if `address` is longer than [`MAX_NBMA_LEN`]
+    pub fn new(addr_type: NbmaAddrType, address: Vec<u8>) -> Result<Self> {
+        check_len(&address, "NBMA address")?;
+        Ok(NbmaAddress {
+            addr_type,
+            address,
+            subaddress: None,
+        })
+    }
+
+    /// Fails with [`BuildError::AddressTooLong`] if `address` is longer than [`MAX_NBMA_LEN`]
+    pub fn with_subaddress(mut self, addr_type: NbmaAddrType, address: Vec<u8>) -> Result<Self> {
+        check_len(&address, "NBMA subaddress")?;
+        self.subaddress = Some(NbmaSubaddress { addr_type, address });
+        Ok(self)
+    }
+
+    /// Build an address from its type & length fields and the raw octets they describe. An empty
+    /// subaddress is treated as no subaddress at all.
+    pub(crate) fn from_parts(addr_tl: AddrTL, address: &[u8], saddr_tl: AddrTL, subaddress: &[u8]) -> Self {
+        let subaddress = if subaddress.is_empty() {
+            None
+        } else {
+            Some(NbmaSubaddress {
+                addr_type: saddr_tl.into(),
+                address: subaddress.to_vec(),
+            })
+        };
+
+        NbmaAddress {
+            addr_type: addr_tl.into(),
+            address: address.to_vec(),
+            subaddress,
+        }
+    }
+
+    pub fn addr_type(&self) -> NbmaAddrType {
+        self.addr_type
+    }
+
+    /// Octets of the address without its subaddress
+    pub fn address(&self) -> &[u8] {
+        &self.address
+    }
+
+    /// The IP address this NBMA address represents, if it is one
+    pub fn ip(&self) -> Option<IpAddr> {
+        if self.addr_type != NbmaAddrType::NSAP {
+            return None;
+        }
+        match self.address.len() {
+            4 => {
+                let octets: [u8; 4] = self.address[..].try_into().unwrap();
+                Some(Ipv4Addr::from(octets).into())
+            },
+            16 => {
+                let octets: [u8; 16] = self.address[..].try_into().unwrap();
+                Some(Ipv6Addr::from(octets).into())
+            },
+            _ => None,
+        }
+    }
+
+    /// Type & length field of the address
+    pub fn addr_tl(&self) -> AddrTL {
+        self.addr_type.tl(self.address.len())
+    }
+
+    /// Type & length field of the subaddress
+    pub fn saddr_tl(&self) -> AddrTL {
+        match self.subaddress {
+            Some(ref s) => s.addr_type.tl(s.address.len()),
+            None => AddrTL::NSAP(0),
+        }
+    }
+
+    pub fn subaddress(&self) -> &[u8] {
+        match self.subaddress {
+            Some(ref s) => &s.address,
+            None => &[],
+        }
+    }
+
+    /// Combined length of address and subaddress in octets
+    pub fn len(&self) -> usize {
+        self.address.len() + self.subaddress().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<IpAddr> for NbmaAddress {
+    fn from(value: IpAddr) -> NbmaAddress {
+        match value {
+            IpAddr::V4(addr) => addr.into(),
+            IpAddr::V6(addr) => addr.into(),
+        }
+    }
+}
+impl From<Ipv4Addr> for NbmaAddress {
+    fn from(value: Ipv4Addr) -> NbmaAddress {
+        NbmaAddress::from_parts(AddrTL::NSAP(4), &value.octets(), AddrTL::NSAP(0), &[])
+    }
+}
+impl From<Ipv6Addr> for NbmaAddress {
+    fn from(value: Ipv6Addr) -> NbmaAddress {
+        NbmaAddress::from_parts(AddrTL::NSAP(16), &value.octets(), AddrTL::NSAP(0), &[])
+    }
+}
+
+impl PartialEq<IpAddr> for NbmaAddress {
+    fn eq(&self, other: &IpAddr) -> bool {
+        self.subaddress.is_none() && self.ip().as_ref() == Some(other)
+    }
+}
+
+fn write_octets(f: &mut fmt::Formatter<'_>, addr_type: NbmaAddrType, octets: &[u8]) -> fmt::Result {
+    // E.164 numbers are carried as IA5 digits
+    if addr_type == NbmaAddrType::E164 {
+        if !octets.is_empty() && octets.iter().all(u8::is_ascii_digit) {
+            return write!(f, "+{}", core::str::from_utf8(octets).unwrap());
+        }
+        f.write_str("e164:")?;
+    }
+    for octet in octets {
+        write!(f, "{:02x}", octet)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for NbmaAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip() {
+            Some(ip) => write!(f, "{}", ip)?,
+            None => write_octets(f, self.addr_type, &self.address)?,
+        }
+        if let Some(ref sub) = self.subaddress {
+            f.write_str("/")?;
+            write_octets(f, sub.addr_type, &sub.address)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_octets(s: &str) -> Option<(NbmaAddrType, Vec<u8>)> {
+    if let Some(digits) = s.strip_prefix('+') {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        return Some((NbmaAddrType::E164, digits.as_bytes().to_vec()));
+    }
+    let (addr_type, hex) = match s.strip_prefix("e164:") {
+        Some(hex) => (NbmaAddrType::E164, hex),
+        None => (NbmaAddrType::NSAP, s),
+    };
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let octets = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect();
+    Some((addr_type, octets))
+}
+
+impl FromStr for NbmaAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<NbmaAddress> {
+        let invalid = || Error::InvalidNbmaAddress(s.to_string());
+        let (address, subaddress) = match s.split_once('/') {
+            Some((address, subaddress)) => (address, Some(subaddress)),
+            None => (s, None),
+        };
+
+        let mut nbma = match address.parse::<IpAddr>() {
+            Ok(ip) => NbmaAddress::from(ip),
+            Err(_) => {
+                let (addr_type, octets) = parse_octets(address).ok_or_else(invalid)?;
+                NbmaAddress::new(addr_type, octets)?
+            },
+        };
+        if let Some(subaddress) = subaddress {
+            let (addr_type, octets) = parse_octets(subaddress).ok_or_else(invalid)?;
+            nbma = nbma.with_subaddress(addr_type, octets)?;
+        }
+        Ok(nbma)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NbmaAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NbmaAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> core::result::Result<NbmaAddress, D::Error> {
+        let s = <alloc::string::String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
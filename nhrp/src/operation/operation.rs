@@ -1,6 +1,9 @@
 use super::*;
+use crate::Emitable;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Operation {
     ResolutionRequest(ResolutionRequestMessage),
     ResolutionReply(ResolutionReplyMessage),
@@ -8,6 +11,39 @@ pub enum Operation {
     RegistrationReply(RegistrationReplyMessage),
     PurgeRequest(PurgeMessage),
     PurgeReply(PurgeMessage),
+    ErrorIndication(ErrorIndicationMessage),
+    TrafficIndication(TrafficIndicationMessage),
+}
+
+impl From<ResolutionRequestMessage> for Operation {
+    fn from(value: ResolutionRequestMessage) -> Operation {
+        Operation::ResolutionRequest(value)
+    }
+}
+impl From<ResolutionReplyMessage> for Operation {
+    fn from(value: ResolutionReplyMessage) -> Operation {
+        Operation::ResolutionReply(value)
+    }
+}
+impl From<RegistrationRequestMessage> for Operation {
+    fn from(value: RegistrationRequestMessage) -> Operation {
+        Operation::RegistrationRequest(value)
+    }
+}
+impl From<RegistrationReplyMessage> for Operation {
+    fn from(value: RegistrationReplyMessage) -> Operation {
+        Operation::RegistrationReply(value)
+    }
+}
+impl From<ErrorIndicationMessage> for Operation {
+    fn from(value: ErrorIndicationMessage) -> Operation {
+        Operation::ErrorIndication(value)
+    }
+}
+impl From<TrafficIndicationMessage> for Operation {
+    fn from(value: TrafficIndicationMessage) -> Operation {
+        Operation::TrafficIndication(value)
+    }
 }
 
 use crate::header::NhrpOp;
@@ -23,6 +59,67 @@ impl Operation {
             RegistrationReply(_) => N::RegistrationReply,
             PurgeRequest(_) => N::PurgeRequest,
             PurgeReply(_) => N::PurgeReply,
+            ErrorIndication(_) => N::ErrorIndication,
+            TrafficIndication(_) => N::TrafficIndication,
+        }
+    }
+
+    /// The common header of this operation. Error and Traffic Indications have their own header
+    /// layout and thus return `None`.
+    pub fn common_header(&self) -> Option<&CommonHeader> {
+        use Operation::*;
+        match *self {
+            ResolutionRequest(ref msg) => Some(msg.header()),
+            ResolutionReply(ref msg) => Some(msg.header()),
+            RegistrationRequest(ref msg) => Some(msg.header()),
+            RegistrationReply(ref msg) => Some(msg.header()),
+            PurgeRequest(ref msg) => Some(msg.header()),
+            PurgeReply(ref msg) => Some(msg.header()),
+            ErrorIndication(_) | TrafficIndication(_) => None,
+        }
+    }
+
+    /// The CIEs in the mandatory part of this operation, in the order they are on the wire
+    pub fn cies(&self) -> &[ClientInformationEntry] {
+        use Operation::*;
+        match *self {
+            ResolutionRequest(ref msg) => msg.cie().map_or(&[], core::slice::from_ref),
+            ResolutionReply(ref msg) => msg.cie(),
+            RegistrationRequest(ref msg) => msg.cie(),
+            RegistrationReply(ref msg) => msg.cie(),
+            PurgeRequest(ref msg) => msg.cie(),
+            PurgeReply(ref msg) => msg.cie(),
+            ErrorIndication(_) | TrafficIndication(_) => &[],
+        }
+    }
+}
+
+impl Emitable for Operation {
+    fn buffer_len(&self) -> usize {
+        use Operation::*;
+        match *self {
+            ResolutionRequest(ref msg) => msg.buffer_len(),
+            ResolutionReply(ref msg) => msg.buffer_len(),
+            RegistrationRequest(ref msg) => msg.buffer_len(),
+            RegistrationReply(ref msg) => msg.buffer_len(),
+            PurgeRequest(ref msg) => msg.buffer_len(),
+            PurgeReply(ref msg) => msg.buffer_len(),
+            ErrorIndication(ref msg) => msg.buffer_len(),
+            TrafficIndication(ref msg) => msg.buffer_len(),
+        }
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        use Operation::*;
+        match *self {
+            ResolutionRequest(ref msg) => msg.emit(buffer),
+            ResolutionReply(ref msg) => msg.emit(buffer),
+            RegistrationRequest(ref msg) => msg.emit(buffer),
+            RegistrationReply(ref msg) => msg.emit(buffer),
+            PurgeRequest(ref msg) => msg.emit(buffer),
+            PurgeReply(ref msg) => msg.emit(buffer),
+            ErrorIndication(ref msg) => msg.emit(buffer),
+            TrafficIndication(ref msg) => msg.emit(buffer),
         }
     }
 }
@@ -1,43 +1,50 @@
+use alloc::vec::Vec;
 use crate::{Parseable, Emitable, Result};
 use super::*;
-use super::cie::buffer::CieIterator;
+use super::cie::parse_cies;
 use super::cie::message::ClientInformationEntry;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PurgeMessage {
     header: CommonHeader,
+    flags: PurgeFlags,
     cie: Vec<ClientInformationEntry>,
 }
 
 impl PurgeMessage {
+    pub fn new(header: CommonHeader, flags: PurgeFlags, cie: Vec<ClientInformationEntry>) -> Self {
+        PurgeMessage {
+            header, flags, cie,
+        }
+    }
+
     pub fn header(&self) -> &CommonHeader {
         &self.header
     }
 
+    pub fn flags(&self) -> PurgeFlags {
+        self.flags
+    }
+
     pub fn cie(&self) -> &Vec<ClientInformationEntry> {
         &self.cie
     }
 
     #[allow(dead_code)]
-    pub fn into_parts(self) -> (CommonHeader, Vec<ClientInformationEntry>) {
-        (self.header, self.cie)
+    pub fn into_parts(self) -> (CommonHeader, PurgeFlags, Vec<ClientInformationEntry>) {
+        (self.header, self.flags, self.cie)
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<PurgeMessage> for OperationBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<PurgeMessage> for OperationBuffer<&T> {
     fn parse(&self) -> Result<PurgeMessage> {
         let header = <Self as Parseable<CommonHeader>>::parse(self)?;
-        let cies = CieIterator::new(self.payload());
-        let mut ciev = Vec::new();
-        for cie in cies {
-            match cie {
-                Ok(cie) => ciev.push(cie.parse()?),
-                Err(e) => return Err(e),
-            }
-        }
+        let ciev = parse_cies(self.payload()).map_err(|e| e.within(self.length(), ""))?;
 
         Ok(PurgeMessage {
-            header: header,
+            header,
+            flags: self.flags().into(),
             cie: ciev,
         })
     }
@@ -50,6 +57,7 @@ impl Emitable for PurgeMessage {
 
     fn emit(&self, buffer: &mut [u8]) {
         self.header.emit(buffer);
+        OperationBuffer::new(&mut *buffer).set_flags(self.flags.into());
         let buffer = &mut buffer[self.header.buffer_len()..];
         self.cie.emit(buffer);
     }
@@ -1,16 +1,18 @@
+use alloc::vec::Vec;
 use crate::{Parseable, Emitable, Result};
 use super::*;
-use super::cie::buffer::CieBuffer;
+use super::cie::parse_cies;
 use super::cie::message::ClientInformationEntry;
 
-use std::net::IpAddr;
-
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum RegistrationCode {
     Success,
     Prohibited,
     InsufficientResources,
     AlreadyRegistered,
+    #[cfg_attr(feature = "serde", serde(untagged))]
     Unknown(u8)
 }
 impl From<u8> for RegistrationCode {
@@ -38,50 +40,72 @@ impl From<RegistrationCode> for u8 {
     }
 }
 
+impl From<RegistrationCode> for CieCode {
+    fn from(value: RegistrationCode) -> CieCode {
+        u8::from(value).into()
+    }
+}
+impl From<CieCode> for RegistrationCode {
+    fn from(value: CieCode) -> RegistrationCode {
+        u8::from(value).into()
+    }
+}
+
+/// Reply to a Registration Request (RFC 2332, 5.2.4)
+///
+/// Every CIE of the request is echoed with a code of its own telling whether that binding was
+/// registered.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegistrationReplyMessage {
     header: CommonHeader,
-    cie: ClientInformationEntry,
+    flags: RegistrationFlags,
+    cie: Vec<ClientInformationEntry>,
 }
 
 impl RegistrationReplyMessage {
-    pub fn new(request_id: u32,
-               code: RegistrationCode,
-               mut cie: ClientInformationEntry,
-               src_nbma_addr: IpAddr,
-               src_proto_addr: IpAddr,
-               dst_proto_addr: IpAddr,
-               unique: bool
-    ) -> Self {
-        let header = CommonHeader {
-            flags: if unique { 0x8000 } else { 0 },
-            request_id: request_id,
-            src_nbma_addr: src_nbma_addr,
-            src_proto_addr: src_proto_addr,
-            dst_proto_addr: dst_proto_addr,
-        };
-
-        cie.code = code.into();
-
+    pub fn new(header: CommonHeader, flags: RegistrationFlags, cie: Vec<ClientInformationEntry>) -> Self {
         RegistrationReplyMessage {
-            header: header, cie: cie,
+            header, flags, cie,
         }
     }
 
+    pub fn header(&self) -> &CommonHeader {
+        &self.header
+    }
+
+    pub fn flags(&self) -> RegistrationFlags {
+        self.flags
+    }
+
+    pub fn cie(&self) -> &Vec<ClientInformationEntry> {
+        &self.cie
+    }
+
+    /// Outcome of the registration as a whole: the code of the first CIE that was refused, or
+    /// `Success` if all of them were registered
+    pub fn code(&self) -> RegistrationCode {
+        self.cie.iter()
+            .map(|cie| RegistrationCode::from(cie.code))
+            .find(|code| *code != RegistrationCode::Success)
+            .unwrap_or(RegistrationCode::Success)
+    }
+
     #[allow(dead_code)]
-    pub fn into_parts(self) -> (CommonHeader, ClientInformationEntry) {
-        (self.header, self.cie)
+    pub fn into_parts(self) -> (CommonHeader, RegistrationFlags, Vec<ClientInformationEntry>) {
+        (self.header, self.flags, self.cie)
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<RegistrationReplyMessage> for OperationBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<RegistrationReplyMessage> for OperationBuffer<&T> {
     fn parse(&self) -> Result<RegistrationReplyMessage> {
         let header = <Self as Parseable<CommonHeader>>::parse(self)?;
-        let cie = CieBuffer::new_checked(self.payload())?.parse()?;
+        let ciev = parse_cies(self.payload()).map_err(|e| e.within(self.length(), ""))?;
 
         Ok(RegistrationReplyMessage {
-            header: header,
-            cie: cie,
+            header,
+            flags: self.flags().into(),
+            cie: ciev,
         })
     }
 }
@@ -93,6 +117,7 @@ impl Emitable for RegistrationReplyMessage {
 
     fn emit(&self, buffer: &mut [u8]) {
         self.header.emit(buffer);
+        OperationBuffer::new(&mut *buffer).set_flags(self.flags.into());
         let buffer = &mut buffer[self.header.buffer_len()..];
         self.cie.emit(buffer);
     }
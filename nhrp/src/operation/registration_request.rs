@@ -1,20 +1,23 @@
+use alloc::vec::Vec;
 use crate::{Parseable, Emitable, Result};
 use super::*;
-use super::cie::buffer::CieIterator;
+use super::cie::parse_cies;
 use super::cie::message::ClientInformationEntry;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegistrationRequestMessage {
     header: CommonHeader,
+    flags: RegistrationFlags,
     cie: Vec<ClientInformationEntry>,
 }
 
 impl RegistrationRequestMessage {
     #![allow(dead_code)]
 
-    pub fn new(header: CommonHeader, cie: Vec<ClientInformationEntry>) -> Self {
+    pub fn new(header: CommonHeader, flags: RegistrationFlags, cie: Vec<ClientInformationEntry>) -> Self {
         RegistrationRequestMessage {
-            header: header, cie: cie,
+            header, flags, cie,
         }
     }
 
@@ -22,25 +25,27 @@ impl RegistrationRequestMessage {
         &self.header
     }
 
-    pub fn into_parts(self) -> (CommonHeader, Vec<ClientInformationEntry>) {
-        (self.header, self.cie)
+    pub fn flags(&self) -> RegistrationFlags {
+        self.flags
+    }
+
+    pub fn cie(&self) -> &Vec<ClientInformationEntry> {
+        &self.cie
+    }
+
+    pub fn into_parts(self) -> (CommonHeader, RegistrationFlags, Vec<ClientInformationEntry>) {
+        (self.header, self.flags, self.cie)
     }
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<RegistrationRequestMessage> for OperationBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<RegistrationRequestMessage> for OperationBuffer<&T> {
     fn parse(&self) -> Result<RegistrationRequestMessage> {
         let header = <Self as Parseable<CommonHeader>>::parse(self)?;
-        let cies = CieIterator::new(self.payload());
-        let mut ciev = Vec::new();
-        for cie in cies {
-            match cie {
-                Ok(cie) => ciev.push(cie.parse()?),
-                Err(e) => return Err(e),
-            }
-        }
+        let ciev = parse_cies(self.payload()).map_err(|e| e.within(self.length(), ""))?;
 
         Ok(RegistrationRequestMessage {
-            header: header,
+            header,
+            flags: self.flags().into(),
             cie: ciev,
         })
     }
@@ -53,6 +58,7 @@ impl Emitable for RegistrationRequestMessage {
 
     fn emit(&self, buffer: &mut [u8]) {
         self.header.emit(buffer);
+        OperationBuffer::new(&mut *buffer).set_flags(self.flags.into());
         let buffer = &mut buffer[self.header.buffer_len()..];
         self.cie.emit(buffer);
     }
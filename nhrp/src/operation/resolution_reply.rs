@@ -1,17 +1,21 @@
+use alloc::{vec, vec::Vec};
 use crate::{Parseable, Emitable, Result};
 use super::*;
-use super::cie::buffer::CieIterator;
+use super::cie::parse_cies;
 use super::cie::message::ClientInformationEntry;
 
-use std::net::IpAddr;
+use core::net::IpAddr;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ResolutionCode {
     Success,
     Prohibited,
     InsufficientResources,
     NoBindingExists,
     BindingNotUnique,
+    #[cfg_attr(feature = "serde", serde(untagged))]
     Unknown(u8)
 }
 impl From<u8> for ResolutionCode {
@@ -41,30 +45,39 @@ impl From<ResolutionCode> for u8 {
     }
 }
 
+impl From<ResolutionCode> for CieCode {
+    fn from(value: ResolutionCode) -> CieCode {
+        u8::from(value).into()
+    }
+}
+impl From<CieCode> for ResolutionCode {
+    fn from(value: CieCode) -> ResolutionCode {
+        u8::from(value).into()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResolutionReplyMessage {
     header: CommonHeader,
+    flags: ResolutionFlags,
     cie: Vec<ClientInformationEntry>,
 }
 
 impl ResolutionReplyMessage {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(request_id: u32,
                code: ResolutionCode,
-               src_n_a: IpAddr,
+               src_n_a: NbmaAddress,
                src_p_a: IpAddr,
-               dst_n_a: Option<IpAddr>,
+               dst_n_a: Option<NbmaAddress>,
                dst_p_a: IpAddr,
-               requester_router: bool,
-               authorative: bool,
-               unique: bool,
-               src_stable: bool,
-               dst_stable: bool,
+               flags: ResolutionFlags,
                holding_time: u16,
                prefix_len: u8
     ) -> Self {
         let header = CommonHeader {
-            flags: ((requester_router as u16)<<15) | ((authorative as u16)<<14) | ((dst_stable as u16)<<13) | ((unique as u16)<<12) | ((src_stable as u16)<<11),
-            request_id: request_id,
+            request_id,
             src_nbma_addr: src_n_a,
             src_proto_addr: src_p_a,
             dst_proto_addr: dst_p_a,
@@ -75,10 +88,10 @@ impl ResolutionReplyMessage {
                 code: code.into(),
                 client_nbma_addr: Some(dst),
                 client_proto_addr: Some(dst_p_a),
-                holding_time: holding_time,
+                holding_time,
                 mtu: 0,
                 preference: 0,
-                prefix_len: prefix_len
+                prefix_len
             },
             None => ClientInformationEntry {
                 code: code.into(),
@@ -92,31 +105,43 @@ impl ResolutionReplyMessage {
         };
 
         ResolutionReplyMessage {
-            header: header, cie: vec![cie],
+            header, flags, cie: vec![cie],
+        }
+    }
+
+    pub(crate) fn from_parts(header: CommonHeader, flags: ResolutionFlags, cie: Vec<ClientInformationEntry>) -> Self {
+        ResolutionReplyMessage {
+            header, flags, cie,
         }
     }
 
+    pub fn header(&self) -> &CommonHeader {
+        &self.header
+    }
+
+    pub fn flags(&self) -> ResolutionFlags {
+        self.flags
+    }
+
+    pub fn cie(&self) -> &Vec<ClientInformationEntry> {
+        &self.cie
+    }
+
     #[allow(dead_code)]
-    pub fn into_parts(self) -> (CommonHeader, Vec<ClientInformationEntry>) {
-        (self.header, self.cie)
+    pub fn into_parts(self) -> (CommonHeader, ResolutionFlags, Vec<ClientInformationEntry>) {
+        (self.header, self.flags, self.cie)
     }
 }
                
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ResolutionReplyMessage> for OperationBuffer<&'a T> {
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ResolutionReplyMessage> for OperationBuffer<&T> {
     fn parse(&self) -> Result<ResolutionReplyMessage> {
         let header = <Self as Parseable<CommonHeader>>::parse(self)?;
-        let cies = CieIterator::new(self.payload());
-        let mut ciev = Vec::new();
-        for cie in cies {
-            match cie {
-                Ok(cie) => ciev.push(cie.parse()?),
-                Err(e) => return Err(e),
-            }
-        }
+        let ciev = parse_cies(self.payload()).map_err(|e| e.within(self.length(), ""))?;
 
         Ok(ResolutionReplyMessage {
-            header: header,
+            header,
+            flags: self.flags().into(),
             cie: ciev,
         })
     }
@@ -129,6 +154,7 @@ impl Emitable for ResolutionReplyMessage {
 
     fn emit(&self, buffer: &mut [u8]) {
         self.header.emit(buffer);
+        OperationBuffer::new(&mut *buffer).set_flags(self.flags.into());
         let buffer = &mut buffer[self.header.buffer_len()..];
         self.cie.emit(buffer);
     }
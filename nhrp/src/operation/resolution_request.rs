@@ -1,26 +1,53 @@
-use crate::{Parseable, Emitable, Result, Error};
+use crate::{Parseable, Emitable, Result};
 use super::*;
-use super::cie::buffer::CieBuffer;
+use super::cie::parse_cies;
 use super::cie::message::ClientInformationEntry;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResolutionRequestMessage {
     header: CommonHeader,
+    flags: ResolutionFlags,
     cie: Option<ClientInformationEntry>,
 }
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<ResolutionRequestMessage> for OperationBuffer<&'a T> {
+impl ResolutionRequestMessage {
+    pub fn new(header: CommonHeader, flags: ResolutionFlags, cie: Option<ClientInformationEntry>) -> Self {
+        ResolutionRequestMessage {
+            header, flags, cie,
+        }
+    }
+
+    pub fn header(&self) -> &CommonHeader {
+        &self.header
+    }
+
+    pub fn flags(&self) -> ResolutionFlags {
+        self.flags
+    }
+
+    pub fn cie(&self) -> Option<&ClientInformationEntry> {
+        self.cie.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn into_parts(self) -> (CommonHeader, ResolutionFlags, Option<ClientInformationEntry>) {
+        (self.header, self.flags, self.cie)
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<ResolutionRequestMessage> for OperationBuffer<&T> {
     fn parse(&self) -> Result<ResolutionRequestMessage> {
         let header = <Self as Parseable<CommonHeader>>::parse(self)?;
-        let cie = match CieBuffer::new_checked(self.payload()) {
-            Ok(buffer) => Some(buffer.parse()?),
-            Err(Error::Truncated) => None,
-            Err(e) => return Err(e),
-        };
+        let cie = parse_cies(self.payload())
+            .map_err(|e| e.within(self.length(), ""))?
+            .into_iter()
+            .next();
 
         Ok(ResolutionRequestMessage {
-            header: header,
-            cie: cie,
+            header,
+            flags: self.flags().into(),
+            cie,
         })
     }
 }
@@ -32,13 +59,11 @@ impl Emitable for ResolutionRequestMessage {
 
     fn emit(&self, buffer: &mut [u8]) {
         self.header.emit(buffer);
-        match self.cie {
-            Some(_) => {
-                let endoffset = self.header.buffer_len() + self.cie.buffer_len();
-                let buffer = &mut buffer[self.header.buffer_len()..endoffset];
-                self.cie.emit(buffer);
-            },
-            None => ()
+        OperationBuffer::new(&mut *buffer).set_flags(self.flags.into());
+        if self.cie.is_some() {
+            let endoffset = self.header.buffer_len() + self.cie.buffer_len();
+            let buffer = &mut buffer[self.header.buffer_len()..endoffset];
+            self.cie.emit(buffer);
         }
     }
 }
@@ -0,0 +1,77 @@
+use alloc::vec::Vec;
+use crate::{Parseable, Emitable, Result};
+use super::*;
+
+use core::net::IpAddr;
+
+/// Traffic Indication, sent by a router that forwarded a packet back out the interface it
+/// arrived on to tell the originating station to resolve a shortcut instead.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrafficIndicationMessage {
+    header: TrafficHeader,
+    #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
+    packet: Vec<u8>,
+}
+
+impl TrafficIndicationMessage {
+    pub fn new(code: TrafficCode,
+               src_nbma_addr: NbmaAddress,
+               src_proto_addr: IpAddr,
+               dst_proto_addr: IpAddr,
+               packet: Vec<u8>,
+    ) -> Self {
+        let header = TrafficHeader {
+            code,
+            src_nbma_addr,
+            src_proto_addr,
+            dst_proto_addr,
+        };
+
+        TrafficIndicationMessage {
+            header, packet,
+        }
+    }
+
+    pub fn header(&self) -> &TrafficHeader {
+        &self.header
+    }
+
+    pub fn code(&self) -> TrafficCode {
+        self.header.code
+    }
+
+    /// The leading bytes of the data packet that triggered this indication, starting with its
+    /// network layer header
+    pub fn packet(&self) -> &[u8] {
+        &self.packet
+    }
+
+    #[allow(dead_code)]
+    pub fn into_parts(self) -> (TrafficHeader, Vec<u8>) {
+        (self.header, self.packet)
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> Parseable<TrafficIndicationMessage> for ErrorIndicationBuffer<&T> {
+    fn parse(&self) -> Result<TrafficIndicationMessage> {
+        let header = <Self as Parseable<TrafficHeader>>::parse(self)?;
+
+        Ok(TrafficIndicationMessage {
+            header,
+            packet: self.payload().to_vec(),
+        })
+    }
+}
+
+impl Emitable for TrafficIndicationMessage {
+    fn buffer_len(&self) -> usize {
+        self.header.buffer_len() + self.packet.len()
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        self.header.emit(buffer);
+        let buffer = &mut buffer[self.header.buffer_len()..self.buffer_len()];
+        buffer.copy_from_slice(&self.packet);
+    }
+}
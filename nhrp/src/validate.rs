@@ -0,0 +1,161 @@
+//! Checks of parsed messages against the rules of RFC 2332 that parsing alone doesn't enforce.
+//!
+//! Parsing only rejects packets that can't be decoded. A message that decodes fine can still
+//! make no sense, e.g. a Resolution Reply without an answer. [`NhrpMessage::validate`] finds
+//! those, leaving it to the receiver to decide what to do about them.
+
+use alloc::vec::Vec;
+use core::net::IpAddr;
+
+use thiserror::Error;
+
+use crate::buffer::{FIXED_HEADER_LEN, HOPCOUNT, VERSION};
+use crate::builder::PREFIX_LEN_FULL;
+use crate::header::NhrpOp;
+use crate::message::NhrpMessage;
+use crate::view::NhrpMessageView;
+use crate::operation::{CieCode, ErrorCode, Operation};
+use crate::operation::buffer::ERR_OFFSET;
+use crate::cie::buffer::{CODE, PREFIX_LEN};
+use crate::Emitable;
+
+/// The only version of NHRP there is
+const NHRP_VERSION: u8 = 1;
+
+/// Part of a message a [`Violation`] is in, so receivers can treat them differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ViolationClass {
+    /// The fixed header
+    Header,
+    /// The mandatory part of the operation, apart from the contents of its CIEs
+    Operation,
+    /// The contents of the CIEs of the operation
+    Cie,
+}
+
+/// A rule of RFC 2332 a message breaks even though it could be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Violation {
+    #[error("version is {0}, but only version 1 is defined")]
+    Version(u8),
+    #[error("hop count is zero")]
+    ZeroHopCount,
+    #[error("{optype:?} carries no CIE")]
+    MissingCie { optype: NhrpOp },
+    #[error("CIE[{index}] of a {optype:?} has code {code:?}, but requests must carry code 0")]
+    RequestCode { optype: NhrpOp, index: usize, offset: usize, code: CieCode },
+    #[error("CIE[{index}] has prefix length {prefix_len}, longer than the {width} bits of its protocol address")]
+    PrefixTooLong { index: usize, offset: usize, prefix_len: u8, width: u8 },
+    #[error("error offset {offset} is outside of the {len} octet packet in error")]
+    ErrorOffset { offset: u16, len: usize },
+}
+
+impl Violation {
+    pub fn class(&self) -> ViolationClass {
+        use Violation::*;
+        match *self {
+            Version(_) | ZeroHopCount => ViolationClass::Header,
+            MissingCie { .. } | ErrorOffset { .. } => ViolationClass::Operation,
+            RequestCode { .. } | PrefixTooLong { .. } => ViolationClass::Cie,
+        }
+    }
+
+    /// Section of RFC 2332 the broken rule is stated in
+    pub fn section(&self) -> &'static str {
+        use Violation::*;
+        match *self {
+            Version(_) | ZeroHopCount => "5.1",
+            MissingCie { optype: NhrpOp::RegistrationReply } => "5.2.4",
+            MissingCie { .. } => "5.2.2",
+            RequestCode { optype: NhrpOp::ResolutionRequest, .. } => "5.2.1",
+            RequestCode { optype: NhrpOp::RegistrationRequest, .. } => "5.2.3",
+            RequestCode { .. } => "5.2.5",
+            PrefixTooLong { .. } => "5.2.0.1",
+            ErrorOffset { .. } => "5.2.7",
+        }
+    }
+
+    /// Offset in octets of the offending field, counted from the start of the packet. Used as
+    /// the error offset when answering with an Error Indication.
+    pub fn offset(&self) -> usize {
+        use Violation::*;
+        match *self {
+            Version(_) => VERSION,
+            ZeroHopCount => HOPCOUNT,
+            MissingCie { .. } => FIXED_HEADER_LEN,
+            RequestCode { offset, .. } => offset + CODE,
+            PrefixTooLong { offset, .. } => offset + PREFIX_LEN,
+            ErrorOffset { .. } => FIXED_HEADER_LEN + ERR_OFFSET.start,
+        }
+    }
+
+    /// Error code to answer the message with
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            Violation::ZeroHopCount => ErrorCode::HopCountExceeded,
+            Violation::MissingCie { optype: NhrpOp::ResolutionReply } => ErrorCode::InvalidResolutionReply,
+            _ => ErrorCode::ProtocolError,
+        }
+    }
+}
+
+fn width(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl NhrpMessage {
+    /// Check the message against the rules of RFC 2332 that parsing doesn't enforce. The
+    /// version is lost in parsing, see [`NhrpMessageView::validate`] for it.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if self.header.hopcount() == 0 {
+            violations.push(Violation::ZeroHopCount);
+        }
+
+        let optype = self.operation.optype();
+        let cies = self.operation.cies();
+        let request = !matches!(optype, NhrpOp::ResolutionReply | NhrpOp::RegistrationReply);
+        match self.operation {
+            // Registration Requests without CIEs register the source addresses
+            Operation::ResolutionReply(_) | Operation::RegistrationReply(_) if cies.is_empty() =>
+                violations.push(Violation::MissingCie { optype }),
+            Operation::ErrorIndication(ref msg) => {
+                let offset = msg.header().offset;
+                if offset != 0 && offset as usize >= msg.packet().len() {
+                    violations.push(Violation::ErrorOffset { offset, len: msg.packet().len() });
+                }
+            },
+            _ => {},
+        }
+
+        if let Some(header) = self.operation.common_header() {
+            let mut offset = FIXED_HEADER_LEN + header.buffer_len();
+            for (index, cie) in cies.iter().enumerate() {
+                if request && cie.code != CieCode::Success {
+                    violations.push(Violation::RequestCode { optype, index, offset, code: cie.code });
+                }
+                let width = width(cie.client_proto_addr.as_ref().unwrap_or(&header.src_proto_addr));
+                if cie.prefix_len > width && cie.prefix_len != PREFIX_LEN_FULL {
+                    violations.push(Violation::PrefixTooLong { index, offset, prefix_len: cie.prefix_len, width });
+                }
+                offset += cie.buffer_len();
+            }
+        }
+        violations
+    }
+}
+
+impl<'a> NhrpMessageView<'a> {
+    /// Check the parts of the encoding that are lost in parsing against RFC 2332. Everything
+    /// else is checked on the parsed message by [`NhrpMessage::validate`].
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if self.version() != NHRP_VERSION {
+            violations.push(Violation::Version(self.version()));
+        }
+        violations
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{Parseable, Result};
+use crate::buffer::{ChecksumPolicy, NhrpBuffer, FIXED_HEADER_LEN};
+use crate::header::{AddressFamily, FixedHeader, NhrpOp, ProtocolType};
+use crate::message::NhrpMessage;
+use crate::operation::{CieIterator, ErrorIndicationBuffer, OperationBuffer};
+use crate::extensions::ExtensionIterator;
+
+/// Borrowed view of an NHRP packet.
+///
+/// All accessors read straight from the underlying buffer; nothing is copied or allocated until
+/// the message is fully parsed with [`Parseable::parse`]. Every length and offset the view
+/// relies on is checked when it is constructed, so none of the accessors can panic.
+#[derive(Clone, Copy)]
+pub struct NhrpMessageView<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> NhrpMessageView<'a> {
+    pub fn new(buffer: &'a [u8]) -> Result<Self> {
+        let nhrp = NhrpBuffer::new_checked(buffer)?;
+        let length = nhrp.length() as usize;
+
+        let view = NhrpMessageView { buffer: &buffer[..length] };
+        let checked = match view.optype() {
+            NhrpOp::ErrorIndication | NhrpOp::TrafficIndication =>
+                ErrorIndicationBuffer::new_checked(view.payload()).map(drop),
+            NhrpOp::Other(_) => Ok(()),
+            _ => OperationBuffer::new_checked(view.payload()).map(drop),
+        };
+        checked.map_err(|e| e.within(FIXED_HEADER_LEN, ""))?;
+        Ok(view)
+    }
+
+    fn nhrp(&self) -> NhrpBuffer<&'a [u8]> {
+        NhrpBuffer::new(self.buffer)
+    }
+
+    /// The raw packet, cut to the length given in its header
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    pub fn header(&self) -> FixedHeader {
+        FixedHeader::new(self.afn(), self.protocol_type(), self.hopcount(), self.optype())
+    }
+
+    pub fn afn(&self) -> AddressFamily {
+        self.nhrp().afn().into()
+    }
+
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.nhrp().protocol_type()
+    }
+
+    pub fn hopcount(&self) -> u8 {
+        self.nhrp().hopcount()
+    }
+
+    pub fn optype(&self) -> NhrpOp {
+        self.nhrp().optype()
+    }
+
+    pub fn version(&self) -> u8 {
+        self.nhrp().version()
+    }
+
+    /// The mandatory part, i.e. everything between the fixed header and the extensions
+    pub fn payload(&self) -> &'a [u8] {
+        self.nhrp().payload()
+    }
+
+    /// The common header of the operation. Error and Traffic Indications have their own header
+    /// layout, see [`NhrpMessageView::error_header`], and unknown operations have neither.
+    pub fn common_header(&self) -> Option<OperationBuffer<&'a [u8]>> {
+        match self.optype() {
+            NhrpOp::ErrorIndication | NhrpOp::TrafficIndication | NhrpOp::Other(_) => None,
+            _ => Some(OperationBuffer::new(self.payload())),
+        }
+    }
+
+    /// The header of an Error or Traffic Indication
+    pub fn error_header(&self) -> Option<ErrorIndicationBuffer<&'a [u8]>> {
+        match self.optype() {
+            NhrpOp::ErrorIndication | NhrpOp::TrafficIndication => Some(ErrorIndicationBuffer::new(self.payload())),
+            _ => None,
+        }
+    }
+
+    pub fn request_id(&self) -> Option<u32> {
+        self.common_header().map(|header| header.request_id())
+    }
+
+    pub fn flags(&self) -> Option<u16> {
+        self.common_header().map(|header| header.flags())
+    }
+
+    /// The CIEs of the operation. Yields nothing for operations without CIEs.
+    ///
+    /// Errors are located relative to the first CIE.
+    pub fn cies(&self) -> CieIterator<&'a [u8]> {
+        match self.common_header() {
+            Some(header) => CieIterator::new(header.payload()),
+            None => CieIterator::new(&[]),
+        }
+    }
+
+    pub fn extensions(&self) -> ExtensionIterator<&'a [u8]> {
+        ExtensionIterator::new(self.nhrp().extensions())
+    }
+
+    pub fn verify_checksum(&self, policy: ChecksumPolicy) -> Result<()> {
+        self.nhrp().verify_checksum(policy)
+    }
+}
+
+impl<'a> core::fmt::Debug for NhrpMessageView<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NhrpMessageView")
+            .field("optype", &self.optype())
+            .field("length", &self.buffer.len())
+            .field("request_id", &self.request_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Parseable<NhrpMessage> for NhrpMessageView<'a> {
+    fn parse(&self) -> Result<NhrpMessage> {
+        self.nhrp().parse()
+    }
+}
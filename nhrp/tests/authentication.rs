@@ -0,0 +1,88 @@
+//! Signing and verifying messages with the Authentication extension
+
+use nhrp::{AuthError, Authenticator, Emitable, Extension, NhrpMessage, AUTHENTICATION, SPI_CLEARTEXT};
+
+mod common;
+use common::{hex, PURGE_REQUEST};
+
+fn signed(auth: &Authenticator) -> Vec<u8> {
+    let mut msg = NhrpMessage::from_bytes(&hex(PURGE_REQUEST)).unwrap();
+    msg.authenticate(auth);
+    let mut packet = vec![0; msg.buffer_len()];
+    msg.emit(&mut packet);
+    packet
+}
+
+fn keyed(spi: u16, key: &[u8]) -> Authenticator {
+    Authenticator::KeyedHash { spi, key: key.to_vec() }
+}
+
+#[test]
+fn verifies_cleartext_password() {
+    let auth = Authenticator::Cleartext(b"secret".to_vec());
+    let packet = signed(&auth);
+    auth.verify(&packet).unwrap();
+
+    let msg = NhrpMessage::from_bytes(&packet).unwrap();
+    assert!(msg.extensions.contains(&Extension::Authentication {
+        compulsory: true,
+        spi: SPI_CLEARTEXT,
+        data: b"secret".to_vec(),
+    }));
+
+    let wrong = Authenticator::Cleartext(b"secreT".to_vec());
+    assert!(matches!(wrong.verify(&packet), Err(AuthError::Mismatch)));
+    // A prefix of the password must not match either
+    let short = Authenticator::Cleartext(b"secre".to_vec());
+    assert!(matches!(short.verify(&packet), Err(AuthError::Mismatch)));
+}
+
+#[test]
+fn verifies_keyed_hash() {
+    let auth = keyed(0x100, b"key");
+    let packet = signed(&auth);
+    auth.verify(&packet).unwrap();
+
+    assert!(matches!(keyed(0x100, b"other key").verify(&packet), Err(AuthError::Mismatch)));
+
+    // The digest covers the operation, but not the checksum which hops may have to update
+    let mut tampered = packet.clone();
+    tampered[27] ^= 0x01;
+    assert!(matches!(auth.verify(&tampered), Err(AuthError::Mismatch)));
+    let mut rechecked = packet.clone();
+    rechecked[12..14].copy_from_slice(&[0, 0]);
+    auth.verify(&rechecked).unwrap();
+}
+
+#[test]
+fn rejects_wrong_spi() {
+    let packet = signed(&keyed(0x100, b"key"));
+    assert!(matches!(keyed(0x101, b"key").verify(&packet),
+        Err(AuthError::SpiMismatch { expected: 0x101, found: 0x100 })));
+    assert!(matches!(Authenticator::Cleartext(b"key".to_vec()).verify(&packet),
+        Err(AuthError::SpiMismatch { expected: SPI_CLEARTEXT, found: 0x100 })));
+}
+
+#[test]
+fn rejects_unauthenticated_messages() {
+    let auth = Authenticator::Cleartext(b"secret".to_vec());
+    assert!(matches!(auth.verify(&hex(PURGE_REQUEST)), Err(AuthError::Missing)));
+    assert!(matches!(auth.verify(&hex(PURGE_REQUEST)[..10]), Err(AuthError::Parse(_))));
+}
+
+#[test]
+fn replaces_existing_authentication() {
+    let mut msg = NhrpMessage::from_bytes(&hex(PURGE_REQUEST)).unwrap();
+    msg.authenticate(&Authenticator::Cleartext(b"old".to_vec()));
+    let auth = keyed(0x100, b"key");
+    msg.authenticate(&auth);
+
+    let etypes: Vec<_> = msg.extensions.iter().map(|e| e.etype()).collect();
+    assert_eq!(etypes.iter().filter(|&&etype| etype == AUTHENTICATION).count(), 1);
+    // End of Extensions stays last
+    assert_eq!(msg.extensions.last(), Some(&Extension::EndOfExtensions));
+
+    let mut packet = vec![0; msg.buffer_len()];
+    msg.emit(&mut packet);
+    auth.verify(&packet).unwrap();
+}
@@ -0,0 +1,208 @@
+//! Building the messages captured in `NHRP.pcapng` from scratch
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use nhrp::{BuildError, CieCode, ClientInformationEntry, CommonHeader, Emitable, Error, ErrorCode,
+    ErrorIndicationMessage, Extension, NbmaAddrType, NbmaAddress, NhrpMessage, Operation, PurgeMessage,
+    RegistrationCode, RegistrationFlags, RegistrationReplyMessage, RegistrationRequestMessage, ResolutionCode,
+    ResolutionReplyMessage, TrafficCode, TrafficIndicationMessage, MAX_NBMA_LEN};
+
+mod common;
+use common::{hex, PURGE_REQUEST, REGISTRATION_REPLY, REGISTRATION_REQUEST};
+
+fn emit(msg: &NhrpMessage) -> Vec<u8> {
+    let mut buf = vec![0; msg.buffer_len()];
+    msg.emit(&mut buf);
+    buf
+}
+
+const SPOKE_NBMA: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 5);
+const HUB_NBMA: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 4);
+const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+const HUB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+#[test]
+fn builds_captured_registration_request() {
+    let cie = ClientInformationEntry::builder()
+        .prefix_len(0xFF)
+        .holding_time(7200)
+        .build().unwrap();
+    let nat = ClientInformationEntry::builder()
+        .client_nbma_addr(HUB_NBMA)
+        .client_proto_addr(HUB)
+        .build().unwrap();
+    let request = RegistrationRequestMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .request_id(1)
+        .flags(RegistrationFlags { unique: true, nat: true, ..Default::default() })
+        .cie(cie)
+        .build().unwrap();
+    let msg = NhrpMessage::builder(request)
+        .extension(Extension::ForwardTransitNhsRecord { compulsory: true, cies: Vec::new() })
+        .extension(Extension::ReverseTransitNhsRecord { compulsory: true, cies: Vec::new() })
+        .extension(Extension::ResponderAddress { compulsory: true, cie: None })
+        .extension(Extension::NatAddress { compulsory: false, cies: vec![nat] })
+        .build().unwrap();
+
+    assert_eq!(emit(&msg), hex(REGISTRATION_REQUEST));
+}
+
+#[test]
+fn builds_captured_registration_reply() {
+    let cie = ClientInformationEntry::builder()
+        .prefix_len(0xFF)
+        .holding_time(7200)
+        .build().unwrap();
+    let responder = ClientInformationEntry::builder()
+        .prefix_len(0)
+        .holding_time(7200)
+        .client_nbma_addr(HUB_NBMA)
+        .client_proto_addr(HUB)
+        .build().unwrap();
+    let nat = ClientInformationEntry::builder()
+        .client_nbma_addr(HUB_NBMA)
+        .client_proto_addr(HUB)
+        .build().unwrap();
+    let reply = RegistrationReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .request_id(1)
+        .flags(RegistrationFlags { unique: true, nat: true, ..Default::default() })
+        .cie(cie)
+        .build().unwrap();
+    let msg = NhrpMessage::builder(reply)
+        .extension(Extension::ForwardTransitNhsRecord { compulsory: true, cies: Vec::new() })
+        .extension(Extension::ReverseTransitNhsRecord { compulsory: true, cies: Vec::new() })
+        .extension(Extension::ResponderAddress { compulsory: true, cie: Some(responder) })
+        .extension(Extension::NatAddress { compulsory: false, cies: vec![nat] })
+        .build().unwrap();
+
+    assert_eq!(emit(&msg), hex(REGISTRATION_REPLY));
+}
+
+#[test]
+fn codes_registration_reply_cies_individually() {
+    let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
+    let registered = ClientInformationEntry::builder().client_proto_addr(SPOKE).build().unwrap();
+    let refused = ClientInformationEntry::builder()
+        .code(RegistrationCode::AlreadyRegistered)
+        .client_proto_addr(other)
+        .build().unwrap();
+    let reply = RegistrationReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .cie(registered.clone())
+        .cie(refused.clone())
+        .build().unwrap();
+    assert_eq!(reply.cie(), &vec![registered.clone(), refused.clone()]);
+    assert_eq!(reply.code(), RegistrationCode::AlreadyRegistered);
+
+    let packet = emit(&NhrpMessage::builder(reply.clone()).build().unwrap());
+    match NhrpMessage::from_bytes(&packet).unwrap().operation {
+        Operation::RegistrationReply(parsed) => assert_eq!(parsed, reply),
+        other => panic!("parsed as {:?}", other),
+    }
+
+    let reply = RegistrationReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .code(RegistrationCode::Prohibited)
+        .cie(registered)
+        .cie(refused)
+        .build().unwrap();
+    assert!(reply.cie().iter().all(|cie| cie.code == CieCode::from(RegistrationCode::Prohibited)));
+
+    let reply = RegistrationReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB).build().unwrap();
+    assert_eq!((reply.cie().len(), reply.code()), (1, RegistrationCode::Success));
+}
+
+#[test]
+fn builds_captured_purge_request() {
+    let purge = PurgeMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .request_id(2)
+        .build().unwrap();
+    let msg = NhrpMessage::builder(Operation::PurgeRequest(purge))
+        .extension(Extension::ReverseTransitNhsRecord { compulsory: true, cies: Vec::new() })
+        .extension(Extension::ResponderAddress { compulsory: true, cie: None })
+        .extension(Extension::EndOfExtensions)
+        .build().unwrap();
+
+    assert_eq!(emit(&msg), hex(PURGE_REQUEST));
+}
+
+#[test]
+fn derives_fixed_header_from_addresses() {
+    let src = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+    let dst = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+    let reply = ResolutionReplyMessage::builder(SPOKE_NBMA, src, dst)
+        .code(ResolutionCode::NoBindingExists)
+        .build().unwrap();
+    let msg = NhrpMessage::builder(reply).build().unwrap();
+    let packet = emit(&msg);
+
+    assert_eq!(&packet[0..4], &[0x00, 0x01, 0x86, 0xdd]);
+    assert_eq!(msg.header.hopcount(), nhrp::DEFAULT_HOPCOUNT);
+    let parsed = NhrpMessage::from_bytes(&packet).unwrap();
+    assert_eq!(parsed, msg);
+}
+
+#[test]
+fn rejects_invalid_combinations() {
+    let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    match RegistrationRequestMessage::builder(SPOKE_NBMA, SPOKE, v6).build() {
+        Err(Error::Build(BuildError::AddressFamilyMismatch { field })) => assert_eq!(field, "dst_proto_addr"),
+        other => panic!("mixed address families were accepted: {:?}", other),
+    }
+
+    let cie = ClientInformationEntry::builder().client_proto_addr(v6).build().unwrap();
+    match RegistrationRequestMessage::builder(SPOKE_NBMA, SPOKE, HUB).cie(cie).build() {
+        Err(Error::Build(BuildError::AddressFamilyMismatch { field })) => assert_eq!(field, "CIE[0].client_proto_addr"),
+        other => panic!("CIE of another address family was accepted: {:?}", other),
+    }
+
+    let cie = ClientInformationEntry::builder().client_proto_addr(SPOKE).prefix_len(33).build();
+    assert!(matches!(cie, Err(Error::Build(BuildError::PrefixTooLong { prefix_len: 33, width: 32 }))));
+
+    let reply = ResolutionReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB).build();
+    assert!(matches!(reply, Err(Error::Build(BuildError::MissingField("cie")))));
+
+    let request = RegistrationRequestMessage::builder(SPOKE_NBMA, SPOKE, HUB).build().unwrap();
+    let msg = NhrpMessage::builder(request)
+        .extension(Extension::EndOfExtensions)
+        .extension(Extension::ResponderAddress { compulsory: true, cie: None })
+        .build();
+    assert!(matches!(msg, Err(Error::Build(BuildError::MisplacedEndOfExtensions))));
+}
+
+#[test]
+fn checks_operations_built_without_builder() {
+    let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    let indication = ErrorIndicationMessage::new(ErrorCode::ProtocolError, 0, HUB_NBMA.into(), HUB, v6, Vec::new());
+    match NhrpMessage::builder(Operation::ErrorIndication(indication)).build() {
+        Err(Error::Build(BuildError::AddressFamilyMismatch { field })) => assert_eq!(field, "dst_proto_addr"),
+        other => panic!("mixed address families were accepted: {:?}", other),
+    }
+
+    let header = CommonHeader { request_id: 1, src_nbma_addr: SPOKE_NBMA.into(), src_proto_addr: SPOKE, dst_proto_addr: HUB };
+    let cie = ClientInformationEntry::builder().client_proto_addr(v6).build().unwrap();
+    let request = RegistrationRequestMessage::new(header, RegistrationFlags::default(), vec![cie]);
+    match NhrpMessage::builder(request).build() {
+        Err(Error::Build(BuildError::AddressFamilyMismatch { field })) => assert_eq!(field, "CIE[0].client_proto_addr"),
+        other => panic!("CIE of another address family was accepted: {:?}", other),
+    }
+}
+
+#[test]
+fn emits_longest_addresses_in_every_header() {
+    // The longest NBMA address there is, next to the longest protocol addresses
+    let nbma = NbmaAddress::new(NbmaAddrType::E164, vec![b'4'; MAX_NBMA_LEN]).unwrap()
+        .with_subaddress(NbmaAddrType::NSAP, vec![0xab; MAX_NBMA_LEN]).unwrap();
+    let src = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+    let dst = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+
+    let operations: [Operation; 3] = [
+        PurgeMessage::builder(nbma.clone(), src, dst).build().map(Operation::PurgeRequest).unwrap(),
+        ErrorIndicationMessage::builder(ErrorCode::ProtocolError, nbma.clone(), src, dst).build().unwrap().into(),
+        TrafficIndicationMessage::builder(TrafficCode::Redirect, nbma.clone(), src, dst).build().unwrap().into(),
+    ];
+    for operation in operations {
+        let msg = NhrpMessage::builder(operation).build().unwrap();
+        let packet = emit(&msg);
+        // Type & length fields of the source NBMA address and subaddress
+        assert_eq!(&packet[18..20], &[0x40 | MAX_NBMA_LEN as u8, MAX_NBMA_LEN as u8]);
+        assert_eq!(NhrpMessage::from_bytes(&packet).unwrap(), msg);
+    }
+}
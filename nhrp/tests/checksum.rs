@@ -0,0 +1,82 @@
+//! Checksum test vectors taken from the NHRP packets in `NHRP.pcapng`
+
+use nhrp::{ChecksumPolicy, Emitable, Error, NhrpBuffer, NhrpMessage};
+
+mod common;
+use common::{hex, PURGE_REPLY, PURGE_REQUEST, REGISTRATION_REPLY, REGISTRATION_REQUEST};
+
+const VECTORS: [(&str, u16); 4] = [
+    (REGISTRATION_REQUEST, 0xd674),
+    (REGISTRATION_REPLY, 0x7df2),
+    (PURGE_REQUEST, 0x3042),
+    (PURGE_REPLY, 0xd7bf),
+];
+
+#[test]
+fn calculates_captured_checksums() {
+    for (packet, checksum) in VECTORS {
+        let packet = hex(packet);
+        let buffer = NhrpBuffer::new_checked(&packet[..]).unwrap();
+        assert_eq!(buffer.checksum(), checksum);
+        assert_eq!(buffer.calculate_checksum(), checksum);
+        buffer.verify_checksum(ChecksumPolicy::Verify).unwrap();
+    }
+}
+
+#[test]
+fn emits_valid_checksums() {
+    for (packet, _) in VECTORS {
+        let packet = hex(packet);
+        let msg = NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify).unwrap();
+        let mut emitted = vec![0; msg.buffer_len()];
+        msg.emit(&mut emitted);
+        assert_eq!(emitted, packet);
+    }
+}
+
+#[test]
+fn rejects_corrupted_packets() {
+    for (packet, checksum) in VECTORS {
+        let mut packet = hex(packet);
+        let last = packet.len() - 1;
+        packet[last] ^= 0x01;
+        match NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify) {
+            Err(Error::Checksum { found, .. }) => assert_eq!(found, checksum),
+            other => panic!("corrupted packet was not rejected: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn zero_checksum_requires_opt_out() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[12..14].copy_from_slice(&[0, 0]);
+
+    assert!(matches!(NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify),
+        Err(Error::Checksum { found: 0, expected: 0x3042 })));
+    NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::AllowZero).unwrap();
+}
+
+#[test]
+fn pads_odd_length_with_low_order_zero() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet.push(0xab);
+    let len = packet.len() as u16;
+    packet[10..12].copy_from_slice(&len.to_be_bytes());
+
+    // Words of the original packet with the updated length, plus 0xab00 for the trailing octet
+    let mut sum = 0u32;
+    for word in packet.chunks(2).enumerate().filter(|(i, _)| *i != 6) {
+        sum += match *word.1 {
+            [high, low] => u16::from_be_bytes([high, low]) as u32,
+            [high] => (high as u32) << 8,
+            _ => unreachable!(),
+        };
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    let buffer = NhrpBuffer::new_checked(&packet[..]).unwrap();
+    assert_eq!(buffer.calculate_checksum(), !sum as u16);
+}
@@ -0,0 +1,20 @@
+//! Helpers and packets shared by the integration tests
+#![allow(dead_code)]
+
+// Captured in `NHRP.pcapng`
+pub const REGISTRATION_REQUEST: &str = "00010800000000000010005cd6740034010304000404800200000001c63364050a0000020a00000100ff000000001c200000000080040000800500008003000000090014002000000000000004000400c63364040a00000180000000";
+pub const REGISTRATION_REPLY: &str = "0001080000000000001000707df20034010404000404800200000001c63364050a0000020a00000100ff000000001c20000000008004000080050000800300140000000000001c2004000400c63364040a00000100090014002000000000000004000400c63364040a00000180000000";
+pub const PURGE_REQUEST: &str = "00010800000000000010003430420028010504000404000000000002c63364050a0000020a000001800500008003000080000000";
+pub const PURGE_REPLY: &str = "000108000000000000100048d7bf0028010604000404000000000002c63364050a0000020a00000180050000800300140000000000001c2004000400c63364040a00000180000000";
+
+// Laid out by hand, see `indications.rs`
+pub const ERROR_INDICATION: &str = "00010800000000000010005cb0230000010704000404000000010028c63364040a0000010a00000200010800000000000010003430420028010504000404000000000002c63364050a0000020a000001800500008003000080000000";
+pub const TRAFFIC_INDICATION: &str = "000108000000000000100044d8400000010804000404000000000000c63364040a0000010a0000024500001c000040003f0100000a0000020a0000030800f7ff00000000";
+
+/// Octets of a string of hex digits
+pub fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
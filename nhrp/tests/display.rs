@@ -0,0 +1,34 @@
+//! Human readable formatting of messages
+
+use nhrp::{NhrpMessage, ResolutionFlags};
+
+mod common;
+use common::{hex, REGISTRATION_REQUEST};
+
+#[test]
+fn formats_flags() {
+    let flags = ResolutionFlags::from(0x8800);
+    assert_eq!(flags.to_string(), "0x8800 (Q=1, A=0, D=0, U=0, S=1, NAT=0)");
+}
+
+#[test]
+fn formats_message_tree() {
+    let msg = NhrpMessage::from_bytes(&hex(REGISTRATION_REQUEST)).unwrap();
+    let tree = msg.to_string();
+    let lines: Vec<&str> = tree.lines().collect();
+    assert_eq!(lines[0], "Next Hop Resolution Protocol (Registration Request)");
+    assert_eq!(lines[1], "    Address Family: IPv4 (1)");
+    assert!(lines.contains(&"        Flags: 0x8002 (U=1, NAT=1)"), "{}", tree);
+    assert!(lines.contains(&"            Holding Time: 7200s"), "{}", tree);
+    assert!(lines.contains(&"    Extension: NAT Address"), "{}", tree);
+}
+
+#[test]
+fn formats_message_summary() {
+    let msg = NhrpMessage::from_bytes(&hex(REGISTRATION_REQUEST)).unwrap();
+    assert_eq!(format!("{:#}", msg),
+        "Registration Request ID 1, 10.0.0.2 (198.51.100.5) > 10.0.0.1, flags 0x8002 (U=1, NAT=1), \
+         CIEs [Success, holding time 7200s], hop count 16, extensions Forward Transit NHS Record \
+         (compulsory); Reverse Transit NHS Record (compulsory); Responder Address (compulsory); \
+         NAT Address [Success 10.0.0.1/32 at 198.51.100.4, holding time 0s]");
+}
@@ -0,0 +1,51 @@
+//! Flags of the common header keep every bit, named or not
+
+use nhrp::{PurgeFlags, RegistrationFlags, ResolutionFlags};
+
+#[test]
+fn resolution_flags_round_trip() {
+    for bits in 0..=u16::MAX {
+        assert_eq!(u16::from(ResolutionFlags::from(bits)), bits);
+    }
+
+    let flags = ResolutionFlags::from(0x8002);
+    assert_eq!(flags, ResolutionFlags { requester_router: true, nat: true, ..Default::default() });
+    let flags = ResolutionFlags::from(0x7801);
+    assert_eq!(flags, ResolutionFlags {
+        authoritative: true,
+        destination_stable: true,
+        unique: true,
+        source_stable: true,
+        other: 0x0001,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn registration_flags_round_trip() {
+    for bits in 0..=u16::MAX {
+        assert_eq!(u16::from(RegistrationFlags::from(bits)), bits);
+    }
+
+    assert_eq!(RegistrationFlags::from(0x8002), RegistrationFlags { unique: true, nat: true, other: 0 });
+    assert_eq!(RegistrationFlags::from(0x4001), RegistrationFlags { unique: false, nat: false, other: 0x4001 });
+}
+
+#[test]
+fn purge_flags_round_trip() {
+    for bits in 0..=u16::MAX {
+        assert_eq!(u16::from(PurgeFlags::from(bits)), bits);
+    }
+
+    assert_eq!(PurgeFlags::from(0x8000), PurgeFlags { no_reply: true, other: 0 });
+    assert_eq!(PurgeFlags::from(0x0002), PurgeFlags { no_reply: false, other: 0x0002 });
+}
+
+#[test]
+fn named_flags_override_other_bits() {
+    // Named flags cleared in the struct must not sneak back in through `other`
+    let flags = RegistrationFlags { unique: false, nat: false, other: 0xffff };
+    assert_eq!(u16::from(flags), 0x7ffd);
+    let flags = PurgeFlags { no_reply: false, other: 0xffff };
+    assert_eq!(u16::from(flags), 0x7fff);
+}
@@ -0,0 +1,84 @@
+//! Error and Traffic Indication wire layout (RFC 2332, 5.2.7)
+//!
+//! `NHRP.pcapng` holds no indications, so these packets are laid out by hand from the RFC and
+//! FRR nhrpd's `nhrp_packet_header`: two unused octets, the code, the error offset, then the
+//! addresses. The Error Indication carries the captured Purge Request as packet in error.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use nhrp::{ChecksumPolicy, Emitable, ErrorCode, ErrorIndicationMessage, NhrpMessage, Operation, TrafficCode,
+    TrafficIndicationMessage};
+
+mod common;
+use common::{hex, ERROR_INDICATION, PURGE_REQUEST, TRAFFIC_INDICATION};
+
+/// ICMP echo request from 10.0.0.2 to 10.0.0.3 that the hub forwarded back out the tunnel
+const REDIRECTED: &str = "4500001c000040003f0100000a0000020a0000030800f7ff00000000";
+
+fn emit(msg: &NhrpMessage) -> Vec<u8> {
+    let mut buf = vec![0; msg.buffer_len()];
+    msg.emit(&mut buf);
+    buf
+}
+
+const HUB_NBMA: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 4);
+const HUB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+#[test]
+fn parses_error_indication() {
+    let packet = hex(ERROR_INDICATION);
+    // Code and offset follow two unused octets after the address lengths
+    assert_eq!(&packet[18 + 4..18 + 10], &[0x00, 0x00, 0x00, 0x01, 0x00, 0x28]);
+
+    let msg = NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify).unwrap();
+    assert_eq!(emit(&msg), packet);
+
+    let indication = match msg.operation {
+        Operation::ErrorIndication(indication) => indication,
+        other => panic!("not an Error Indication: {:?}", other),
+    };
+    let header = indication.header();
+    assert_eq!(header.code, ErrorCode::UnrecognizedExtension);
+    assert_eq!(header.offset, 0x28);
+    assert_eq!(header.src_nbma_addr, IpAddr::V4(HUB_NBMA));
+    assert_eq!(header.src_proto_addr, HUB);
+    assert_eq!(header.dst_proto_addr, SPOKE);
+    assert_eq!(indication.packet(), &hex(PURGE_REQUEST)[..]);
+}
+
+#[test]
+fn builds_error_indication() {
+    let indication = ErrorIndicationMessage::new(ErrorCode::UnrecognizedExtension, 0x28,
+        HUB_NBMA.into(), HUB, SPOKE, hex(PURGE_REQUEST));
+    let msg = NhrpMessage::builder(Operation::ErrorIndication(indication)).build().unwrap();
+
+    assert_eq!(emit(&msg), hex(ERROR_INDICATION));
+}
+
+#[test]
+fn parses_traffic_indication() {
+    let packet = hex(TRAFFIC_INDICATION);
+    let msg = NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify).unwrap();
+    assert_eq!(emit(&msg), packet);
+
+    let indication = match msg.operation {
+        Operation::TrafficIndication(indication) => indication,
+        other => panic!("not a Traffic Indication: {:?}", other),
+    };
+    let header = indication.header();
+    assert_eq!(header.code, TrafficCode::Redirect);
+    assert_eq!(header.src_nbma_addr, IpAddr::V4(HUB_NBMA));
+    assert_eq!(header.src_proto_addr, HUB);
+    assert_eq!(header.dst_proto_addr, SPOKE);
+    assert_eq!(indication.packet(), &hex(REDIRECTED)[..]);
+}
+
+#[test]
+fn builds_traffic_indication() {
+    let indication = TrafficIndicationMessage::new(TrafficCode::Redirect,
+        HUB_NBMA.into(), HUB, SPOKE, hex(REDIRECTED));
+    let msg = NhrpMessage::builder(Operation::TrafficIndication(indication)).build().unwrap();
+
+    assert_eq!(emit(&msg), hex(TRAFFIC_INDICATION));
+}
@@ -0,0 +1,195 @@
+//! Malformed packets must be rejected with an error instead of panicking
+
+use std::net::IpAddr;
+
+use nhrp::{AddrTL, AddressFamily, ClientInformationEntry, Emitable, Error, Extension, ExtensionType, Malformed,
+    NhrpMessage, NhrpMessageView, Operation, ProtocolClass, ProtocolType, RegistrationRequestMessage};
+
+mod common;
+use common::{hex, PURGE_REQUEST, REGISTRATION_REQUEST};
+
+fn malformed(packet: &[u8]) -> Malformed {
+    match NhrpMessage::from_bytes(packet) {
+        Err(Error::Malformed(m)) => m,
+        other => panic!("packet was not rejected as malformed: {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_unknown_operation() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[17] = 42;
+    assert!(matches!(NhrpMessage::from_bytes(&packet), Err(Error::UnknownOperation(42))));
+}
+
+#[test]
+fn rejects_extension_offset_outside_packet() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[14..16].copy_from_slice(&0xfff0u16.to_be_bytes());
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.span(), error.found.as_str()), ("extoffset", 14..16, "65520"));
+    assert!(NhrpMessageView::new(&packet).is_err());
+
+    packet[14..16].copy_from_slice(&4u16.to_be_bytes());
+    assert_eq!(malformed(&packet).field, "extoffset");
+}
+
+#[test]
+fn rejects_length_shorter_than_fixed_header() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[10..12].copy_from_slice(&12u16.to_be_bytes());
+    assert_eq!(malformed(&packet).field, "length");
+    assert_eq!(malformed(&packet[..15]).field, "fixed_header");
+}
+
+#[test]
+fn rejects_reserved_addr_tl_bit() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[18] |= 0x80;
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset, error.found.as_str()), ("src_nbma_addr_tl", 18, "0x84"));
+
+    assert_eq!(AddrTL::try_from(0x44).unwrap(), AddrTL::E164(4));
+    assert_eq!(AddrTL::try_from(0x3f).unwrap(), AddrTL::NSAP(63));
+}
+
+#[test]
+fn extension_types_are_range_checked() {
+    assert_eq!(ExtensionType::try_from(0x3fff).unwrap(), ExtensionType::Experimental(0x3fff));
+    assert!(matches!(ExtensionType::try_from(0x4000), Err(Error::InvalidExtensionType(0x4000))));
+}
+
+#[test]
+fn protocol_types_round_trip() {
+    for value in [0x0800, 0x86dd, 0x00cc, 0x0200, 0x0401, 0x0502] {
+        let class = ProtocolClass::from(value);
+        assert_eq!(u16::try_from(class).unwrap(), value);
+    }
+    assert_eq!(ProtocolClass::from(0x0800), ProtocolClass::Ethertype(0x0800));
+    assert_eq!(ProtocolClass::from(0x00cc), ProtocolClass::NLPID(0xcc));
+    assert!(matches!(u16::try_from(ProtocolClass::Ethertype(0x0100)),
+        Err(Error::InvalidProtocolType(0x0100))));
+}
+
+#[test]
+fn address_families_round_trip() {
+    for value in [1, 2, 3, 6, 8, 0x4000] {
+        assert_eq!(u16::from(AddressFamily::from(value)), value);
+    }
+    assert_eq!(AddressFamily::from(8), AddressFamily::E164);
+    assert_eq!(AddressFamily::from_ip(&"2001:db8::1".parse().unwrap()), AddressFamily::IPv6);
+    assert_eq!(AddressFamily::E164.address_len(), None);
+
+    let addr: IpAddr = "192.0.2.1".parse().unwrap();
+    assert_eq!(ProtocolType::from(addr), ProtocolType::IPV4);
+    assert_eq!(ProtocolType::IPV4.protype, ProtocolClass::Ethertype(0x0800));
+    assert_eq!(ProtocolType::IPV6.address_len(), Some(16));
+    let nlpid = ProtocolType { protype: ProtocolClass::NLPID(0xcc), prosnap: [0; 5] };
+    assert_eq!(nlpid.address_len(), Some(4));
+}
+
+#[test]
+fn rejects_addresses_not_matching_address_family() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    packet[0..2].copy_from_slice(&2u16.to_be_bytes());
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset), ("src_nbma_addr_tl", 18));
+    assert_eq!((error.expected.as_str(), error.found.as_str()), ("0 or 16 for address family IPv6", "4"));
+
+    // Unknown address families and those with addresses of varying length are not checked
+    packet[0..2].copy_from_slice(&8u16.to_be_bytes());
+    assert!(NhrpMessage::from_bytes(&packet).is_ok());
+}
+
+#[test]
+fn rejects_addresses_not_matching_protocol_type() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    packet[2..4].copy_from_slice(&0x86ddu16.to_be_bytes());
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset), ("src_proto_addr_len", 20));
+    assert_eq!(error.expected, "16 for protocol type 0x86dd");
+}
+
+#[test]
+fn rejects_missing_protocol_addresses() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    // Drop the source protocol address and shift the extensions up behind it
+    packet.drain(32..36);
+    packet[20] = 0;
+    let pktsz = u16::from_be_bytes([packet[10], packet[11]]) - 4;
+    packet[10..12].copy_from_slice(&pktsz.to_be_bytes());
+    let extoff = u16::from_be_bytes([packet[14], packet[15]]) - 4;
+    packet[14..16].copy_from_slice(&extoff.to_be_bytes());
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset), ("src_proto_addr_len", 20));
+    assert_eq!((error.expected.as_str(), error.found.as_str()), ("4 or 16", "0"));
+}
+
+#[test]
+fn rejects_cies_not_matching_protocol_type() {
+    let mut msg = NhrpMessage::from_bytes(&hex(REGISTRATION_REQUEST)).unwrap();
+    let Operation::RegistrationRequest(ref request) = msg.operation else { unreachable!() };
+    let (header, flags, _) = request.clone().into_parts();
+    let cie = ClientInformationEntry::builder()
+        .client_proto_addr("2001:db8::1".parse::<IpAddr>().unwrap())
+        .build()
+        .unwrap();
+    msg.operation = Operation::RegistrationRequest(RegistrationRequestMessage::new(header, flags, vec![cie]));
+
+    let mut packet = vec![0; msg.buffer_len()];
+    msg.emit(&mut packet);
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset), ("CIE[0].cli_proto_addr_len", 50));
+    assert_eq!((error.expected.as_str(), error.found.as_str()), ("0 or 4 for protocol type 0x0800", "16"));
+}
+
+#[test]
+fn rejects_extension_cies_not_matching_address_family() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    // Move the client protocol address of the NAT Address extension's CIE into its NBMA address
+    packet[76] = 8;
+    packet[78] = 0;
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.offset), ("extension[3].CIE[0].cli_nbma_addr_tl", 76));
+    assert_eq!(error.expected, "0 or 4 for address family IPv4");
+}
+
+#[test]
+fn rejects_responder_address_with_several_cies() {
+    let mut msg = NhrpMessage::from_bytes(&hex(REGISTRATION_REQUEST)).unwrap();
+    match msg.extensions[3] {
+        Extension::NatAddress { ref mut cies, .. } => cies.push(cies[0].clone()),
+        ref other => panic!("not a NAT Address extension: {:?}", other),
+    }
+    let mut packet = vec![0; msg.buffer_len()];
+    msg.emit(&mut packet);
+    // Retype the NAT Address extension following the empty Responder Address
+    packet[64..66].copy_from_slice(&0x8003u16.to_be_bytes());
+
+    let error = malformed(&packet);
+    assert_eq!((error.field.as_str(), error.span()), ("extension[3].length", 66..68));
+    assert_eq!((error.expected.as_str(), error.found.as_str()), ("at most one CIE", "2 CIEs"));
+}
+
+#[test]
+fn locates_errors_in_cies() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    // The CIE follows the 40 octets of fixed and common header and ends at the extensions
+    packet[50] = 16;
+    let error = malformed(&packet);
+    assert_eq!(error.field, "CIE[0].cli_proto_addr_len");
+    assert_eq!(error.span(), 50..51);
+    assert_eq!((error.expected.as_str(), error.found.as_str()), ("at most 0", "16"));
+    assert_eq!(error.to_string(),
+        "invalid CIE[0].cli_proto_addr_len at offset 50: expected at most 0, found 16");
+}
+
+#[test]
+fn locates_errors_in_extensions() {
+    let mut packet = hex(REGISTRATION_REQUEST);
+    // Length of the Reverse Transit NHS Record following the empty Forward Transit NHS Record
+    packet[58..60].copy_from_slice(&0x40u16.to_be_bytes());
+    let error = malformed(&packet);
+    assert_eq!(error.field, "extension[1].length");
+    assert_eq!(error.span(), 58..60);
+}
@@ -0,0 +1,76 @@
+//! NBMA addresses: their textual form and the length limit of the wire format
+
+use std::net::IpAddr;
+
+use nhrp::{BuildError, ClientInformationEntry, Emitable, Error, NbmaAddrType, NbmaAddress, PurgeMessage,
+    MAX_NBMA_LEN};
+
+fn too_long(result: Result<NbmaAddress, Error>) -> (String, usize) {
+    match result {
+        Err(Error::Build(BuildError::AddressTooLong { field, len })) => (field, len),
+        other => panic!("over-long address was accepted: {:?}", other),
+    }
+}
+
+#[test]
+fn formats_and_parses_nbma_addresses() {
+    let cases = [
+        // Messages without a source NBMA address carry an empty one
+        ("", NbmaAddress::new(NbmaAddrType::NSAP, Vec::new()).unwrap()),
+        ("198.51.100.4", NbmaAddress::from("198.51.100.4".parse::<IpAddr>().unwrap())),
+        ("2001:db8::1", NbmaAddress::from("2001:db8::1".parse::<IpAddr>().unwrap())),
+        ("+4930123456", NbmaAddress::new(NbmaAddrType::E164, b"4930123456".to_vec()).unwrap()),
+        ("e164:01ff", NbmaAddress::new(NbmaAddrType::E164, vec![0x01, 0xff]).unwrap()),
+        ("47000580ffe1", NbmaAddress::new(NbmaAddrType::NSAP, vec![0x47, 0x00, 0x05, 0x80, 0xff, 0xe1]).unwrap()),
+        ("47000580ffe1/+12", NbmaAddress::new(NbmaAddrType::NSAP, vec![0x47, 0x00, 0x05, 0x80, 0xff, 0xe1]).unwrap()
+            .with_subaddress(NbmaAddrType::E164, b"12".to_vec()).unwrap()),
+        ("198.51.100.4/0a0b", NbmaAddress::from("198.51.100.4".parse::<IpAddr>().unwrap())
+            .with_subaddress(NbmaAddrType::NSAP, vec![0x0a, 0x0b]).unwrap()),
+    ];
+    for (text, addr) in cases {
+        assert_eq!(addr.to_string(), text);
+        assert_eq!(text.parse::<NbmaAddress>().unwrap(), addr, "{} does not round trip", text);
+    }
+
+    for invalid in ["+", "+12a", "e164:1", "abc", "10.0.0.1/xyz", "10.0.0.1/+"] {
+        assert!(matches!(invalid.parse::<NbmaAddress>(), Err(Error::InvalidNbmaAddress(_))), "{:?} parsed", invalid);
+    }
+}
+
+#[test]
+fn rejects_over_long_nbma_addresses() {
+    let longest = NbmaAddress::new(NbmaAddrType::NSAP, vec![0xab; MAX_NBMA_LEN]).unwrap()
+        .with_subaddress(NbmaAddrType::E164, vec![b'1'; MAX_NBMA_LEN]).unwrap();
+    assert_eq!(longest.len(), 2 * MAX_NBMA_LEN);
+
+    let long = vec![0xab; MAX_NBMA_LEN + 1];
+    assert_eq!(too_long(NbmaAddress::new(NbmaAddrType::NSAP, long.clone())),
+        ("NBMA address".to_string(), MAX_NBMA_LEN + 1));
+    assert_eq!(too_long(NbmaAddress::from(IpAddr::from([198, 51, 100, 4])).with_subaddress(NbmaAddrType::NSAP, long)),
+        ("NBMA subaddress".to_string(), MAX_NBMA_LEN + 1));
+
+    let text = format!("+{}", "1".repeat(MAX_NBMA_LEN + 1));
+    assert_eq!(too_long(text.parse()).1, MAX_NBMA_LEN + 1);
+    let text = format!("198.51.100.4/{}", "ab".repeat(MAX_NBMA_LEN + 1));
+    assert_eq!(too_long(text.parse()).1, MAX_NBMA_LEN + 1);
+}
+
+#[test]
+fn emits_longest_nbma_addresses() {
+    let longest = NbmaAddress::new(NbmaAddrType::E164, vec![b'4'; MAX_NBMA_LEN]).unwrap()
+        .with_subaddress(NbmaAddrType::NSAP, vec![0xab; MAX_NBMA_LEN]).unwrap();
+    let spoke = IpAddr::from([10, 0, 0, 2]);
+    let cie = ClientInformationEntry::builder()
+        .client_nbma_addr(longest.clone())
+        .client_proto_addr(spoke)
+        .build().unwrap();
+    let purge = PurgeMessage::builder(longest.clone(), spoke, IpAddr::from([10, 0, 0, 1]))
+        .cie(cie)
+        .build().unwrap();
+
+    let mut buffer = vec![0; purge.buffer_len()];
+    purge.emit(&mut buffer);
+    // Type & length fields of the source NBMA address and subaddress
+    assert_eq!(&buffer[0..2], &[0x40 | MAX_NBMA_LEN as u8, MAX_NBMA_LEN as u8]);
+    assert_eq!(purge.header().src_nbma_addr, longest);
+}
@@ -0,0 +1,83 @@
+//! Messages generated by the `arbitrary` impls parse back into themselves
+
+use std::collections::HashSet;
+
+use arbitrary::{Arbitrary, Unstructured};
+use nhrp::{ChecksumPolicy, Emitable, Extension, NhrpMessage, NhrpOp, Operation};
+
+const CASES: u64 = 2000;
+/// Input for each case, more than enough for the largest messages generated
+const INPUT_LEN: usize = 4096;
+
+/// Input for case `seed`, from xorshift64 so failing cases can be reproduced
+fn input(seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+    (0..INPUT_LEN / 8)
+        .flat_map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state.to_le_bytes()
+        })
+        .collect()
+}
+
+fn messages() -> impl Iterator<Item = (u64, NhrpMessage)> {
+    (0..CASES).map(|seed| {
+        let input = input(seed);
+        let msg = NhrpMessage::arbitrary(&mut Unstructured::new(&input))
+            .unwrap_or_else(|e| panic!("case {}: {}", seed, e));
+        (seed, msg)
+    })
+}
+
+fn emit(msg: &NhrpMessage) -> Vec<u8> {
+    let mut buf = vec![0; msg.buffer_len()];
+    msg.emit(&mut buf);
+    buf
+}
+
+#[test]
+fn messages_round_trip() {
+    for (seed, msg) in messages() {
+        let packet = emit(&msg);
+        let parsed = NhrpMessage::from_bytes_checked(&packet, ChecksumPolicy::Verify)
+            .unwrap_or_else(|e| panic!("case {}: {} in {:02x?}\n{:#?}", seed, e, packet, msg));
+        assert_eq!(parsed, msg, "case {}", seed);
+    }
+}
+
+/// Extensions by their index in the declaration of [`Extension`]
+fn extension_kind(extension: &Extension) -> usize {
+    use Extension::*;
+    match extension {
+        EndOfExtensions => 0,
+        ResponderAddress { .. } => 1,
+        ForwardTransitNhsRecord { .. } => 2,
+        ReverseTransitNhsRecord { .. } => 3,
+        Authentication { .. } => 4,
+        VendorPrivate { .. } => 5,
+        NatAddress { .. } => 6,
+        Other { .. } => 7,
+    }
+}
+
+#[test]
+fn messages_cover_all_variants() {
+    let mut ops = HashSet::new();
+    let mut extensions = HashSet::new();
+    let mut cie_addrs = HashSet::new();
+    for (_, msg) in messages() {
+        ops.insert(msg.header.optype());
+        extensions.extend(msg.extensions.iter().map(extension_kind));
+        if let Operation::RegistrationReply(ref reply) = msg.operation {
+            cie_addrs.extend(reply.cie().iter().filter_map(|cie| cie.client_proto_addr).map(|addr| addr.is_ipv6()));
+        }
+    }
+
+    assert_eq!(ops.len(), 8, "{:?}", ops);
+    assert!(!ops.iter().any(|op| matches!(op, NhrpOp::Other(_))));
+    assert_eq!(extensions.len(), 8, "{:?}", extensions);
+    // Both kinds of protocol addresses in CIEs
+    assert_eq!(cie_addrs.len(), 2);
+}
@@ -0,0 +1,115 @@
+//! JSON representation of messages, which must survive the trip back to wire bytes
+
+use nhrp::{CieCode, ClientInformationEntry, Emitable, Extension, ExtensionType, NbmaAddrType, NbmaAddress,
+    NhrpMessage, Operation};
+use serde_json::json;
+
+mod common;
+use common::{hex, PURGE_REQUEST, REGISTRATION_REQUEST};
+
+fn emit(msg: &NhrpMessage) -> Vec<u8> {
+    let mut buf = vec![0; msg.buffer_len()];
+    msg.emit(&mut buf);
+    buf
+}
+
+#[test]
+fn round_trips_captured_messages() {
+    for packet in [REGISTRATION_REQUEST, PURGE_REQUEST] {
+        let msg = NhrpMessage::from_bytes(&hex(packet)).unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        let decoded: NhrpMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(emit(&decoded), hex(packet));
+    }
+}
+
+#[test]
+fn uses_readable_representation() {
+    let msg = NhrpMessage::from_bytes(&hex(REGISTRATION_REQUEST)).unwrap();
+    let value = serde_json::to_value(&msg).unwrap();
+
+    assert_eq!(value["header"]["afn"], 1);
+    assert_eq!(value["header"]["protocol_type"], json!({ "protype": { "ethertype": 0x0800 }, "prosnap": "0000000000" }));
+    assert_eq!(value["header"]["optype"], "registration_request");
+
+    let request = &value["operation"]["registration_request"];
+    assert_eq!(request["header"]["src_nbma_addr"], "198.51.100.5");
+    assert_eq!(request["header"]["dst_proto_addr"], "10.0.0.1");
+    assert_eq!(request["flags"], json!({ "unique": true, "nat": true, "other": 0 }));
+    assert_eq!(request["cie"][0]["code"], "success");
+    assert_eq!(request["cie"][0]["holding_time"], 7200);
+
+    assert_eq!(value["extensions"][0], json!({ "forward_transit_nhs_record": { "compulsory": true, "cies": [] } }));
+    assert_eq!(value["extensions"][3]["nat_address"]["cies"][0]["client_nbma_addr"], "198.51.100.4");
+}
+
+#[test]
+fn accepts_hand_written_messages() {
+    // Flags that aren't given are cleared, unassigned codes are plain numbers
+    let msg: NhrpMessage = serde_json::from_value(json!({
+        "header": {
+            "afn": 1,
+            "protocol_type": { "protype": { "ethertype": 0x0800 }, "prosnap": "0000000000" },
+            "hopcount": 16,
+            "optype": "purge_request",
+        },
+        "operation": { "purge_request": {
+            "header": {
+                "request_id": 7,
+                "src_nbma_addr": "198.51.100.5",
+                "src_proto_addr": "10.0.0.2",
+                "dst_proto_addr": "10.0.0.1",
+            },
+            "flags": { "no_reply": true },
+            "cie": [{
+                "code": 42,
+                "prefix_len": 32,
+                "mtu": 0,
+                "holding_time": 0,
+                "preference": 0,
+                "client_nbma_addr": null,
+                "client_proto_addr": "10.0.0.2",
+            }],
+        }},
+        "extensions": [
+            { "vendor_private": { "compulsory": false, "vendor_id": "00000c", "data": "cafe" } },
+            { "other": { "etype": 0x1001, "compulsory": true, "data": "" } },
+        ],
+    })).unwrap();
+
+    let purge = match msg.operation {
+        Operation::PurgeRequest(ref purge) => purge,
+        ref other => panic!("decoded as {:?}", other),
+    };
+    assert!(purge.flags().no_reply);
+    assert_eq!(purge.cie()[0].code, CieCode::Unknown(42));
+    assert_eq!(msg.extensions[1], Extension::Other { etype: ExtensionType::ATM(0x1001), compulsory: true, data: Vec::new() });
+
+    let parsed = NhrpMessage::from_bytes(&emit(&msg)).unwrap();
+    assert_eq!(parsed.operation, msg.operation);
+}
+
+#[test]
+fn round_trips_nbma_addresses() {
+    let addresses = [
+        NbmaAddress::from("2001:db8::1".parse::<std::net::IpAddr>().unwrap()),
+        NbmaAddress::new(NbmaAddrType::E164, b"4930123456".to_vec()).unwrap(),
+        NbmaAddress::new(NbmaAddrType::E164, vec![0x01, 0xff]).unwrap(),
+        NbmaAddress::new(NbmaAddrType::NSAP, vec![0x47, 0x00, 0x05, 0x80, 0xff, 0xe1]).unwrap()
+            .with_subaddress(NbmaAddrType::E164, b"12".to_vec()).unwrap(),
+    ];
+    for addr in addresses {
+        let text = addr.to_string();
+        assert_eq!(text.parse::<NbmaAddress>().unwrap(), addr, "{} does not round trip", text);
+
+        let cie = ClientInformationEntry::builder().client_nbma_addr(addr.clone()).build().unwrap();
+        let json = serde_json::to_string(&cie).unwrap();
+        assert_eq!(serde_json::from_str::<ClientInformationEntry>(&json).unwrap(), cie);
+    }
+
+    assert!("10.0.0.1/xyz".parse::<NbmaAddress>().is_err());
+    assert!("abc".parse::<NbmaAddress>().is_err());
+    // One octet more than a type & length field describes
+    assert!(serde_json::from_value::<NbmaAddress>(json!("00".repeat(64))).is_err());
+}
@@ -0,0 +1,116 @@
+//! Messages that parse but break the rules of RFC 2332
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use nhrp::{CieCode, ClientInformationEntry, CommonHeader, Emitable, Error, ErrorCode, NhrpMessage, NhrpMessageView,
+    NhrpOp, Operation, RegistrationReplyMessage, PurgeMessage, ResolutionCode, ResolutionReplyMessage, Violation, ViolationClass};
+
+mod common;
+use common::{hex, PURGE_REQUEST, REGISTRATION_REQUEST};
+
+const SPOKE_NBMA: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 5);
+const SPOKE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+const HUB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+fn emit(msg: &NhrpMessage) -> Vec<u8> {
+    let mut buf = vec![0; msg.buffer_len()];
+    msg.emit(&mut buf);
+    buf
+}
+
+#[test]
+fn captured_messages_are_valid() {
+    for packet in [hex(REGISTRATION_REQUEST), hex(PURGE_REQUEST)] {
+        assert_eq!(NhrpMessageView::new(&packet).unwrap().validate(), vec![]);
+        assert_eq!(NhrpMessage::from_bytes(&packet).unwrap().validate(), vec![]);
+    }
+}
+
+#[test]
+fn reports_unknown_version() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[16] = 2;
+    let violations = NhrpMessageView::new(&packet).unwrap().validate();
+    assert_eq!(violations, vec![Violation::Version(2)]);
+    assert_eq!((violations[0].class(), violations[0].offset(), violations[0].section()),
+        (ViolationClass::Header, 16, "5.1"));
+}
+
+#[test]
+fn reports_zero_hop_count() {
+    let mut packet = hex(PURGE_REQUEST);
+    packet[9] = 0;
+    let violations = NhrpMessage::from_bytes(&packet).unwrap().validate();
+    assert_eq!(violations, vec![Violation::ZeroHopCount]);
+    assert_eq!(violations[0].error_code(), ErrorCode::HopCountExceeded);
+}
+
+#[test]
+fn reports_resolution_reply_without_cie() {
+    let reply = ResolutionReplyMessage::builder(SPOKE_NBMA, SPOKE, HUB)
+        .code(ResolutionCode::NoBindingExists)
+        .build().unwrap();
+    let mut packet = emit(&NhrpMessage::builder(reply).build().unwrap());
+    // Cut off the CIE the builder added to carry the code
+    let len = packet.len() - 12;
+    packet.truncate(len);
+    packet[10..12].copy_from_slice(&(len as u16).to_be_bytes());
+
+    let violations = NhrpMessage::from_bytes(&packet).unwrap().validate();
+    assert_eq!(violations, vec![Violation::MissingCie { optype: NhrpOp::ResolutionReply }]);
+    assert_eq!((violations[0].class(), violations[0].section()), (ViolationClass::Operation, "5.2.2"));
+    assert_eq!(violations[0].error_code(), ErrorCode::InvalidResolutionReply);
+}
+
+#[test]
+fn reports_registration_reply_without_cie() {
+    let header = CommonHeader {
+        request_id: 1,
+        src_nbma_addr: SPOKE_NBMA.into(),
+        src_proto_addr: SPOKE,
+        dst_proto_addr: HUB,
+    };
+    let reply = RegistrationReplyMessage::new(header, Default::default(), Vec::new());
+    let packet = emit(&NhrpMessage::builder(reply).build().unwrap());
+
+    let violations = NhrpMessage::from_bytes(&packet).unwrap().validate();
+    assert_eq!(violations, vec![Violation::MissingCie { optype: NhrpOp::RegistrationReply }]);
+    assert_eq!(violations[0].section(), "5.2.4");
+    assert_eq!(violations[0].error_code(), ErrorCode::ProtocolError);
+}
+
+#[test]
+fn reports_cie_violations() {
+    let mut msg = NhrpMessage::from_bytes(&hex(PURGE_REQUEST)).unwrap();
+    let Operation::PurgeRequest(ref purge) = msg.operation else { unreachable!() };
+    let (header, flags, _) = purge.clone().into_parts();
+    let mut cie = ClientInformationEntry::builder()
+        .client_proto_addr(SPOKE)
+        .build().unwrap();
+    cie.prefix_len = 33;
+    cie.code = CieCode::Prohibited;
+    msg.operation = Operation::PurgeRequest(PurgeMessage::new(header, flags, vec![cie]));
+
+    let violations = msg.validate();
+    let [ref code, ref prefix_len] = violations[..] else { panic!("{:?}", violations) };
+    assert!(matches!(code, Violation::RequestCode { index: 0, code: CieCode::Prohibited, .. }));
+    assert_eq!((code.offset(), code.section()), (40, "5.2.5"));
+    assert!(matches!(prefix_len, Violation::PrefixTooLong { prefix_len: 33, width: 32, .. }));
+    assert_eq!((prefix_len.offset(), prefix_len.class()), (41, ViolationClass::Cie));
+    assert_eq!(prefix_len.to_string(),
+        "CIE[0] has prefix length 33, longer than the 32 bits of its protocol address");
+}
+
+#[test]
+fn structurally_invalid_messages_do_not_parse() {
+    // An extension offset inside the common header cuts off its addresses
+    let mut packet = hex(PURGE_REQUEST);
+    packet[14..16].copy_from_slice(&30u16.to_be_bytes());
+    assert!(matches!(NhrpMessage::from_bytes(&packet), Err(Error::Malformed(_))));
+
+    // Registration Replies must carry the CIE with their code
+    let mut packet = hex(REGISTRATION_REQUEST);
+    packet[17] = 4;
+    packet[14..16].copy_from_slice(&40u16.to_be_bytes());
+    assert!(matches!(NhrpMessage::from_bytes(&packet), Err(Error::Malformed(_))));
+}
@@ -0,0 +1,90 @@
+//! The borrowed view must read the same message as the full parse
+
+use nhrp::{ChecksumPolicy, ClientInformationEntry, Extension, NhrpMessage, NhrpMessageView, Operation, Parseable};
+
+mod common;
+use common::{hex, ERROR_INDICATION, PURGE_REPLY, PURGE_REQUEST, REGISTRATION_REPLY, REGISTRATION_REQUEST,
+    TRAFFIC_INDICATION};
+
+const FIXTURES: [&str; 6] = [
+    REGISTRATION_REQUEST, REGISTRATION_REPLY, PURGE_REQUEST, PURGE_REPLY, ERROR_INDICATION, TRAFFIC_INDICATION,
+];
+
+fn flags(operation: &Operation) -> Option<u16> {
+    match operation {
+        Operation::ResolutionRequest(msg) => Some(msg.flags().into()),
+        Operation::ResolutionReply(msg) => Some(msg.flags().into()),
+        Operation::RegistrationRequest(msg) => Some(msg.flags().into()),
+        Operation::RegistrationReply(msg) => Some(msg.flags().into()),
+        Operation::PurgeRequest(msg) | Operation::PurgeReply(msg) => Some(msg.flags().into()),
+        Operation::ErrorIndication(_) | Operation::TrafficIndication(_) => None,
+    }
+}
+
+#[test]
+fn view_matches_parsed_message() {
+    for packet in FIXTURES {
+        let packet = hex(packet);
+        let msg = NhrpMessage::from_bytes(&packet).unwrap();
+        let view = NhrpMessageView::new(&packet).unwrap();
+
+        assert_eq!(view.as_bytes(), &packet[..]);
+        assert_eq!(view.header(), msg.header);
+        assert_eq!(view.version(), 1);
+        view.verify_checksum(ChecksumPolicy::Verify).unwrap();
+
+        let header = msg.operation.common_header();
+        assert_eq!(view.request_id(), header.map(|h| h.request_id));
+        assert_eq!(view.flags(), flags(&msg.operation));
+        if let (Some(buffer), Some(header)) = (view.common_header(), header) {
+            assert_eq!(buffer.src_nbma_addr(), header.src_nbma_addr.address());
+            assert_eq!(buffer.src_nbma_saddr(), header.src_nbma_addr.subaddress());
+        }
+
+        let cies: Vec<ClientInformationEntry> = view.cies()
+            .map(|cie| cie.and_then(|cie| cie.parse()))
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(cies, msg.operation.cies());
+        let extensions: Vec<Extension> = view.extensions()
+            .map(|e| e.and_then(|e| e.parse()))
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(extensions, msg.extensions);
+
+        assert_eq!(view.parse().unwrap(), msg);
+    }
+}
+
+#[test]
+fn view_reads_indication_headers() {
+    for packet in [ERROR_INDICATION, TRAFFIC_INDICATION] {
+        let packet = hex(packet);
+        let msg = NhrpMessage::from_bytes(&packet).unwrap();
+        let view = NhrpMessageView::new(&packet).unwrap();
+        assert!(view.common_header().is_none());
+        assert_eq!(view.cies().count(), 0);
+
+        let buffer = view.error_header().unwrap();
+        match msg.operation {
+            Operation::ErrorIndication(ref indication) => {
+                assert_eq!(buffer.error_code(), u16::from(indication.code()));
+                assert_eq!(buffer.error_offset(), indication.header().offset);
+                assert_eq!(&view.payload()[buffer.length()..], indication.packet());
+            },
+            Operation::TrafficIndication(ref indication) => {
+                assert_eq!(buffer.error_code(), u16::from(indication.code()));
+                assert_eq!(&view.payload()[buffer.length()..], indication.packet());
+            },
+            ref other => panic!("not an indication: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn view_ignores_trailing_bytes() {
+    let mut packet = hex(PURGE_REQUEST);
+    let len = packet.len();
+    packet.extend_from_slice(&[0xff; 8]);
+    let view = NhrpMessageView::new(&packet).unwrap();
+    assert_eq!(view.as_bytes().len(), len);
+    assert_eq!(view.parse().unwrap(), NhrpMessage::from_bytes(&packet[..len]).unwrap());
+}